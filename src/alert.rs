@@ -0,0 +1,148 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use surrealdb::Surreal;
+use surrealdb::engine::any::Any;
+
+/// Which current price an alert watches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertField {
+    BuyPrice,
+    SellPrice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparator {
+    /// Strictly below the threshold
+    Below,
+    /// Strictly above the threshold
+    Above,
+}
+
+/// Body of `POST /api/alerts`: "notify when `field` of item `gw2_id` goes
+/// `comparator` `threshold`".
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct NewAlert {
+    pub gw2_id: u32,
+    pub field: AlertField,
+    pub comparator: Comparator,
+    /// Price in copper
+    pub threshold: i64,
+}
+
+impl NewAlert {
+    pub fn is_met(&self, price: i64) -> bool {
+        match self.comparator {
+            Comparator::Below => price < self.threshold,
+            Comparator::Above => price > self.threshold,
+        }
+    }
+}
+
+/// A stored alert. It fires once, the first time a price sync sees its
+/// condition met, and stays inactive afterwards.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Alert {
+    #[schema(value_type = Object)]
+    pub id: surrealdb::RecordId,
+    #[serde(flatten)]
+    pub alert: NewAlert,
+    pub active: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Stores `alert` as active and returns it.
+pub async fn create(db: &Surreal<Any>, alert: NewAlert) -> surrealdb::Result<Option<Alert>> {
+    db.query("CREATE ONLY alerts CONTENT $alert")
+        .bind((
+            "alert",
+            serde_json::json!({
+                "gw2_id": alert.gw2_id,
+                "field": alert.field,
+                "comparator": alert.comparator,
+                "threshold": alert.threshold,
+                "active": true,
+                "created_at": chrono::Utc::now(),
+            }),
+        ))
+        .await?
+        .check()?
+        .take(0)
+}
+
+/// Evaluates every active alert against the current item prices, deactivating
+/// the ones that fire and recording each in `triggered_alerts`. Returns how many fired.
+pub async fn check(db: &Surreal<Any>) -> surrealdb::Result<usize> {
+    let alerts: Vec<Alert> = db
+        .query("SELECT * FROM alerts WHERE active = true")
+        .await?
+        .take(0)?;
+    if alerts.is_empty() {
+        return Ok(0);
+    }
+
+    #[derive(Deserialize)]
+    struct CurrentPrice {
+        id: surrealdb::RecordId,
+        buy: Option<i64>,
+        sell: Option<i64>,
+    }
+    let item_id = |gw2_id: u32| surrealdb::RecordId::from(("item", gw2_id.to_string()));
+    let items: Vec<surrealdb::RecordId> = alerts.iter().map(|a| item_id(a.alert.gw2_id)).collect();
+    let prices: HashMap<String, CurrentPrice> = db
+        .query("SELECT id, buys.unit_price AS buy, sells.unit_price AS sell FROM $items")
+        .bind(("items", items))
+        .await?
+        .take::<Vec<CurrentPrice>>(0)?
+        .into_iter()
+        .map(|p| (p.id.to_string(), p))
+        .collect();
+
+    #[derive(Serialize)]
+    struct Triggered {
+        alert: surrealdb::RecordId,
+        gw2_id: u32,
+        field: AlertField,
+        comparator: Comparator,
+        threshold: i64,
+        price: i64,
+        triggered_at: chrono::DateTime<chrono::Utc>,
+    }
+    let now = chrono::Utc::now();
+    // Items without a price (unknown, or unlisted on that side) can't fire anything
+    let triggered: Vec<Triggered> = alerts
+        .into_iter()
+        .filter_map(|a| {
+            let current = prices.get(&item_id(a.alert.gw2_id).to_string())?;
+            let price = match a.alert.field {
+                AlertField::BuyPrice => current.buy,
+                AlertField::SellPrice => current.sell,
+            }?;
+            a.alert.is_met(price).then(|| Triggered {
+                alert: a.id,
+                gw2_id: a.alert.gw2_id,
+                field: a.alert.field,
+                comparator: a.alert.comparator,
+                threshold: a.alert.threshold,
+                price,
+                triggered_at: now,
+            })
+        })
+        .collect();
+    if triggered.is_empty() {
+        return Ok(0);
+    }
+
+    let count = triggered.len();
+    db.query(
+        "FOR $t IN $triggered {
+            UPDATE $t.alert SET active = false, triggered_at = $t.triggered_at RETURN NONE;
+            CREATE triggered_alerts CONTENT $t RETURN NONE;
+        }",
+    )
+    .bind(("triggered", triggered))
+    .await?
+    .check()?;
+    Ok(count)
+}
@@ -0,0 +1,132 @@
+use axum::extract::{Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+
+/// Optional shared secret for the API. When `None`, every request is let through.
+pub type ApiKey = Option<Arc<str>>;
+
+pub async fn api_key_middleware(
+    State(api_key): State<ApiKey>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(expected) = api_key else {
+        return next.run(request).await;
+    };
+    if bearer_matches(&request, &expected) {
+        next.run(request).await
+    } else {
+        unauthorized()
+    }
+}
+
+/// Guards the admin endpoints with their own key. Unlike the API key, no key
+/// means the endpoints are off rather than open.
+pub async fn admin_key_middleware(
+    State(admin_key): State<ApiKey>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(expected) = admin_key else {
+        return (StatusCode::FORBIDDEN, "Admin endpoints are disabled").into_response();
+    };
+    if bearer_matches(&request, &expected) {
+        next.run(request).await
+    } else {
+        unauthorized()
+    }
+}
+
+fn bearer_matches(request: &Request, expected: &str) -> bool {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|key| constant_time_eq(key.as_bytes(), expected.as_bytes()))
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        "Missing or invalid API key",
+    )
+        .into_response()
+}
+
+// Avoids leaking how much of the key matched through response timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    fn app(api_key: ApiKey) -> Router {
+        Router::new().route("/", get(|| async { "ok" })).layer(
+            axum::middleware::from_fn_with_state(api_key, api_key_middleware),
+        )
+    }
+
+    async fn status_for(app: Router, auth: Option<&str>) -> StatusCode {
+        let mut request = Request::get("/");
+        if let Some(auth) = auth {
+            request = request.header(header::AUTHORIZATION, auth);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_valid_key_passes() {
+        let app = app(Some("secret".into()));
+        assert_eq!(status_for(app, Some("Bearer secret")).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_missing_or_wrong_key_fails() {
+        let app = app(Some("secret".into()));
+        assert_eq!(
+            status_for(app.clone(), None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status_for(app, Some("Bearer wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_when_unset() {
+        assert_eq!(status_for(app(None), None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_admin_disabled_when_unset() {
+        let admin = |admin_key: ApiKey| {
+            Router::new().route("/", get(|| async { "ok" })).layer(
+                axum::middleware::from_fn_with_state(admin_key, admin_key_middleware),
+            )
+        };
+        assert_eq!(status_for(admin(None), None).await, StatusCode::FORBIDDEN);
+        let keyed = admin(Some("root".into()));
+        assert_eq!(
+            status_for(keyed.clone(), Some("Bearer secret")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status_for(keyed, Some("Bearer root")).await, StatusCode::OK);
+    }
+}
@@ -1,57 +1,374 @@
-use axum::{Json, Router, routing::get};
+use axum::response::IntoResponse;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::{
+    Json, Router,
+    routing::{get, post},
+};
 use clap::Parser;
-use gw2shinies_backend::{Args, DBItem, Database, ItemParams};
+use futures::{StreamExt, TryStreamExt};
+use gw2shinies_backend::alert::{self, Alert, NewAlert};
+use gw2shinies_backend::auth::{ApiKey, admin_key_middleware, api_key_middleware};
+use gw2shinies_backend::candles::{self, Candle};
+use gw2shinies_backend::history_import::{HistoryImporter, ImportReport};
+use gw2shinies_backend::items_cache::{ItemsCache, ItemsPage};
+use gw2shinies_backend::price_updates::{self, PriceUpdate};
+use gw2shinies_backend::rate_limit::{RateLimiter, rate_limit_middleware};
+use gw2shinies_backend::sync_status::{self, JobStatus};
+use gw2shinies_backend::{
+    Args, AverageKind, ChangesParams, DBItem, Database, GemOverview, GemParams,
+    HistoryBatchRequest, HistoryParams, HistoryPoint, ItemBatchRequest, ItemParams, MarketStats,
+    MovingAverageParams, MovingAveragePoint, Opportunity, OpportunityParams, OpportunityWeights,
+    VendorFlip, Volatility, Vwap, WindowParams, indicators, parse_item_fields, timed_query,
+};
 use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
 
-#[derive(Serialize)]
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        health_handler,
+        get_items_handler,
+        get_items_csv_handler,
+        get_items_batch_handler,
+        get_item_changes_handler,
+        get_item_handler,
+        get_item_history_handler,
+        get_history_batch_handler,
+        get_item_moving_average_handler,
+        get_item_volatility_handler,
+        get_item_vwap_handler,
+        get_item_candles_handler,
+        get_stats_handler,
+        get_opportunities_handler,
+        get_vendor_flips_handler,
+        get_gems_handler,
+        create_alert_handler,
+        stream_handler,
+        get_jobs_handler,
+        import_history_handler
+    ),
+    components(schemas(
+        HealthCheck,
+        DBItem,
+        gw2shinies_backend::PriceDetail,
+        gw2shinies_backend::ItemSort,
+        HistoryPoint,
+        HistoryBatchRequest,
+        MovingAveragePoint,
+        AverageKind,
+        Volatility,
+        Vwap,
+        Candle,
+        MarketStats,
+        Opportunity,
+        VendorFlip,
+        GemOverview,
+        gw2shinies_backend::exchange::GemRate,
+        gw2shinies_backend::alert::Alert,
+        gw2shinies_backend::alert::NewAlert,
+        gw2shinies_backend::alert::AlertField,
+        gw2shinies_backend::alert::Comparator,
+        PriceUpdate,
+        ImportReport,
+        JobStatus
+    ))
+)]
+struct ApiDoc;
+
+#[derive(Serialize, utoipa::ToSchema)]
 struct HealthCheck {
     status: String,
     message: String,
+    /// "ok" or "down"
+    db: String,
 }
 
-async fn health_handler() -> Json<HealthCheck> {
-    Json(HealthCheck {
-        status: "ok".to_string(),
-        message: "Skritt colony active. Yes.".to_string(),
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "Service and database are up", body = HealthCheck),
+        (status = 503, description = "Database is unreachable", body = HealthCheck)
+    )
+)]
+async fn health_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+) -> (axum::http::StatusCode, Json<HealthCheck>) {
+    // Liveness only; job outcomes are served by the admin-only /api/admin/jobs
+    let check = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        db.query("RETURN 1").await?.check().map(drop)
     })
+    .await;
+
+    match check {
+        Ok(Ok(())) => (
+            axum::http::StatusCode::OK,
+            Json(HealthCheck {
+                status: "ok".to_string(),
+                message: "Skritt colony active. Yes.".to_string(),
+                db: "ok".to_string(),
+            }),
+        ),
+        failure => {
+            match failure {
+                Ok(Err(e)) => eprintln!("Health check database query failed: {}", e),
+                _ => eprintln!("Health check database query timed out"),
+            }
+            (
+                axum::http::StatusCode::SERVICE_UNAVAILABLE,
+                Json(HealthCheck {
+                    status: "degraded".to_string(),
+                    message: "Skritt colony lost its shinies. No!".to_string(),
+                    db: "down".to_string(),
+                }),
+            )
+        }
+    }
+}
+
+/// Item projection with the derived trading metrics. Profit and ROI are stored by
+/// the price sync; the inline fallback (net of `$fee`) covers items priced before that.
+const ITEM_FIELDS: &str = "*, 
+        (IF profit != NONE THEN profit ELSE math::round((sells.unit_price OR 0) * (1 - $fee)) - (buys.unit_price OR 0) END) AS profit,
+        (IF roi != NONE THEN roi ELSE IF (buys.unit_price OR 0) > 0 THEN (math::round((sells.unit_price OR 0) * (1 - $fee)) - (buys.unit_price OR 0)) / (buys.unit_price OR 0) * 100 ELSE 0 END) AS roi,
+        ((sells.unit_price OR 0) - (buys.unit_price OR 0)) AS spread,
+        (IF last_price_update = NONE THEN true ELSE <datetime>last_price_update < time::now() - <duration>$stale_after END) AS is_stale";
+
+/// Values `ITEM_FIELDS` reads from its bindings; bind it to every query that
+/// selects them.
+#[derive(Clone, Serialize)]
+struct ItemFieldBindings {
+    /// Trading post cut for the inline profit and ROI fallback
+    fee: f64,
+    /// SurrealQL duration after which a price counts as stale
+    stale_after: String,
 }
 
+impl ItemFieldBindings {
+    fn new(price_sync_interval: std::time::Duration) -> Self {
+        let stale_after = price_sync_interval * gw2shinies_backend::STALE_AFTER_SYNCS;
+        Self {
+            fee: gw2shinies_backend::TRADING_POST_FEE,
+            stale_after: format!("{}s", stale_after.as_secs()),
+        }
+    }
+}
+
+/// Items the GW2 API no longer lists only linger for their history; every item
+/// listing filters them out with this condition.
+const LISTED_ITEMS: &str = "is_removed != true";
+
+/// Upper bound on ids accepted by the batch endpoint
+const MAX_BATCH_IDS: usize = 200;
+/// Upper bound on ids accepted by the history batch endpoint; each is a series
+const MAX_HISTORY_BATCH_IDS: usize = 50;
+/// Items whose history is queried at once for a history batch
+const HISTORY_BATCH_CONCURRENCY: usize = 8;
+/// Upper bound on buckets per series in a history batch, which bounds its range
+/// by resolution: about six weeks of hours, or nearly three years of days
+const MAX_HISTORY_BATCH_BUCKETS: i32 = 1000;
+
+/// Candle requests spanning more than this read the hourly rollup instead of raw history
+const ROLLUP_MIN_RANGE: chrono::Duration = chrono::Duration::days(7);
+
+/// Rows fetched per database round-trip while streaming the CSV export
+const CSV_BATCH_SIZE: u32 = 500;
+
+/// Builds the filtered, sorted item query shared by the JSON and CSV listings.
+/// Pagination is left to the caller.
+fn build_items_query(
+    params: ItemParams,
+    fields: &ItemFieldBindings,
+) -> (String, Vec<(&'static str, serde_json::Value)>) {
+    let mut conditions: Vec<&str> = Vec::new();
+    let mut computed_conditions: Vec<&str> = Vec::new();
+    let mut bindings: Vec<(&'static str, serde_json::Value)> = vec![
+        ("fee", fields.fee.into()),
+        ("stale_after", fields.stale_after.clone().into()),
+    ];
+
+    if let Some(search) = params.search
+        && !search.is_empty()
+    {
+        // Basic case-insensitive search
+        conditions.push("string::lowercase(name) CONTAINS string::lowercase($search)");
+        bindings.push(("search", search.into()));
+    }
+
+    if let Some(min_level) = params.min_level {
+        conditions.push("level >= $min_level");
+        bindings.push(("min_level", min_level.into()));
+    }
+    if let Some(max_level) = params.max_level {
+        conditions.push("level <= $max_level");
+        bindings.push(("max_level", max_level.into()));
+    }
+
+    // Items without a listing have NONE prices, which would otherwise sort below any max
+    let price_filters = [
+        (
+            "min_buy",
+            params.min_buy,
+            "buys.unit_price != NONE AND buys.unit_price >= $min_buy",
+        ),
+        (
+            "max_buy",
+            params.max_buy,
+            "buys.unit_price != NONE AND buys.unit_price <= $max_buy",
+        ),
+        (
+            "min_sell",
+            params.min_sell,
+            "sells.unit_price != NONE AND sells.unit_price >= $min_sell",
+        ),
+        (
+            "max_sell",
+            params.max_sell,
+            "sells.unit_price != NONE AND sells.unit_price <= $max_sell",
+        ),
+    ];
+    for (key, value, condition) in price_filters {
+        if let Some(value) = value {
+            conditions.push(condition);
+            bindings.push((key, value.into()));
+        }
+    }
+
+    if params.exclude_anomalies.unwrap_or(false) {
+        conditions.push("price_anomaly != true");
+    }
+
+    // Most views only care about items that can actually be traded
+    conditions.push("is_tradeable = $tradeable");
+    conditions.push(LISTED_ITEMS);
+    bindings.push(("tradeable", params.tradeable.unwrap_or(true).into()));
+
+    // profit/roi only exist after projection, so they're filtered in an outer SELECT
+    let computed_filters = [
+        ("min_profit", params.min_profit, "profit >= $min_profit"),
+        ("max_profit", params.max_profit, "profit <= $max_profit"),
+        ("min_roi", params.min_roi, "roi >= $min_roi"),
+        ("max_roi", params.max_roi, "roi <= $max_roi"),
+    ];
+    for (key, value, condition) in computed_filters {
+        if let Some(value) = value {
+            computed_conditions.push(condition);
+            bindings.push((key, value.into()));
+        }
+    }
+
+    let mut query_string = format!("SELECT {} FROM item", ITEM_FIELDS);
+    if !conditions.is_empty() {
+        query_string.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+    }
+    if !computed_conditions.is_empty() {
+        query_string = format!(
+            "SELECT * FROM ({}) WHERE {}",
+            query_string,
+            computed_conditions.join(" AND ")
+        );
+    }
+
+    // Sort descending on the chosen metric, profit by default
+    query_string.push_str(&format!(
+        " ORDER BY {} DESC",
+        params.sort.unwrap_or_default().column()
+    ));
+
+    (query_string, bindings)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items",
+    params(ItemParams),
+    responses(
+        (status = 200, description = "Page of items sorted by profit", body = [DBItem],
+            headers(
+                ("Link" = String, description = "RFC 5988 first/prev/next/last page links"),
+                ("X-Total-Count" = u64, description = "Number of items matching the filters")
+            )
+        ),
+        (status = 400, description = "Unknown field requested", body = String),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
 async fn get_items_handler(
     axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::State(item_fields): axum::extract::State<ItemFieldBindings>,
+    axum::extract::State(cache): axum::extract::State<ItemsCache>,
+    axum::extract::OriginalUri(uri): axum::extract::OriginalUri,
     axum::extract::Query(params): axum::extract::Query<ItemParams>,
-) -> Result<Json<Vec<DBItem>>, (axum::http::StatusCode, String)> {
-    let limit = params.limit.unwrap_or(50).min(100);
-    let page = params.page.unwrap_or(1);
+) -> Result<(axum::http::HeaderMap, Json<Vec<serde_json::Value>>), (axum::http::StatusCode, String)>
+{
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    let page = params.page.unwrap_or(1).max(1);
     let start = (page - 1) * limit;
+    let fields = params
+        .fields
+        .as_deref()
+        .map(parse_item_fields)
+        .transpose()
+        .map_err(|e| (axum::http::StatusCode::BAD_REQUEST, e))?;
 
-    let mut query_string = "SELECT *, 
-        (math::round((sells.unit_price OR 0) * 0.85) - (buys.unit_price OR 0)) AS profit,
-        (IF (buys.unit_price OR 0) > 0 THEN (math::round((sells.unit_price OR 0) * 0.85) - (buys.unit_price OR 0)) / (buys.unit_price OR 0) * 100 ELSE 0 END) AS roi
-        FROM item".to_string();
-    let mut bindings: Vec<(String, String)> = Vec::new();
-
-    if let Some(search) = params.search {
-        if !search.is_empty() {
-            // Basic case-insensitive search
-            query_string
-                .push_str(" WHERE string::lowercase(name) CONTAINS string::lowercase($search)");
-            bindings.push(("search".to_string(), search));
+    let (query_string, bindings) = build_items_query(params, &item_fields);
+    // The built query already normalizes parameter order and defaults; `fields`
+    // only shapes the response, so it stays out of the key
+    let cache_key = format!(
+        "{} LIMIT {} START {} {:?}",
+        query_string, limit, start, bindings
+    );
+    let ItemsPage { items, total } = match cache.get(&cache_key) {
+        Some(cached) => cached,
+        None => {
+            let page = fetch_items_page(&db, &query_string, bindings, limit, start).await?;
+            cache.insert(cache_key, page.clone());
+            page
         }
-    }
+    };
+    println!(
+        "Fetched {} items (Page {}, Limit {})",
+        items.len(),
+        page,
+        limit
+    );
 
-    // Default sort by profit descending if no search, otherwise maybe just relevance?
-    // For now let's just add a basic sort
-    query_string.push_str(" ORDER BY profit DESC");
+    let mut headers = axum::http::HeaderMap::new();
+    let links = pagination_links(uri.path(), uri.query(), page, limit, total);
+    if let Ok(value) = links.parse() {
+        headers.insert(axum::http::header::LINK, value);
+    }
+    headers.insert("x-total-count", total.into());
 
-    query_string.push_str(&format!(" LIMIT {} START {}", limit, start));
+    let fields = fields.unwrap_or_else(|| DBItem::FIELDS.to_vec());
+    Ok((
+        headers,
+        Json(items.iter().map(|item| item.project(&fields)).collect()),
+    ))
+}
 
-    let mut response = db.query(query_string);
+/// Runs an items listing query for one page, plus the total it matches.
+async fn fetch_items_page(
+    db: &surrealdb::Surreal<surrealdb::engine::any::Any>,
+    query_string: &str,
+    bindings: Vec<(&'static str, serde_json::Value)>,
+    limit: u32,
+    start: u32,
+) -> Result<ItemsPage, (axum::http::StatusCode, String)> {
+    let mut response = db
+        .query(format!("{} LIMIT {} START {}", query_string, limit, start))
+        .query(format!("SELECT count() FROM ({}) GROUP ALL", query_string));
 
     for (key, value) in bindings {
         response = response.bind((key, value));
     }
 
-    match response.await {
+    match timed_query("items_list", response).await {
         Ok(mut result) => {
             let items: Vec<DBItem> = result.take(0).map_err(|e| {
                 eprintln!("Failed to parse items: {}", e);
@@ -60,13 +377,21 @@ async fn get_items_handler(
                     "Failed to parse data".to_string(),
                 )
             })?;
-            println!(
-                "Fetched {} items (Page {}, Limit {})",
-                items.len(),
-                page,
-                limit
-            );
-            Ok(Json(items))
+            let total = result
+                .take::<Option<serde_json::Value>>(1)
+                .map_err(|e| {
+                    eprintln!("Failed to count items: {}", e);
+                    (
+                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to parse data".to_string(),
+                    )
+                })?
+                .and_then(|v| v.get("count")?.as_u64())
+                .unwrap_or(0);
+            Ok(ItemsPage {
+                items: std::sync::Arc::new(items),
+                total,
+            })
         }
         Err(e) => {
             eprintln!("Failed to fetch items: {}", e);
@@ -78,6 +403,1190 @@ async fn get_items_handler(
     }
 }
 
+/// Builds an RFC 5988 `Link` header value for a paginated listing, keeping all
+/// other query parameters as sent. `next`/`prev` are only present when valid.
+fn pagination_links(path: &str, query: Option<&str>, page: u32, limit: u32, total: u64) -> String {
+    let last = total.div_ceil(limit as u64).max(1) as u32;
+    let base: Vec<&str> = query
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| {
+            !pair.is_empty() && !pair.starts_with("page=") && !pair.starts_with("limit=")
+        })
+        .collect();
+    let link = |target: u32, rel: &str| {
+        let mut pairs = base.clone();
+        let page_pair = format!("page={}", target);
+        let limit_pair = format!("limit={}", limit);
+        pairs.push(&page_pair);
+        pairs.push(&limit_pair);
+        format!("<{}?{}>; rel=\"{}\"", path, pairs.join("&"), rel)
+    };
+
+    let mut links = vec![link(1, "first")];
+    if page > 1 {
+        links.push(link((page - 1).min(last), "prev"));
+    }
+    if page < last {
+        links.push(link(page + 1, "next"));
+    }
+    links.push(link(last, "last"));
+    links.join(", ")
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items.csv",
+    params(ItemParams),
+    responses(
+        (status = 200, description = "All matching items as CSV (page/limit are ignored)", content_type = "text/csv", body = String)
+    )
+)]
+async fn get_items_csv_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::State(fields): axum::extract::State<ItemFieldBindings>,
+    axum::extract::Query(params): axum::extract::Query<ItemParams>,
+) -> axum::response::Response {
+    let (query_string, bindings) = build_items_query(params, &fields);
+
+    // Page through the result set so only one batch is held in memory at a time
+    let rows = futures::stream::try_unfold(Some(0u32), move |start| {
+        let db = db.clone();
+        let query_string = query_string.clone();
+        let bindings = bindings.clone();
+        async move {
+            let Some(start) = start else {
+                return Ok(None);
+            };
+
+            let mut response = db.query(format!(
+                "{} LIMIT {} START {}",
+                query_string, CSV_BATCH_SIZE, start
+            ));
+            for (key, value) in bindings {
+                response = response.bind((key, value));
+            }
+            let items: Vec<DBItem> = response.await?.take(0)?;
+
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            if start == 0 {
+                writer
+                    .write_record(["gw2_id", "name", "rarity", "buy", "sell", "profit", "roi"])?;
+            }
+            for item in &items {
+                writer.serialize((
+                    item.gw2_id,
+                    &item.name,
+                    &item.rarity,
+                    item.buys.as_ref().map(|p| p.unit_price),
+                    item.sells.as_ref().map(|p| p.unit_price),
+                    item.profit,
+                    item.roi,
+                ))?;
+            }
+            let chunk = writer.into_inner().map_err(|e| e.into_error())?;
+
+            let next = (items.len() == CSV_BATCH_SIZE as usize).then_some(start + CSV_BATCH_SIZE);
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(Some((chunk, next)))
+        }
+    })
+    .inspect_err(|e| eprintln!("Failed to stream items CSV: {}", e));
+
+    (
+        [
+            (axum::http::header::CONTENT_TYPE, "text/csv"),
+            (
+                axum::http::header::CONTENT_DISPOSITION,
+                "attachment; filename=\"items.csv\"",
+            ),
+        ],
+        axum::body::Body::from_stream(rows),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/items/batch",
+    request_body = ItemBatchRequest,
+    responses(
+        (status = 200, description = "Items matching the requested ids", body = [DBItem]),
+        (status = 400, description = "Too many ids", body = String),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_items_batch_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::State(fields): axum::extract::State<ItemFieldBindings>,
+    Json(request): Json<ItemBatchRequest>,
+) -> Result<Json<Vec<DBItem>>, (axum::http::StatusCode, String)> {
+    if request.ids.len() > MAX_BATCH_IDS {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!("At most {} ids may be requested at once", MAX_BATCH_IDS),
+        ));
+    }
+    if request.ids.is_empty() {
+        return Ok(Json(vec![]));
+    }
+
+    let query_string = format!(
+        "SELECT {} FROM item WHERE gw2_id IN $ids AND {}",
+        ITEM_FIELDS, LISTED_ITEMS
+    );
+    let mut result = db
+        .query(query_string)
+        .bind(fields)
+        .bind(("ids", request.ids))
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch item batch: {}", e);
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            )
+        })?;
+    let items: Vec<DBItem> = result.take(0).map_err(|e| {
+        eprintln!("Failed to parse items: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to parse data".to_string(),
+        )
+    })?;
+    Ok(Json(items))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items/changes",
+    params(ChangesParams),
+    responses(
+        (status = 200, description = "Items whose buy or sell price changed after `since`, oldest change first", body = [DBItem],
+            headers(
+                ("Link" = String, description = "RFC 5988 link to the next page, present when this one is full")
+            )
+        ),
+        (status = 400, description = "Missing or malformed `since`", body = String),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_item_changes_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::State(fields): axum::extract::State<ItemFieldBindings>,
+    axum::extract::OriginalUri(uri): axum::extract::OriginalUri,
+    axum::extract::Query(params): axum::extract::Query<ChangesParams>,
+) -> Result<(axum::http::HeaderMap, Json<Vec<DBItem>>), (axum::http::StatusCode, String)> {
+    let internal_error = |e: surrealdb::Error| {
+        eprintln!("Failed to fetch item changes: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )
+    };
+    let limit = params.limit.unwrap_or(500).clamp(1, 1000);
+
+    // price_changed_at is an indexed datetime, stamped only when a price moves.
+    // Items changed by the same write share it, so pages break ties on gw2_id:
+    // a resumed page starts with the rest of the items changed at `since`. The
+    // two parts are separate selects since an OR across them can't use the index
+    let select = |condition: &str| {
+        format!(
+            "(SELECT {}, price_changed_at AS changed_at FROM item
+                WHERE {} AND {} ORDER BY changed_at ASC, gw2_id ASC LIMIT $limit)",
+            ITEM_FIELDS, LISTED_ITEMS, condition
+        )
+    };
+    let later = select("price_changed_at > $since");
+    let page = if params.after.is_some() {
+        format!(
+            "array::concat({}, {})",
+            select("price_changed_at = $since AND gw2_id > $after"),
+            later
+        )
+    } else {
+        later
+    };
+    let query_string = format!(
+        "LET $page = array::slice({}, 0, $limit);
+        RETURN $page;
+        RETURN array::last($page);",
+        page
+    );
+    let mut result = timed_query(
+        "items_changes",
+        db.query(query_string)
+            .bind(fields)
+            .bind(("since", surrealdb::Datetime::from(params.since)))
+            .bind(("after", params.after))
+            .bind(("limit", limit)),
+    )
+    .await
+    .map_err(internal_error)?;
+
+    #[derive(serde::Deserialize)]
+    struct Cursor {
+        changed_at: chrono::DateTime<chrono::Utc>,
+        gw2_id: u32,
+    }
+    let items: Vec<DBItem> = result.take(1).map_err(|e| {
+        eprintln!("Failed to parse items: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to parse data".to_string(),
+        )
+    })?;
+    let last: Option<Cursor> = result.take(2).map_err(internal_error)?;
+
+    let mut headers = axum::http::HeaderMap::new();
+    if let Some(last) = last.filter(|_| items.len() == limit as usize) {
+        let next = format!(
+            "<{}?since={}&after={}&limit={}>; rel=\"next\"",
+            uri.path(),
+            last.changed_at
+                .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+            last.gw2_id,
+            limit
+        );
+        if let Ok(value) = next.parse() {
+            headers.insert(axum::http::header::LINK, value);
+        }
+    }
+    Ok((headers, Json(items)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    responses(
+        (status = 200, description = "Market-wide summary", body = MarketStats),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_stats_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+) -> Result<Json<MarketStats>, (axum::http::StatusCode, String)> {
+    let internal_error = |e: surrealdb::Error| {
+        eprintln!("Failed to compute market stats: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )
+    };
+
+    let mut result = db
+        .query(format!(
+            "SELECT count() FROM item WHERE is_tradeable = true AND {} GROUP ALL",
+            LISTED_ITEMS
+        ))
+        .query(format!(
+            "SELECT count() FROM item WHERE {} AND buys.unit_price != NONE AND sells.unit_price != NONE GROUP ALL",
+            LISTED_ITEMS
+        ))
+        .query(format!(
+            "SELECT math::mean(spread) AS mean, math::median(spread) AS median FROM (
+                SELECT sells.unit_price - buys.unit_price AS spread FROM item
+                WHERE {} AND buys.unit_price != NONE AND sells.unit_price != NONE
+            ) GROUP ALL",
+            LISTED_ITEMS
+        ))
+        // Price sync stores last_price_update as a string
+        .query("RETURN array::max(SELECT VALUE <datetime>last_price_update FROM item WHERE last_price_update != NONE)")
+        .await
+        .map_err(internal_error)?;
+
+    let count = |value: Option<serde_json::Value>| {
+        value.and_then(|v| v.get("count")?.as_u64()).unwrap_or(0)
+    };
+    let tradeable_items = count(result.take(0).map_err(internal_error)?);
+    let priced_items = count(result.take(1).map_err(internal_error)?);
+    let spreads: Option<serde_json::Value> = result.take(2).map_err(internal_error)?;
+    let last_price_update: Option<chrono::DateTime<chrono::Utc>> =
+        result.take(3).map_err(internal_error)?;
+
+    Ok(Json(MarketStats {
+        tradeable_items,
+        priced_items,
+        mean_spread: spreads.as_ref().and_then(|v| v.get("mean")?.as_f64()),
+        median_spread: spreads.as_ref().and_then(|v| v.get("median")?.as_f64()),
+        last_price_update,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/opportunities",
+    params(OpportunityParams),
+    responses(
+        (status = 200, description = "Flippable items, best score first", body = [Opportunity]),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_opportunities_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::State(fields): axum::extract::State<ItemFieldBindings>,
+    axum::extract::Query(params): axum::extract::Query<OpportunityParams>,
+) -> Result<Json<Vec<Opportunity>>, (axum::http::StatusCode, String)> {
+    let defaults = OpportunityWeights::default();
+    let weights = OpportunityWeights {
+        profit: params.profit_weight.unwrap_or(defaults.profit),
+        roi: params.roi_weight.unwrap_or(defaults.roi),
+        liquidity: params.liquidity_weight.unwrap_or(defaults.liquidity),
+    };
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+
+    // Filtering happens in the database; the score itself is computed here
+    let query_string = format!(
+        "SELECT * FROM (
+            SELECT {} FROM item
+            WHERE is_tradeable = true AND {} AND buys != NONE AND sells != NONE
+                AND buys.quantity + sells.quantity >= $min_volume
+        ) WHERE profit >= $min_profit",
+        ITEM_FIELDS, LISTED_ITEMS
+    );
+    let mut result = db
+        .query(query_string)
+        .bind(fields)
+        .bind(("min_profit", params.min_profit.unwrap_or(1.0)))
+        .bind(("min_volume", params.min_volume.unwrap_or(100)))
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch opportunities: {}", e);
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            )
+        })?;
+    let items: Vec<DBItem> = result.take(0).map_err(|e| {
+        eprintln!("Failed to parse opportunities: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to parse data".to_string(),
+        )
+    })?;
+
+    let mut opportunities: Vec<Opportunity> = items
+        .into_iter()
+        .map(|item| {
+            let quantity = |side: &Option<gw2shinies_backend::PriceDetail>| {
+                side.as_ref().map_or(0, |side| side.quantity as i64)
+            };
+            let (buys, sells) = (quantity(&item.buys), quantity(&item.sells));
+            let volume = buys + sells;
+            // Items the price sync hasn't written since liquidity was stored get the
+            // same score computed here
+            let liquidity = item
+                .liquidity
+                .unwrap_or_else(|| gw2shinies_backend::liquidity_score(buys, sells));
+            let score = gw2shinies_backend::opportunity_score(
+                item.profit.unwrap_or(0.0),
+                item.roi.unwrap_or(0.0) as f64,
+                liquidity,
+                weights,
+            );
+            Opportunity {
+                item,
+                volume,
+                score,
+            }
+        })
+        .collect();
+    opportunities.sort_by(|a, b| b.score.total_cmp(&a.score));
+    opportunities.truncate(limit);
+    Ok(Json(opportunities))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/vendor-flips",
+    responses(
+        (status = 200, description = "Items an NPC vendor pays more for than the trading post, best margin first", body = [VendorFlip]),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_vendor_flips_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::State(fields): axum::extract::State<ItemFieldBindings>,
+) -> Result<Json<Vec<VendorFlip>>, (axum::http::StatusCode, String)> {
+    // A flip buys out the highest buy order's price and vendors the item, so it
+    // only pays when the vendor beats that price outright
+    let query_string = format!(
+        "SELECT {}, vendor_value - buys.unit_price AS vendor_margin FROM item
+        WHERE is_tradeable = true AND {} AND buys.unit_price > 0
+            AND vendor_value > buys.unit_price
+        ORDER BY vendor_margin DESC LIMIT 100",
+        ITEM_FIELDS, LISTED_ITEMS
+    );
+    let mut result = db.query(query_string).bind(fields).await.map_err(|e| {
+        eprintln!("Failed to fetch vendor flips: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )
+    })?;
+    let items: Vec<DBItem> = result.take(0).map_err(|e| {
+        eprintln!("Failed to parse vendor flips: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to parse data".to_string(),
+        )
+    })?;
+
+    Ok(Json(
+        items
+            .into_iter()
+            .map(|item| {
+                let buy = item.buys.as_ref().map_or(0, |b| b.unit_price as i64);
+                let vendor_profit = item.vendor_value.unwrap_or(0) - buy;
+                VendorFlip {
+                    item,
+                    vendor_profit,
+                }
+            })
+            .collect(),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items/{id}",
+    params(("id" = u32, Path, description = "GW2 item id")),
+    responses(
+        (status = 200, description = "The item with its trading metrics", body = DBItem),
+        (status = 404, description = "No item with this id has been synced", body = Object, example = json!({"error": {"code": "not_found"}})),
+        (status = 500, description = "Database error", body = Object)
+    )
+)]
+async fn get_item_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::State(fields): axum::extract::State<ItemFieldBindings>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+) -> Result<Json<DBItem>, (axum::http::StatusCode, Json<serde_json::Value>)> {
+    let internal_error = |e: surrealdb::Error| {
+        eprintln!("Failed to fetch item {}: {}", id, e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({ "error": { "code": "internal", "message": e.to_string() } })),
+        )
+    };
+
+    let item: Option<DBItem> = db
+        .query(format!("SELECT {} FROM $item", ITEM_FIELDS))
+        .bind(fields)
+        .bind(("item", surrealdb::RecordId::from(("item", id.to_string()))))
+        .await
+        .map_err(internal_error)?
+        .take(0)
+        .map_err(internal_error)?;
+
+    // Selecting a missing record yields no row rather than an error
+    item.map(Json).ok_or((
+        axum::http::StatusCode::NOT_FOUND,
+        Json(serde_json::json!({ "error": { "code": "not_found" } })),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items/{id}/history",
+    params(("id" = u32, Path, description = "GW2 item id"), HistoryParams),
+    responses(
+        (status = 200, description = "Price history in chronological order", body = [HistoryPoint]),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_item_history_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    axum::extract::Query(params): axum::extract::Query<HistoryParams>,
+) -> Result<Json<Vec<HistoryPoint>>, (axum::http::StatusCode, String)> {
+    let points = fetch_history(&db, id, params.from, params.to, params.resolution).await?;
+    Ok(Json(points))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/history/batch",
+    request_body = HistoryBatchRequest,
+    responses(
+        (status = 200, description = "Bucketed price history per requested gw2 id, in chronological order", body = std::collections::BTreeMap<String, Vec<HistoryPoint>>),
+        (status = 400, description = "Too many ids, or a range of more than 1000 buckets", body = String),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_history_batch_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    Json(request): Json<HistoryBatchRequest>,
+) -> Result<Json<BTreeMap<u32, Vec<HistoryPoint>>>, (axum::http::StatusCode, String)> {
+    let mut ids = request.ids;
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.len() > MAX_HISTORY_BATCH_IDS {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!(
+                "At most {} ids may be requested at once",
+                MAX_HISTORY_BATCH_IDS
+            ),
+        ));
+    }
+    // Without `from` the range reaches back as far as allowed
+    let max_range = request.resolution.width() * MAX_HISTORY_BATCH_BUCKETS;
+    let end = request.to.unwrap_or_else(chrono::Utc::now);
+    let from = request.from.unwrap_or(end - max_range);
+    if end - from > max_range {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            format!(
+                "At most {} buckets of {} may be requested at once",
+                MAX_HISTORY_BATCH_BUCKETS,
+                request.resolution.duration()
+            ),
+        ));
+    }
+
+    let series = futures::stream::iter(ids)
+        .map(|id| {
+            let db = &db;
+            async move {
+                let points =
+                    fetch_history(db, id, Some(from), request.to, Some(request.resolution)).await?;
+                Ok::<_, (axum::http::StatusCode, String)>((id, points))
+            }
+        })
+        .buffered(HISTORY_BATCH_CONCURRENCY)
+        .try_collect()
+        .await?;
+    Ok(Json(series))
+}
+
+/// Chronological price history of item `id`, optionally averaged into
+/// `resolution` buckets.
+async fn fetch_history(
+    db: &surrealdb::Surreal<surrealdb::engine::any::Any>,
+    id: u32,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    resolution: Option<gw2shinies_backend::Resolution>,
+) -> Result<Vec<HistoryPoint>, (axum::http::StatusCode, String)> {
+    let mut conditions = vec!["item = $item"];
+    if from.is_some() {
+        conditions.push("<datetime>timestamp >= <datetime>$from");
+    }
+    if to.is_some() {
+        conditions.push("<datetime>timestamp <= <datetime>$to");
+    }
+
+    // Price sync stores timestamps as strings, hence the casts.
+    // Bucketing mirrors the pruning tiers, averaging the points within each bucket
+    let query_string = match resolution {
+        None => format!(
+            "SELECT timestamp, buy_price, sell_price, buy_quantity, sell_quantity FROM item_history WHERE {} ORDER BY timestamp ASC",
+            conditions.join(" AND ")
+        ),
+        Some(resolution) => format!(
+            "SELECT timestamp,
+                math::round(buy_price) AS buy_price,
+                math::round(sell_price) AS sell_price,
+                math::round(buy_quantity) AS buy_quantity,
+                math::round(sell_quantity) AS sell_quantity
+            FROM (
+                SELECT time::floor(<datetime>timestamp, {res}) AS timestamp,
+                    math::mean(buy_price) AS buy_price,
+                    math::mean(sell_price) AS sell_price,
+                    math::mean(buy_quantity) AS buy_quantity,
+                    math::mean(sell_quantity) AS sell_quantity
+                FROM item_history WHERE {cond} GROUP BY timestamp
+            ) ORDER BY timestamp ASC",
+            res = resolution.duration(),
+            cond = conditions.join(" AND ")
+        ),
+    };
+
+    let mut result = db
+        .query(query_string)
+        .bind(("item", surrealdb::RecordId::from(("item", id.to_string()))))
+        .bind(("from", from))
+        .bind(("to", to))
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to fetch history for item {}: {}", id, e);
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Database error: {}", e),
+            )
+        })?;
+    result.take(0).map_err(|e| {
+        eprintln!("Failed to parse history: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to parse data".to_string(),
+        )
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items/{id}/moving-average",
+    params(("id" = u32, Path, description = "GW2 item id"), MovingAverageParams),
+    responses(
+        (status = 200, description = "Moving averages of buy and sell price, one per history sample", body = [MovingAveragePoint]),
+        (status = 400, description = "Invalid window", body = String),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_item_moving_average_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    axum::extract::Query(params): axum::extract::Query<MovingAverageParams>,
+) -> Result<Json<Vec<MovingAveragePoint>>, (axum::http::StatusCode, String)> {
+    let window = params.window.unwrap_or(20);
+    if !(1..=500).contains(&window) {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            "window must be between 1 and 500".to_string(),
+        ));
+    }
+    let points = fetch_history(&db, id, params.from, params.to, params.resolution).await?;
+
+    let average = match params.kind.unwrap_or_default() {
+        AverageKind::Sma => indicators::sma,
+        AverageKind::Ema => indicators::ema,
+    };
+    let buys: Vec<f64> = points.iter().map(|p| p.buy_price as f64).collect();
+    let sells: Vec<f64> = points.iter().map(|p| p.sell_price as f64).collect();
+    let averages = points
+        .iter()
+        .zip(average(&buys, window))
+        .zip(average(&sells, window))
+        .map(|((point, buy_price), sell_price)| MovingAveragePoint {
+            timestamp: point.timestamp,
+            buy_price,
+            sell_price,
+        })
+        .collect();
+    Ok(Json(averages))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items/{id}/volatility",
+    params(("id" = u32, Path, description = "GW2 item id"), WindowParams),
+    responses(
+        (status = 200, description = "Coefficient of variation of the sell price over the trailing window", body = Volatility),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_item_volatility_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    axum::extract::Query(params): axum::extract::Query<WindowParams>,
+) -> Result<Json<Volatility>, (axum::http::StatusCode, String)> {
+    let hours = params.hours.unwrap_or(168).clamp(1, 720);
+    let from = chrono::Utc::now() - chrono::Duration::hours(hours as i64);
+    let points = fetch_history(&db, id, Some(from), None, None).await?;
+
+    let sells: Vec<f64> = points.iter().map(|p| p.sell_price as f64).collect();
+    Ok(Json(Volatility {
+        gw2_id: id,
+        hours,
+        samples: sells.len(),
+        coefficient_of_variation: indicators::coefficient_of_variation(&sells),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items/{id}/vwap",
+    params(("id" = u32, Path, description = "GW2 item id"), WindowParams),
+    responses(
+        (status = 200, description = "Volume-weighted average buy and sell price over the trailing window", body = Vwap),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_item_vwap_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    axum::extract::Query(params): axum::extract::Query<WindowParams>,
+) -> Result<Json<Vwap>, (axum::http::StatusCode, String)> {
+    let hours = params.hours.unwrap_or(168).clamp(1, 720);
+    let from = chrono::Utc::now() - chrono::Duration::hours(hours as i64);
+    let points = fetch_history(&db, id, Some(from), None, None).await?;
+
+    let side = |price: fn(&HistoryPoint) -> i64, quantity: fn(&HistoryPoint) -> i64| {
+        let prices: Vec<f64> = points.iter().map(|p| price(p) as f64).collect();
+        let quantities: Vec<f64> = points.iter().map(|p| quantity(p) as f64).collect();
+        indicators::vwap(&prices, &quantities)
+    };
+    Ok(Json(Vwap {
+        gw2_id: id,
+        hours,
+        samples: points.len(),
+        buy_price: side(|p| p.buy_price, |p| p.buy_quantity),
+        sell_price: side(|p| p.sell_price, |p| p.sell_quantity),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/items/{id}/candles",
+    params(("id" = u32, Path, description = "GW2 item id"), HistoryParams),
+    responses(
+        (status = 200, description = "Sell price candles in chronological order (1h buckets by default)", body = [Candle]),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_item_candles_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::Path(id): axum::extract::Path<u32>,
+    axum::extract::Query(params): axum::extract::Query<HistoryParams>,
+) -> Result<Json<Vec<Candle>>, (axum::http::StatusCode, String)> {
+    let internal_error = |e: String| {
+        eprintln!("Failed to build candles for item {}: {}", id, e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )
+    };
+    let resolution = params
+        .resolution
+        .unwrap_or(gw2shinies_backend::Resolution::Hour);
+    let to = params.to;
+
+    // Long ranges read the hourly rollup, which pruning leaves alone, for the
+    // hours it covers. Raw rows fill in the rest: history from before the rollup
+    // first ran (e.g. recovered from gw2bltc) and the hours it hasn't reached yet
+    let large = params
+        .from
+        .is_none_or(|from| to.unwrap_or_else(chrono::Utc::now) - from > ROLLUP_MIN_RANGE);
+    let covered = if large {
+        gw2shinies_backend::history_rollup::coverage(&db)
+            .await
+            .map_err(|e| internal_error(e.to_string()))?
+    } else {
+        None
+    };
+    let to_candle =
+        |p: &HistoryPoint| Candle::from_point(p.timestamp, p.sell_price, p.sell_quantity);
+    let mut merged: Vec<Candle> = Vec::new();
+    let mut raw_from = params.from;
+    if let Some(covered) = covered {
+        if params.from.is_none_or(|from| from < covered.from) {
+            let before_to = to.map_or(covered.from, |to| to.min(covered.from));
+            let before = fetch_history(&db, id, params.from, Some(before_to), None).await?;
+            merged.extend(
+                before
+                    .iter()
+                    .filter(|p| p.timestamp < covered.from)
+                    .map(to_candle),
+            );
+        }
+
+        #[derive(serde::Deserialize)]
+        struct RollupRow {
+            t: chrono::DateTime<chrono::Utc>,
+            open: i64,
+            high: i64,
+            low: i64,
+            close: i64,
+            volume: f64,
+            samples: u64,
+        }
+        let mut conditions = vec![
+            "item = $item",
+            "<datetime>bucket_start >= <datetime>$covered_from",
+            "<datetime>bucket_start < <datetime>$covered_to",
+        ];
+        if params.from.is_some() {
+            conditions.push("<datetime>bucket_start >= <datetime>$from");
+        }
+        if to.is_some() {
+            conditions.push("<datetime>bucket_start <= <datetime>$to");
+        }
+        let mut response = db
+            .query(format!(
+                "SELECT <datetime>bucket_start AS t, sell_open AS open, sell_high AS high, sell_low AS low,
+                    sell_close AS close, sell_quantity AS volume, samples
+                FROM item_history_1h WHERE {} ORDER BY t ASC",
+                conditions.join(" AND ")
+            ))
+            .bind(("item", surrealdb::RecordId::from(("item", id.to_string()))))
+            .bind(("covered_from", covered.from))
+            .bind(("covered_to", covered.to))
+            .bind(("from", params.from))
+            .bind(("to", to))
+            .await
+            .map_err(|e| internal_error(e.to_string()))?;
+        let rows: Vec<RollupRow> = response
+            .take(0)
+            .map_err(|e| internal_error(e.to_string()))?;
+        let mut rolled_up: Vec<Candle> = rows
+            .into_iter()
+            .map(|r| Candle {
+                t: r.t,
+                open: r.open,
+                high: r.high,
+                low: r.low,
+                close: r.close,
+                volume: r.volume,
+                samples: r.samples,
+            })
+            .collect();
+        // The rollup has no candles for hours it skipped after a long outage
+        for gap in &covered.gaps {
+            let gap_from = params.from.map_or(gap.from, |from| from.max(gap.from));
+            let gap_to = to.map_or(gap.to, |to| to.min(gap.to));
+            if gap_from > gap_to {
+                continue;
+            }
+            let points = fetch_history(&db, id, Some(gap_from), Some(gap_to), None).await?;
+            rolled_up.extend(
+                points
+                    .iter()
+                    .filter(|p| p.timestamp < gap.to)
+                    .map(to_candle),
+            );
+        }
+        rolled_up.sort_by_key(|c| c.t);
+        merged.extend(rolled_up);
+        raw_from = Some(params.from.map_or(covered.to, |from| from.max(covered.to)));
+    }
+
+    let points = fetch_history(&db, id, raw_from, to, None).await?;
+    merged.extend(points.iter().map(to_candle));
+
+    let candles =
+        candles::bucket(merged, resolution.width()).map_err(|e| internal_error(e.to_string()))?;
+    Ok(Json(candles))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/gems",
+    params(GemParams),
+    responses(
+        (status = 200, description = "Current gem exchange rate and its recent history", body = GemOverview),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_gems_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    axum::extract::Query(params): axum::extract::Query<GemParams>,
+) -> Result<Json<GemOverview>, (axum::http::StatusCode, String)> {
+    let internal_error = |e: surrealdb::Error| {
+        eprintln!("Failed to fetch gem history: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )
+    };
+
+    let hours = params.hours.unwrap_or(24).clamp(1, 720);
+    let since = chrono::Utc::now() - chrono::Duration::hours(hours as i64);
+    // The gem sync stores timestamps as strings, hence the casts
+    let mut result = db
+        .query("SELECT *, <datetime>timestamp AS ts OMIT id FROM gem_history ORDER BY ts DESC LIMIT 1")
+        .query(
+            "SELECT *, <datetime>timestamp AS ts OMIT id FROM gem_history WHERE <datetime>timestamp >= <datetime>$since ORDER BY ts ASC",
+        )
+        .bind(("since", since))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(GemOverview {
+        current: result.take(0).map_err(internal_error)?,
+        history: result.take(1).map_err(internal_error)?,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/alerts",
+    request_body = NewAlert,
+    responses(
+        (status = 201, description = "The alert was stored and is active", body = Alert),
+        (status = 400, description = "Negative threshold", body = String),
+        (status = 404, description = "No item with this id has been synced", body = String),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn create_alert_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    Json(request): Json<NewAlert>,
+) -> Result<(axum::http::StatusCode, Json<Alert>), (axum::http::StatusCode, String)> {
+    let internal_error = |e: surrealdb::Error| {
+        eprintln!("Failed to create alert: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )
+    };
+    if request.threshold < 0 {
+        return Err((
+            axum::http::StatusCode::BAD_REQUEST,
+            "Threshold must not be negative".to_string(),
+        ));
+    }
+
+    let item: Option<surrealdb::RecordId> = db
+        .query("SELECT VALUE id FROM $item")
+        .bind((
+            "item",
+            surrealdb::RecordId::from(("item", request.gw2_id.to_string())),
+        ))
+        .await
+        .map_err(internal_error)?
+        .take(0)
+        .map_err(internal_error)?;
+    if item.is_none() {
+        return Err((
+            axum::http::StatusCode::NOT_FOUND,
+            format!("Unknown item: {}", request.gw2_id),
+        ));
+    }
+
+    match alert::create(&db, request).await.map_err(internal_error)? {
+        Some(alert) => Ok((axum::http::StatusCode::CREATED, Json(alert))),
+        None => Err((
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            "Alert was not stored".to_string(),
+        )),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs",
+    responses(
+        (status = 200, description = "Latest outcome and staleness of each scraper job", body = [JobStatus]),
+        (status = 401, description = "Missing or invalid admin key", body = String),
+        (status = 403, description = "No admin key is configured", body = String),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn get_jobs_handler(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<Json<Vec<JobStatus>>, (axum::http::StatusCode, String)> {
+    let statuses = sync_status::job_statuses(&state.db, state.price_sync_interval).await;
+    statuses.map(Json).map_err(|e| {
+        eprintln!("Failed to fetch job statuses: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/history/import",
+    request_body(content = String, content_type = "text/csv", description = "`gw2_id,timestamp,buy,sell,buy_qty,sell_qty` rows, optionally under a header line"),
+    responses(
+        (status = 200, description = "Rows imported; invalid and duplicate rows are skipped and counted", body = ImportReport),
+        (status = 400, description = "Body could not be read", body = String),
+        (status = 401, description = "Missing or invalid admin key", body = String),
+        (status = 403, description = "No admin key is configured", body = String),
+        (status = 500, description = "Database error", body = String)
+    )
+)]
+async fn import_history_handler(
+    axum::extract::State(db): axum::extract::State<surrealdb::Surreal<surrealdb::engine::any::Any>>,
+    body: axum::body::Body,
+) -> Result<Json<ImportReport>, (axum::http::StatusCode, String)> {
+    let internal_error = |e: surrealdb::Error| {
+        eprintln!("Failed to import history: {}", e);
+        (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Database error: {}", e),
+        )
+    };
+    // Read chunk by chunk so a large file never sits in memory whole
+    let mut importer = HistoryImporter::new(db);
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|e| {
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("Failed to read body: {}", e),
+            )
+        })?;
+        importer.push(&chunk).await.map_err(internal_error)?;
+    }
+    let report = importer.finish().await.map_err(internal_error)?;
+    println!(
+        "Imported {} history rows ({} duplicates, {} invalid).",
+        report.inserted, report.duplicates, report.invalid
+    );
+    Ok(Json(report))
+}
+
+/// Query timings in the Prometheus text format
+async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "text/plain; version=0.0.4",
+        )],
+        gw2shinies_backend::metrics::render(),
+    )
+}
+
+/// Server-sent events: a `price_update` event, carrying a `PriceUpdate`, each time
+/// the scraper finishes a price sync. The stream ends when the server shuts down.
+#[utoipa::path(
+    get,
+    path = "/api/stream",
+    responses(
+        (status = 200, description = "`price_update` events", content_type = "text/event-stream", body = PriceUpdate),
+        (status = 503, description = "The database connection can't follow price syncs", body = String)
+    )
+)]
+async fn stream_handler(
+    axum::extract::State(state): axum::extract::State<AppState>,
+) -> Result<
+    Sse<impl futures::Stream<Item = Result<Event, axum::Error>>>,
+    (axum::http::StatusCode, String),
+> {
+    if !state.live_updates {
+        return Err((
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            "Price updates need a websocket or embedded database connection".to_string(),
+        ));
+    }
+    let updates = futures::stream::unfold(state.updates.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(update) => {
+                    let event = Event::default().event("price_update").json_data(&update);
+                    return Some((event, receiver));
+                }
+                // A client too slow to keep up just misses the older updates
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    // Open streams would otherwise hold graceful shutdown forever
+    Ok(
+        Sse::new(updates.take_until(state.shutdown.cancelled_owned()))
+            .keep_alive(KeepAlive::default()),
+    )
+}
+
+/// How long an `/api/items` page is served from memory; prices only move once
+/// per sync, and a finished sync clears the cache anyway.
+const ITEMS_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(30);
+/// Distinct `/api/items` queries kept, so odd filter combinations can't grow it unbounded
+const ITEMS_CACHE_CAPACITY: usize = 256;
+
+/// Shared state of every handler; handlers that only query take the database.
+#[derive(Clone)]
+struct AppState {
+    db: surrealdb::Surreal<surrealdb::engine::any::Any>,
+    /// Finished price syncs, fed by `price_updates::forward`
+    updates: broadcast::Sender<PriceUpdate>,
+    /// Whether anything feeds `updates`; off when the database can't run live queries
+    live_updates: bool,
+    /// Cancelled when the server starts shutting down
+    shutdown: CancellationToken,
+    /// Recent `/api/items` pages, emptied on every price update
+    items_cache: ItemsCache,
+    /// Key for the `/api/admin` endpoints, which are disabled without one
+    admin_key: ApiKey,
+    /// Bound to every query selecting `ITEM_FIELDS`
+    item_fields: ItemFieldBindings,
+    /// The scraper's price sync interval, which sets when its job is stale
+    price_sync_interval: std::time::Duration,
+}
+
+impl AppState {
+    fn new(db: surrealdb::Surreal<surrealdb::engine::any::Any>) -> Self {
+        Self {
+            db,
+            updates: broadcast::channel(16).0,
+            live_updates: true,
+            shutdown: CancellationToken::new(),
+            items_cache: ItemsCache::new(ITEMS_CACHE_TTL, ITEMS_CACHE_CAPACITY),
+            admin_key: None,
+            item_fields: ItemFieldBindings::new(std::time::Duration::from_secs(
+                gw2shinies_backend::DEFAULT_PRICE_SYNC_INTERVAL_SECS,
+            )),
+            price_sync_interval: std::time::Duration::from_secs(
+                gw2shinies_backend::DEFAULT_PRICE_SYNC_INTERVAL_SECS,
+            ),
+        }
+    }
+}
+
+impl axum::extract::FromRef<AppState> for ItemFieldBindings {
+    fn from_ref(state: &AppState) -> Self {
+        state.item_fields.clone()
+    }
+}
+
+impl axum::extract::FromRef<AppState> for ItemsCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.items_cache.clone()
+    }
+}
+
+impl axum::extract::FromRef<AppState> for surrealdb::Surreal<surrealdb::engine::any::Any> {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+fn app(state: AppState, limiter: RateLimiter, api_key: ApiKey) -> Router {
+    // build our application with a route
+    Router::new()
+        .route("/api/items", get(get_items_handler))
+        .route("/api/items.csv", get(get_items_csv_handler))
+        .route("/api/items/batch", post(get_items_batch_handler))
+        .route("/api/items/changes", get(get_item_changes_handler))
+        .route("/api/items/{id}", get(get_item_handler))
+        .route("/api/stats", get(get_stats_handler))
+        .route("/api/opportunities", get(get_opportunities_handler))
+        .route("/api/vendor-flips", get(get_vendor_flips_handler))
+        .route("/api/items/{id}/history", get(get_item_history_handler))
+        .route("/api/history/batch", post(get_history_batch_handler))
+        .route(
+            "/api/items/{id}/moving-average",
+            get(get_item_moving_average_handler),
+        )
+        .route(
+            "/api/items/{id}/volatility",
+            get(get_item_volatility_handler),
+        )
+        .route("/api/items/{id}/vwap", get(get_item_vwap_handler))
+        .route("/api/items/{id}/candles", get(get_item_candles_handler))
+        .route("/api/gems", get(get_gems_handler))
+        .route("/api/alerts", post(create_alert_handler))
+        .route("/api/stream", get(stream_handler))
+        .route("/metrics", get(metrics_handler))
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
+        // Everything above is authenticated and rate limited, /health stays exempt for probes
+        .route_layer(axum::middleware::from_fn_with_state(
+            api_key,
+            api_key_middleware,
+        ))
+        .route_layer(axum::middleware::from_fn_with_state(
+            limiter,
+            rate_limit_middleware,
+        ))
+        // The whole admin namespace checks its own key instead of the API key
+        .merge(
+            Router::new()
+                .route("/api/admin/jobs", get(get_jobs_handler))
+                .route("/api/admin/history/import", post(import_history_handler))
+                .route_layer(axum::middleware::from_fn_with_state(
+                    state.admin_key.clone(),
+                    admin_key_middleware,
+                )),
+        )
+        .route("/health", get(health_handler))
+        .layer(tower_http::cors::CorsLayer::permissive())
+        .with_state(state)
+}
+
 #[tokio::main]
 async fn main() {
     // initialize tracing
@@ -85,24 +1594,77 @@ async fn main() {
 
     let args = Args::parse();
 
-    let database = Database::init(&args.surreal_uri, &args.surreal_user, &args.surreal_pass)
+    let config = args
+        .database_config()
+        .expect("Failed to read the SurrealDB password file");
+    let database = Database::init(&config)
         .await
         .expect("Failed to initialize database");
 
-    // build our application with a route
-    let app = Router::new()
-        .route("/health", get(health_handler))
-        .route("/api/items", get(get_items_handler))
-        .layer(tower_http::cors::CorsLayer::permissive())
-        .with_state(database.db);
+    let limiter = RateLimiter::new(args.rate_limit_per_minute);
+    let limiter_pruning = limiter.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            limiter_pruning.prune_idle();
+        }
+    });
+
+    // The scraper shares these settings, so staleness follows its schedule
+    let price_sync_interval = std::time::Duration::from_secs(args.price_sync_interval_secs);
+    let state = AppState {
+        live_updates: config.supports_live_queries(),
+        admin_key: args.admin_api_key.map(Into::into),
+        item_fields: ItemFieldBindings::new(price_sync_interval),
+        price_sync_interval,
+        ..AppState::new(database.db.clone())
+    };
+    // Over HTTP the live query would only fail and retry forever
+    let forwarder = if state.live_updates {
+        Some(tokio::spawn(price_updates::forward(
+            database.db.clone(),
+            state.updates.clone(),
+            state.shutdown.clone(),
+        )))
+    } else {
+        eprintln!(
+            "Warning: {} can't run live queries, so /api/stream is disabled. Connect over ws:// or wss:// to enable it.",
+            config.uri
+        );
+        None
+    };
+    tokio::spawn(
+        state
+            .items_cache
+            .clone()
+            .clear_on_updates(state.updates.subscribe()),
+    );
+    let shutdown = state.shutdown.clone();
+    let app = app(state, limiter, args.api_key.map(Into::into));
 
     // run our app with hyper
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    println!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+    let listener = tokio::net::TcpListener::bind(args.bind_addr)
         .await
-        .unwrap();
+        .unwrap_or_else(|e| panic!("Failed to bind {}: {}", args.bind_addr, e));
+    println!("listening on {}", listener.local_addr().unwrap());
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        shutdown.cancel();
+    })
+    .await
+    .unwrap();
+    if let Some(forwarder) = forwarder {
+        let _ = forwarder.await;
+    }
+
+    if let Err(e) = database.close().await {
+        eprintln!("Failed to close the database session: {}", e);
+    }
 }
 
 async fn shutdown_signal() {
@@ -130,3 +1692,1369 @@ async fn shutdown_signal() {
 
     println!("signal received, starting graceful shutdown");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    async fn setup_db() -> surrealdb::Surreal<surrealdb::engine::any::Any> {
+        let db = surrealdb::engine::any::connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        gw2shinies_backend::migrate(&db).await.unwrap();
+        db
+    }
+
+    async fn get_json(app: Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn seed_item(
+        db: &surrealdb::Surreal<surrealdb::engine::any::Any>,
+        item: serde_json::Value,
+    ) {
+        db.query("UPSERT type::thing('item', <string>$item.gw2_id) CONTENT $item")
+            .bind(("item", item))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+    }
+
+    fn priced_item(gw2_id: u32, buy: u32, sell: u32) -> serde_json::Value {
+        serde_json::json!({
+            "gw2_id": gw2_id,
+            "name": format!("Item {}", gw2_id),
+            "rarity": "Fine",
+            "is_tradeable": true,
+            "buys": { "quantity": 10, "unit_price": buy },
+            "sells": { "quantity": 10, "unit_price": sell },
+        })
+    }
+
+    fn ids(items: &serde_json::Value) -> Vec<u64> {
+        items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["gw2_id"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_profit_and_roi_filters() {
+        let db = setup_db().await;
+        // profit = round(sell * 0.85) - buy
+        seed_item(&db, priced_item(1, 100, 200)).await; // profit 70, roi 70
+        seed_item(&db, priced_item(2, 100, 120)).await; // profit 2, roi 2
+        seed_item(&db, priced_item(3, 1000, 2000)).await; // profit 700, roi 70
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, items) = get_json(app.clone(), "/api/items?min_profit=50").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&items), vec![3, 1]);
+
+        let (_, items) = get_json(app.clone(), "/api/items?min_profit=50&max_profit=100").await;
+        assert_eq!(ids(&items), vec![1]);
+
+        let (_, items) = get_json(app.clone(), "/api/items?min_roi=1&max_roi=10").await;
+        assert_eq!(ids(&items), vec![2]);
+
+        let (_, items) = get_json(app, "/api/items?min_roi=100").await;
+        assert!(ids(&items).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tradeable_filter() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 200)).await;
+        let mut bound = priced_item(2, 0, 0);
+        bound["is_tradeable"] = false.into();
+        seed_item(&db, bound).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (_, items) = get_json(app.clone(), "/api/items").await;
+        assert_eq!(ids(&items), vec![1]);
+
+        let (_, items) = get_json(app.clone(), "/api/items?tradeable=true").await;
+        assert_eq!(ids(&items), vec![1]);
+
+        let (_, items) = get_json(app, "/api/items?tradeable=false").await;
+        assert_eq!(ids(&items), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_level_filter() {
+        let db = setup_db().await;
+        for (gw2_id, level) in [(1, 0), (2, 40), (3, 80), (4, 80)] {
+            let mut item = priced_item(gw2_id, 100, 100 + gw2_id * 100);
+            item["level"] = level.into();
+            seed_item(&db, item).await;
+        }
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (_, items) = get_json(app.clone(), "/api/items?min_level=80").await;
+        assert_eq!(ids(&items), vec![4, 3]);
+
+        let (_, items) = get_json(app.clone(), "/api/items?min_level=1&max_level=79").await;
+        assert_eq!(ids(&items), vec![2]);
+
+        // Composes with the other filters
+        let (_, items) = get_json(app, "/api/items?max_level=80&search=item%203").await;
+        assert_eq!(ids(&items), vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_price_filters() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 4_000, 6_000)).await;
+        seed_item(&db, priced_item(2, 9_000, 12_000)).await;
+        let mut unlisted = priced_item(3, 0, 0);
+        unlisted.as_object_mut().unwrap().remove("buys");
+        unlisted.as_object_mut().unwrap().remove("sells");
+        seed_item(&db, unlisted).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        // Affordable at 50 silver; the unlisted item is excluded
+        let (_, items) = get_json(app.clone(), "/api/items?max_buy=5000").await;
+        assert_eq!(ids(&items), vec![1]);
+
+        let (_, items) = get_json(app.clone(), "/api/items?min_buy=5000").await;
+        assert_eq!(ids(&items), vec![2]);
+
+        let (_, items) = get_json(app.clone(), "/api/items?min_sell=1000&max_sell=20000").await;
+        assert_eq!(ids(&items), vec![2, 1]);
+
+        let (_, items) = get_json(app, "/api/items?max_sell=1000").await;
+        assert!(ids(&items).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spread_field_and_sort() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 300)).await; // spread 200, profit 155
+        seed_item(&db, priced_item(2, 10_000, 11_000)).await; // spread 1000, profit -650
+        seed_item(&db, priced_item(3, 50, 400)).await; // spread 350, profit 290
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (_, items) = get_json(app.clone(), "/api/items").await;
+        assert_eq!(ids(&items), vec![3, 1, 2]);
+        assert_eq!(items[0]["spread"], 350);
+
+        let (_, items) = get_json(app.clone(), "/api/items?sort=spread").await;
+        assert_eq!(ids(&items), vec![2, 3, 1]);
+        assert_eq!(items[0]["spread"], 1000);
+
+        let (status, _) = get_json(app, "/api/items?sort=name").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_liquidity_sort() {
+        let db = setup_db().await;
+        for (id, liquidity) in [(1, Some(1.5)), (2, Some(4.2)), (3, None)] {
+            let mut item = priced_item(id, 100, 200);
+            if let Some(liquidity) = liquidity {
+                item["liquidity"] = liquidity.into();
+            }
+            seed_item(&db, item).await;
+        }
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (_, items) = get_json(app, "/api/items?sort=liquidity").await;
+        assert_eq!(ids(&items), vec![2, 1, 3]);
+        assert_eq!(items[0]["liquidity"], 4.2);
+    }
+
+    #[tokio::test]
+    async fn test_exclude_price_anomalies() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 200)).await;
+        let mut spiked = priced_item(2, 100, 5000);
+        spiked["price_anomaly"] = true.into();
+        spiked["price_anomaly_magnitude"] = 42.0.into();
+        seed_item(&db, spiked).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (_, items) = get_json(app.clone(), "/api/items").await;
+        assert_eq!(ids(&items), vec![2, 1]);
+        assert_eq!(items[0]["price_anomaly"], true);
+        assert_eq!(items[1]["price_anomaly"], false);
+
+        let (_, items) = get_json(app, "/api/items?exclude_anomalies=true").await;
+        assert_eq!(ids(&items), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_recently_added_sort() {
+        let db = setup_db().await;
+        for (id, first_seen) in [
+            (1, Some("2024-01-01T00:00:00Z")),
+            (2, Some("2024-06-01T00:00:00Z")),
+            (3, None),
+        ] {
+            let mut item = priced_item(id, 100, 200);
+            if let Some(first_seen) = first_seen {
+                item["first_seen"] = first_seen.into();
+            }
+            seed_item(&db, item).await;
+        }
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (_, items) = get_json(app, "/api/items?sort=first_seen").await;
+        assert_eq!(ids(&items), vec![2, 1, 3]);
+        assert_eq!(items[0]["first_seen"], "2024-06-01T00:00:00Z");
+    }
+
+    async fn post_json(
+        app: Router,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = app
+            .oneshot(
+                Request::post(uri)
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_items_batch() {
+        let db = setup_db().await;
+        for gw2_id in 1..=4 {
+            seed_item(&db, priced_item(gw2_id, 100, 200)).await;
+        }
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, items) = post_json(
+            app.clone(),
+            "/api/items/batch",
+            serde_json::json!({ "ids": [2, 4, 99] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let mut found = ids(&items);
+        found.sort();
+        assert_eq!(found, vec![2, 4]);
+        assert_eq!(items[0]["profit"], 70.0);
+
+        let too_many: Vec<u32> = (0..=MAX_BATCH_IDS as u32).collect();
+        let (status, _) = post_json(
+            app,
+            "/api/items/batch",
+            serde_json::json!({ "ids": too_many }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_removed_items_are_not_listed() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 200)).await;
+        let mut removed = priced_item(2, 100, 200);
+        removed["is_removed"] = true.into();
+        seed_item(&db, removed).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (_, items) = get_json(app.clone(), "/api/items").await;
+        assert_eq!(ids(&items), vec![1]);
+        let (_, items) = post_json(
+            app.clone(),
+            "/api/items/batch",
+            serde_json::json!({ "ids": [1, 2] }),
+        )
+        .await;
+        assert_eq!(ids(&items), vec![1]);
+        let (_, stats) = get_json(app.clone(), "/api/stats").await;
+        assert_eq!(stats["tradeable_items"], 1);
+
+        // The item itself stays reachable for its history
+        let (status, _) = get_json(app, "/api/items/2").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_price_update_stream() {
+        let state = AppState::new(setup_db().await);
+        let (updates, shutdown) = (state.updates.clone(), state.shutdown.clone());
+        let app = app(state, RateLimiter::new(120), None);
+
+        let response = app
+            .oneshot(Request::get("/api/stream").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let mut body = response.into_body().into_data_stream();
+
+        updates
+            .send(PriceUpdate {
+                finished_at: chrono::Utc::now(),
+                items_updated: 2,
+                changed: vec![19684],
+            })
+            .unwrap();
+        let chunk = body.next().await.unwrap().unwrap();
+        let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+        assert!(chunk.starts_with("event: price_update\n"), "{}", chunk);
+        let data = chunk
+            .lines()
+            .find_map(|line| line.strip_prefix("data: "))
+            .unwrap();
+        let update: serde_json::Value = serde_json::from_str(data).unwrap();
+        assert_eq!(update["items_updated"], 2);
+        assert_eq!(update["changed"], serde_json::json!([19684]));
+
+        // Shutting down ends the stream instead of holding the connection open
+        shutdown.cancel();
+        assert!(body.next().await.is_none());
+
+        // Over HTTP nothing would ever arrive
+        let state = AppState {
+            live_updates: false,
+            ..AppState::new(setup_db().await)
+        };
+        let (status, _) = get_json(
+            super::app(state, RateLimiter::new(120), None),
+            "/api/stream",
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_create_alert() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 200)).await;
+        let app = app(AppState::new(db.clone()), RateLimiter::new(120), None);
+
+        let alert = |gw2_id: u32, comparator: &str| {
+            serde_json::json!({
+                "gw2_id": gw2_id,
+                "field": "sell_price",
+                "comparator": comparator,
+                "threshold": 150
+            })
+        };
+        let (status, created) = post_json(app.clone(), "/api/alerts", alert(1, "below")).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["gw2_id"], 1);
+        assert_eq!(created["comparator"], "below");
+        assert_eq!(created["active"], true);
+
+        let (status, _) = post_json(app.clone(), "/api/alerts", alert(99, "below")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = post_json(app, "/api/alerts", alert(1, "sideways")).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let stored: Option<serde_json::Value> = db
+            .query("SELECT count() FROM alerts GROUP ALL")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(stored.unwrap()["count"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_items_csv_export() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 200)).await;
+        let mut quoted = priced_item(2, 50, 400);
+        quoted["name"] = "Sword, \"Sharp\"".into();
+        seed_item(&db, quoted).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let response = app
+            .oneshot(
+                Request::get("/api/items.csv?min_profit=50")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/csv");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let csv = String::from_utf8(body.to_vec()).unwrap();
+
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "gw2_id,name,rarity,buy,sell,profit,roi",
+                "2,\"Sword, \"\"Sharp\"\"\",Fine,50,400,290.0,580.0",
+                "1,Item 1,Fine,100,200,70.0,70.0",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_checks_database() {
+        let app = app(AppState::new(setup_db().await), RateLimiter::new(120), None);
+        let (status, body) = get_json(app, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["db"], "ok");
+        assert!(body.get("syncs").is_none());
+
+        // A client that never connected behaves like an unreachable database
+        let disconnected = surrealdb::Surreal::<surrealdb::engine::any::Any>::init();
+        let (status, body) = get_json(
+            super::app(AppState::new(disconnected), RateLimiter::new(120), None),
+            "/health",
+        )
+        .await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["db"], "down");
+    }
+
+    #[tokio::test]
+    async fn test_field_selection() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 200)).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, items) = get_json(app.clone(), "/api/items?fields=name,profit").await;
+        assert_eq!(status, StatusCode::OK);
+        let mut keys: Vec<&String> = items[0].as_object().unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["gw2_id", "id", "name", "profit"]);
+
+        let (status, _) = get_json(app, "/api/items?fields=name,password").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    async fn seed_history(
+        db: &surrealdb::Surreal<surrealdb::engine::any::Any>,
+        gw2_id: u32,
+        timestamp: &str,
+        sell_price: i64,
+    ) {
+        db.query("CREATE item_history SET item = $item, timestamp = $timestamp, buy_price = $sell_price - 10, sell_price = $sell_price, buy_quantity = 100, sell_quantity = 100")
+            .bind(("item", surrealdb::RecordId::from(("item", gw2_id.to_string()))))
+            .bind(("timestamp", timestamp.to_string()))
+            .bind(("sell_price", sell_price))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_history_batch() {
+        let db = setup_db().await;
+        seed_history(&db, 1, "2025-01-01T00:00:00Z", 100).await;
+        seed_history(&db, 1, "2025-01-01T00:30:00Z", 200).await;
+        seed_history(&db, 1, "2025-01-01T01:10:00Z", 300).await;
+        seed_history(&db, 2, "2025-01-01T00:20:00Z", 5000).await;
+        seed_history(&db, 2, "2025-01-02T00:00:00Z", 6000).await;
+        // More than 1000 hours before `to`, which is as far back as 1h buckets reach
+        seed_history(&db, 1, "2024-11-01T00:00:00Z", 900).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, series) = post_json(
+            app.clone(),
+            "/api/history/batch",
+            serde_json::json!({
+                "ids": [2, 1, 2, 3],
+                "to": "2025-01-01T12:00:00Z",
+                "resolution": "1h"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let sells = |id: &str| -> Vec<i64> {
+            series[id]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|p| p["sell_price"].as_i64().unwrap())
+                .collect()
+        };
+        assert_eq!(series.as_object().unwrap().len(), 3);
+        assert_eq!(sells("1"), vec![150, 300]);
+        assert_eq!(sells("2"), vec![5000]);
+        assert_eq!(sells("3"), Vec::<i64>::new());
+
+        let (status, _) = post_json(
+            app.clone(),
+            "/api/history/batch",
+            serde_json::json!({
+                "ids": [1],
+                "from": "2024-01-01T00:00:00Z",
+                "to": "2025-01-01T00:00:00Z",
+                "resolution": "1h"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let too_many: Vec<u32> = (0..=MAX_HISTORY_BATCH_IDS as u32).collect();
+        let (status, _) = post_json(
+            app.clone(),
+            "/api/history/batch",
+            serde_json::json!({ "ids": too_many, "resolution": "1d" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // Raw rows aren't served in bulk
+        let (status, _) =
+            post_json(app, "/api/history/batch", serde_json::json!({ "ids": [1] })).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_import_history_csv() {
+        let db = setup_db().await;
+        seed_history(&db, 1, "2025-01-01T00:00:00Z", 100).await;
+        seed_item(&db, priced_item(1, 90, 100)).await;
+        seed_item(&db, priced_item(2, 40, 50)).await;
+        let state = AppState {
+            admin_key: Some("root".into()),
+            ..AppState::new(db.clone())
+        };
+        let app = app(state, RateLimiter::new(120), Some("secret".into()));
+        let import = |auth: &'static str| {
+            Request::post("/api/admin/history/import")
+                .header("content-type", "text/csv")
+                .header("authorization", auth)
+                .body(Body::from(
+                    "gw2_id,timestamp,buy,sell,buy_qty,sell_qty\n\
+                    1,2025-01-01T00:00:00Z,90,100,100,100\n\
+                    1,2025-01-01T01:00:00Z,190,200,100,100\n\
+                    2,2025-01-01T00:30:00Z,40,50,7,8\n\
+                    2,not a time,40,50,7,8\n",
+                ))
+                .unwrap()
+        };
+
+        // The API key doesn't open admin endpoints
+        let response = app.clone().oneshot(import("Bearer secret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.clone().oneshot(import("Bearer root")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(report["rows"], 4);
+        assert_eq!(report["inserted"], 2);
+        assert_eq!(report["duplicates"], 1);
+        assert_eq!(report["invalid"], 1);
+
+        let history = |id: u32| fetch_history(&db, id, None, None, None);
+        let sells: Vec<i64> = history(1)
+            .await
+            .unwrap()
+            .iter()
+            .map(|p| p.sell_price)
+            .collect();
+        assert_eq!(sells, vec![100, 200]);
+        let imported = history(2).await.unwrap();
+        assert_eq!(imported.len(), 1);
+        assert_eq!((imported[0].buy_price, imported[0].sell_quantity), (40, 8));
+
+        // Importing the same file again adds nothing
+        let response = app.oneshot(import("Bearer root")).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            (report["inserted"].as_u64(), report["duplicates"].as_u64()),
+            (Some(0), Some(3))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_item_history_resolution() {
+        let db = setup_db().await;
+        seed_history(&db, 1, "2025-01-01T00:00:00Z", 100).await;
+        seed_history(&db, 1, "2025-01-01T00:15:00Z", 200).await;
+        seed_history(&db, 1, "2025-01-01T01:30:00Z", 300).await;
+        seed_history(&db, 1, "2025-01-01T07:00:00Z", 400).await;
+        seed_history(&db, 2, "2025-01-01T00:00:00Z", 999).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, points) = get_json(app.clone(), "/api/items/1/history").await;
+        assert_eq!(status, StatusCode::OK);
+        let prices: Vec<i64> = points
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["sell_price"].as_i64().unwrap())
+            .collect();
+        assert_eq!(prices, vec![100, 200, 300, 400]);
+
+        let (_, points) = get_json(app.clone(), "/api/items/1/history?resolution=1h").await;
+        let points = points.as_array().unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0]["timestamp"], "2025-01-01T00:00:00Z");
+        assert_eq!(points[0]["sell_price"], 150);
+        assert_eq!(points[1]["timestamp"], "2025-01-01T01:00:00Z");
+
+        let (_, points) = get_json(app.clone(), "/api/items/1/history?resolution=6h").await;
+        let prices: Vec<i64> = points
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["sell_price"].as_i64().unwrap())
+            .collect();
+        assert_eq!(prices, vec![200, 400]);
+
+        let (_, points) = get_json(
+            app.clone(),
+            "/api/items/1/history?from=2025-01-01T01:00:00Z&to=2025-01-01T02:00:00Z",
+        )
+        .await;
+        assert_eq!(points.as_array().unwrap().len(), 1);
+
+        let (status, _) = get_json(app, "/api/items/1/history?resolution=5m").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_item_moving_average() {
+        let db = setup_db().await;
+        for (minute, sell_price) in [(0, 100), (10, 200), (20, 300), (30, 600)] {
+            seed_history(
+                &db,
+                1,
+                &format!("2025-01-01T00:{:02}:00Z", minute),
+                sell_price,
+            )
+            .await;
+        }
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, points) = get_json(app.clone(), "/api/items/1/moving-average?window=2").await;
+        assert_eq!(status, StatusCode::OK);
+        let sells: Vec<Option<f64>> = points
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["sell_price"].as_f64())
+            .collect();
+        assert_eq!(sells, vec![None, Some(150.0), Some(250.0), Some(450.0)]);
+
+        let (_, points) =
+            get_json(app.clone(), "/api/items/1/moving-average?window=3&kind=ema").await;
+        // Seeded with 200, then 200 + 0.5 * (600 - 200)
+        assert_eq!(points[3]["sell_price"], 400.0);
+
+        let (status, _) = get_json(app, "/api/items/1/moving-average?window=0").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_item_volatility() {
+        let db = setup_db().await;
+        let now = chrono::Utc::now();
+        for (hours_ago, flat, swinging) in [(3, 500, 100), (2, 500, 900), (1, 500, 100)] {
+            let timestamp = (now - chrono::Duration::hours(hours_ago)).to_rfc3339();
+            seed_history(&db, 1, &timestamp, flat).await;
+            seed_history(&db, 2, &timestamp, swinging).await;
+        }
+        // Outside the default week, so it doesn't count
+        let old = (now - chrono::Duration::days(20)).to_rfc3339();
+        seed_history(&db, 3, &old, 100).await;
+        seed_history(&db, 3, &now.to_rfc3339(), 200).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, flat) = get_json(app.clone(), "/api/items/1/volatility").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(flat["samples"], 3);
+        assert!(flat["coefficient_of_variation"].as_f64().unwrap() < 1e-9);
+
+        let (_, swinging) = get_json(app.clone(), "/api/items/2/volatility").await;
+        assert!(swinging["coefficient_of_variation"].as_f64().unwrap() > 0.5);
+
+        let (_, sparse) = get_json(app.clone(), "/api/items/3/volatility").await;
+        assert_eq!(sparse["samples"], 1);
+        assert!(sparse["coefficient_of_variation"].is_null());
+        let (_, sparse) = get_json(app, "/api/items/3/volatility?hours=720").await;
+        assert_eq!(sparse["samples"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_item_vwap() {
+        let db = setup_db().await;
+        let now = chrono::Utc::now();
+        // The empty sell side at 900 carries no weight
+        for (hours_ago, sell_price, sell_quantity) in [(3, 100, 10), (2, 200, 30), (1, 900, 0)] {
+            db.query("CREATE item_history SET item = item:⟨1⟩, timestamp = $timestamp, buy_price = 50, sell_price = $sell_price, buy_quantity = 0, sell_quantity = $sell_quantity")
+                .bind(("timestamp", now - chrono::Duration::hours(hours_ago)))
+                .bind(("sell_price", sell_price))
+                .bind(("sell_quantity", sell_quantity))
+                .await
+                .unwrap()
+                .check()
+                .unwrap();
+        }
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, vwap) = get_json(app, "/api/items/1/vwap").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(vwap["samples"], 3);
+        assert_eq!(vwap["sell_price"], 175.0);
+        assert!(vwap["buy_price"].is_null());
+    }
+
+    /// Marks the hourly rollup as covering `from` up to `to`, as its runs would.
+    async fn cover_rollup(
+        db: &surrealdb::Surreal<surrealdb::engine::any::Any>,
+        from: &str,
+        to: &str,
+    ) {
+        db.query(
+            "UPSERT sync_status:history_rollup MERGE {
+                job: 'history_rollup', rolled_up_from: <datetime>$from, rolled_up_to: <datetime>$to
+            }",
+        )
+        .bind(("from", from.to_string()))
+        .bind(("to", to.to_string()))
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_item_candles() {
+        let db = setup_db().await;
+        for (minute, sell_price) in [(0, 100), (10, 140), (20, 80), (30, 120)] {
+            seed_history(
+                &db,
+                1,
+                &format!("2025-01-01T00:{:02}:00Z", minute),
+                sell_price,
+            )
+            .await;
+        }
+        seed_history(&db, 1, "2025-01-01T01:05:00Z", 90).await;
+        let app = app(AppState::new(db.clone()), RateLimiter::new(120), None);
+
+        let (status, candles) = get_json(
+            app.clone(),
+            "/api/items/1/candles?resolution=1h&from=2025-01-01T00:00:00Z&to=2025-01-01T02:00:00Z",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let candles = candles.as_array().unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0]["t"], "2025-01-01T00:00:00Z");
+        assert_eq!(candles[0]["open"], 100);
+        assert_eq!(candles[0]["high"], 140);
+        assert_eq!(candles[0]["low"], 80);
+        assert_eq!(candles[0]["close"], 120);
+        assert_eq!(candles[0]["volume"], 100.0);
+        assert_eq!(candles[1]["open"], 90);
+
+        // Long ranges start from the hourly rollup and add the raw rows after it.
+        // The rolled-up raw rows are pruned, so only the rollup has that hour
+        gw2shinies_backend::history_rollup::HistoryRollup::new(db.clone())
+            .rollup_hour("2025-01-01T00:00:00Z".parse().unwrap())
+            .await
+            .unwrap();
+        cover_rollup(&db, "2025-01-01T00:00:00Z", "2025-01-01T01:00:00Z").await;
+        db.query("DELETE item_history WHERE <datetime>timestamp < d'2025-01-01T01:00:00Z'")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        let (_, candles) = get_json(app, "/api/items/1/candles?resolution=1d").await;
+        let candles = candles.as_array().unwrap();
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0]["t"], "2025-01-01T00:00:00Z");
+        assert_eq!(candles[0]["open"], 100);
+        assert_eq!(candles[0]["high"], 140);
+        assert_eq!(candles[0]["low"], 80);
+        assert_eq!(candles[0]["close"], 90);
+    }
+
+    #[tokio::test]
+    async fn test_item_candles_fill_rollup_gaps() {
+        let db = setup_db().await;
+        seed_history(&db, 1, "2025-01-01T00:10:00Z", 100).await;
+        seed_history(&db, 1, "2025-01-01T02:10:00Z", 150).await;
+        seed_history(&db, 1, "2025-01-01T04:10:00Z", 80).await;
+        let rollup = gw2shinies_backend::history_rollup::HistoryRollup::new(db.clone());
+        for hour in ["2025-01-01T00:00:00Z", "2025-01-01T04:00:00Z"] {
+            rollup.rollup_hour(hour.parse().unwrap()).await.unwrap();
+        }
+        // The rollup skipped 01:00 to 04:00 after an outage; the hours it did roll
+        // up have been pruned from raw history
+        cover_rollup(&db, "2025-01-01T00:00:00Z", "2025-01-01T05:00:00Z").await;
+        db.query(
+            "UPDATE sync_status:history_rollup SET rolled_up_gaps = [{ from: '2025-01-01T01:00:00Z', to: '2025-01-01T04:00:00Z' }];
+             DELETE item_history WHERE <datetime>timestamp < d'2025-01-01T01:00:00Z' OR <datetime>timestamp >= d'2025-01-01T04:00:00Z';",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, candles) = get_json(app, "/api/items/1/candles?resolution=1d").await;
+        assert_eq!(status, StatusCode::OK);
+        let candles = candles.as_array().unwrap();
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0]["open"], 100);
+        assert_eq!(candles[0]["high"], 150);
+        assert_eq!(candles[0]["close"], 80);
+    }
+
+    #[tokio::test]
+    async fn test_item_candles_before_rollup() {
+        let db = setup_db().await;
+        // Recovered history from long before the rollup first ran
+        seed_history(&db, 1, "2024-12-01T10:00:00Z", 200).await;
+        seed_history(&db, 1, "2024-12-01T12:00:00Z", 150).await;
+        seed_history(&db, 1, "2025-01-01T00:10:00Z", 100).await;
+        seed_history(&db, 1, "2025-01-01T03:10:00Z", 90).await;
+        gw2shinies_backend::history_rollup::HistoryRollup::new(db.clone())
+            .rollup_hour("2025-01-01T00:00:00Z".parse().unwrap())
+            .await
+            .unwrap();
+        cover_rollup(&db, "2025-01-01T00:00:00Z", "2025-01-01T01:00:00Z").await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, candles) = get_json(app, "/api/items/1/candles?resolution=1d").await;
+        assert_eq!(status, StatusCode::OK);
+        let candles = candles.as_array().unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0]["t"], "2024-12-01T00:00:00Z");
+        assert_eq!(candles[0]["open"], 200);
+        assert_eq!(candles[0]["close"], 150);
+        assert_eq!(candles[1]["t"], "2025-01-01T00:00:00Z");
+        assert_eq!(candles[1]["open"], 100);
+        assert_eq!(candles[1]["close"], 90);
+    }
+
+    #[tokio::test]
+    async fn test_opportunities_ranking() {
+        let db = setup_db().await;
+        let with_volume = |gw2_id: u32, buy: u32, sell: u32, quantity: u32| {
+            let mut item = priced_item(gw2_id, buy, sell);
+            item["buys"]["quantity"] = quantity.into();
+            item["sells"]["quantity"] = quantity.into();
+            item
+        };
+        // Solid profit on a deep book
+        seed_item(&db, with_volume(1, 1000, 2000, 5000)).await;
+        // Bigger profit, but hardly anything listed
+        seed_item(&db, with_volume(2, 1000, 3000, 60)).await;
+        // Thin margin on a deep book
+        seed_item(&db, with_volume(3, 1000, 1200, 5000)).await;
+        // Loses money after the fee
+        seed_item(&db, with_volume(4, 1000, 1100, 5000)).await;
+        // Deep book, but the price sync stored it as illiquid, and that's what counts
+        let mut illiquid = with_volume(5, 1000, 1900, 5000);
+        illiquid["liquidity"] = 0.0.into();
+        seed_item(&db, illiquid).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, ranked) = get_json(app.clone(), "/api/opportunities").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&ranked), vec![1, 2, 3, 5]);
+        assert!(ranked[0]["score"].as_f64().unwrap() > ranked[1]["score"].as_f64().unwrap());
+        assert_eq!(ranked[0]["volume"], 10000);
+
+        let (_, ranked) = get_json(app.clone(), "/api/opportunities?min_volume=1000").await;
+        assert_eq!(ids(&ranked), vec![1, 3, 5]);
+
+        // Weighting only profit puts the thin-book item first
+        let (_, ranked) = get_json(
+            app,
+            "/api/opportunities?liquidity_weight=0&roi_weight=0&min_profit=100",
+        )
+        .await;
+        assert_eq!(ids(&ranked), vec![2, 1, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_vendor_flips() {
+        let db = setup_db().await;
+        let with_vendor_value = |gw2_id: u32, buy: u32, vendor_value: u32| {
+            let mut item = priced_item(gw2_id, buy, buy * 2);
+            item["vendor_value"] = vendor_value.into();
+            item
+        };
+        seed_item(&db, with_vendor_value(1, 100, 150)).await;
+        seed_item(&db, with_vendor_value(2, 100, 300)).await;
+        // Worth less to the vendor than the buy price
+        seed_item(&db, with_vendor_value(3, 100, 50)).await;
+        // Beats a fee-reduced sale but not the buy price, so flipping it loses money
+        seed_item(&db, with_vendor_value(5, 100, 90)).await;
+        let mut bound = with_vendor_value(4, 100, 500);
+        bound["is_tradeable"] = false.into();
+        seed_item(&db, bound).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, flips) = get_json(app, "/api/vendor-flips").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&flips), vec![2, 1]);
+        assert_eq!(flips[0]["vendor_value"], 300);
+        assert_eq!(flips[0]["vendor_profit"], 200);
+    }
+
+    #[tokio::test]
+    async fn test_item_changes_since() {
+        use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let db = setup_db().await;
+        for gw2_id in 1..=3 {
+            seed_item(&db, priced_item(gw2_id, 100, 200)).await;
+        }
+        let server = MockServer::start().await;
+        let prices = |buys: &[(u32, u32)]| {
+            let prices: Vec<serde_json::Value> = buys
+                .iter()
+                .map(|(id, buy)| {
+                    serde_json::json!({
+                        "id": id,
+                        "buys": { "quantity": 10, "unit_price": buy },
+                        "sells": { "quantity": 10, "unit_price": 200 }
+                    })
+                })
+                .collect();
+            let ids: Vec<u32> = buys.iter().map(|(id, _)| *id).collect();
+            let joined: Vec<String> = ids.iter().map(u32::to_string).collect();
+            (
+                Mock::given(method("GET"))
+                    .and(path("/v2/commerce/prices"))
+                    .and(query_param_is_missing("ids"))
+                    .respond_with(ResponseTemplate::new(200).set_body_json(ids)),
+                Mock::given(method("GET"))
+                    .and(path("/v2/commerce/prices"))
+                    .and(query_param("ids", joined.join(",")))
+                    .respond_with(ResponseTemplate::new(200).set_body_json(prices)),
+            )
+        };
+        let sync = gw2shinies_backend::price_sync::PriceSync::new(db.clone()).with_client(
+            gw2shinies_backend::gw2_api::Gw2Client::with_urls(server.uri(), "".to_string()),
+        );
+
+        let (list, chunk) = prices(&[(1, 100), (2, 100), (3, 100)]);
+        let first = (
+            list.mount_as_scoped(&server).await,
+            chunk.mount_as_scoped(&server).await,
+        );
+        sync.run_sync().await.unwrap();
+        drop(first);
+        let since = chrono::Utc::now();
+
+        // All three are listed again, but item 2 at the same price
+        let (list, chunk) = prices(&[(1, 110), (2, 100), (3, 120)]);
+        list.mount(&server).await;
+        chunk.mount(&server).await;
+        sync.run_sync().await.unwrap();
+
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+        let uri = format!(
+            "/api/items/changes?since={}",
+            since.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+        );
+        let (status, items) = get_json(app.clone(), &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&items), vec![1, 3]);
+        assert_eq!(items[0]["buys"]["unit_price"], 110);
+
+        // One per page, following the next links
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(format!("{}&limit=1", uri))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let link = response.headers()["link"].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let items: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(ids(&items), vec![1]);
+        let next = link
+            .strip_prefix('<')
+            .and_then(|link| link.strip_suffix(">; rel=\"next\""))
+            .unwrap();
+        let (_, items) = get_json(app.clone(), next).await;
+        assert_eq!(ids(&items), vec![3]);
+
+        let (status, _) = get_json(app, "/api/items/changes").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_item_detail() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 200)).await;
+        let app = app(AppState::new(db.clone()), RateLimiter::new(120), None);
+
+        let (status, item) = get_json(app.clone(), "/api/items/1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(item["gw2_id"], 1);
+        assert_eq!(item["profit"], 70.0);
+
+        // Metrics stored by the price sync win over the inline computation
+        let mut synced = priced_item(2, 100, 200);
+        synced["profit"] = 60.into();
+        synced["roi"] = 60.0.into();
+        seed_item(&db, synced).await;
+        let (_, item) = get_json(app.clone(), "/api/items/2").await;
+        assert_eq!(item["profit"], 60.0);
+        assert_eq!(item["roi"], 60.0);
+
+        // Never synced
+        let (status, body) = get_json(app, "/api/items/424242").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            serde_json::json!({ "error": { "code": "not_found" } })
+        );
+    }
+
+    #[test]
+    fn test_pagination_links() {
+        assert_eq!(
+            pagination_links("/api/items", Some("search=ore&page=2&limit=10"), 2, 10, 35),
+            "</api/items?search=ore&page=1&limit=10>; rel=\"first\", \
+             </api/items?search=ore&page=1&limit=10>; rel=\"prev\", \
+             </api/items?search=ore&page=3&limit=10>; rel=\"next\", \
+             </api/items?search=ore&page=4&limit=10>; rel=\"last\""
+        );
+
+        // Single page: neither prev nor next
+        assert_eq!(
+            pagination_links("/api/items", None, 1, 50, 0),
+            "</api/items?page=1&limit=50>; rel=\"first\", </api/items?page=1&limit=50>; rel=\"last\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stale_flag() {
+        let db = setup_db().await;
+        let mut fresh = priced_item(1, 100, 200);
+        fresh["last_price_update"] = chrono::Utc::now().to_rfc3339().into();
+        seed_item(&db, fresh).await;
+        let app = app(AppState::new(db.clone()), RateLimiter::new(120), None);
+
+        let (_, item) = get_json(app.clone(), "/api/items/1").await;
+        assert_eq!(item["is_stale"], false);
+
+        // Age the last update past the threshold, as if syncs kept failing for this item
+        let aged = (chrono::Utc::now() - chrono::Duration::hours(2)).to_rfc3339();
+        db.query("UPDATE item:⟨1⟩ SET last_price_update = $aged")
+            .bind(("aged", aged))
+            .await
+            .unwrap();
+        let (_, item) = get_json(app.clone(), "/api/items/1").await;
+        assert_eq!(item["is_stale"], true);
+
+        // With hourly syncs the same age is still within a few missed runs
+        let hourly = AppState {
+            item_fields: ItemFieldBindings::new(std::time::Duration::from_secs(3600)),
+            ..AppState::new(db.clone())
+        };
+        let (_, item) = get_json(
+            super::app(hourly, RateLimiter::new(120), None),
+            "/api/items/1",
+        )
+        .await;
+        assert_eq!(item["is_stale"], false);
+
+        // Never priced at all
+        seed_item(&db, priced_item(2, 100, 200)).await;
+        let (_, item) = get_json(app, "/api/items/2").await;
+        assert_eq!(item["is_stale"], true);
+    }
+
+    #[tokio::test]
+    async fn test_items_link_header() {
+        let db = setup_db().await;
+        for gw2_id in 1..=5 {
+            seed_item(&db, priced_item(gw2_id, 100, 200)).await;
+        }
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let response = app
+            .oneshot(
+                Request::get("/api/items?limit=2&page=3")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-total-count"], "5");
+        let link = response.headers()["link"].to_str().unwrap();
+        assert!(link.contains("</api/items?page=2&limit=2>; rel=\"prev\""));
+        assert!(link.contains("</api/items?page=3&limit=2>; rel=\"last\""));
+        assert!(!link.contains("rel=\"next\""));
+    }
+
+    #[tokio::test]
+    async fn test_items_list_cache() {
+        let db = setup_db().await;
+        seed_item(&db, priced_item(1, 100, 200)).await;
+        let state = AppState::new(db.clone());
+        let cache = state.items_cache.clone();
+        let app = app(state, RateLimiter::new(120), None);
+
+        let (_, first) = get_json(app.clone(), "/api/items?limit=10&sort=profit").await;
+        assert_eq!(cache.hits(), 0);
+        // Same query with its parameters reordered, within the TTL
+        seed_item(&db, priced_item(2, 100, 300)).await;
+        let (_, second) = get_json(app.clone(), "/api/items?sort=profit&limit=10").await;
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(first, second);
+        assert_eq!(second.as_array().unwrap().len(), 1);
+
+        // Clearing, as a finished price sync does, goes back to the database
+        cache.clear();
+        let (_, third) = get_json(app, "/api/items?limit=10&sort=profit").await;
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(third.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_market_stats() {
+        let db = setup_db().await;
+        let mut item = priced_item(1, 100, 200);
+        item["last_price_update"] = "2025-01-01T00:00:00Z".into();
+        seed_item(&db, item).await;
+        let mut item = priced_item(2, 100, 400);
+        item["last_price_update"] = "2025-01-02T00:00:00Z".into();
+        seed_item(&db, item).await;
+        seed_item(&db, priced_item(3, 100, 1100)).await;
+        let mut unlisted = priced_item(4, 0, 0);
+        unlisted.as_object_mut().unwrap().remove("sells");
+        seed_item(&db, unlisted).await;
+        let mut bound = priced_item(5, 0, 0);
+        bound["is_tradeable"] = false.into();
+        bound.as_object_mut().unwrap().remove("buys");
+        bound.as_object_mut().unwrap().remove("sells");
+        seed_item(&db, bound).await;
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, stats) = get_json(app, "/api/stats").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["tradeable_items"], 4);
+        assert_eq!(stats["priced_items"], 3);
+        // Spreads are 100, 300, 1000
+        assert_eq!(stats["median_spread"], 300.0);
+        assert_eq!(stats["mean_spread"], 1400.0 / 3.0);
+        assert_eq!(stats["last_price_update"], "2025-01-02T00:00:00Z");
+    }
+
+    #[tokio::test]
+    async fn test_gem_rates() {
+        let db = setup_db().await;
+        let now = chrono::Utc::now();
+        for (hours_ago, coins_per_gem) in [(48, 2400), (2, 2500), (1, 2550)] {
+            let rate = gw2shinies_backend::exchange::GemRate::new(
+                &gw2shinies_backend::exchange::GemExchange {
+                    coins_per_gem,
+                    coins_per_gem_sold: coins_per_gem - 600,
+                },
+                now - chrono::Duration::hours(hours_ago),
+            );
+            db.query("CREATE gem_history CONTENT $rate")
+                .bind(("rate", rate))
+                .await
+                .unwrap()
+                .check()
+                .unwrap();
+        }
+        let app = app(AppState::new(db), RateLimiter::new(120), None);
+
+        let (status, gems) = get_json(app.clone(), "/api/gems").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(gems["current"]["coins_per_gem"], 2550);
+        assert_eq!(gems["current"]["coins_per_gem_sold"], 1950);
+        let history = gems["history"].as_array().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0]["coins_per_gem"], 2500);
+        assert_eq!(history[0]["gems_per_coin"], 0.0004);
+
+        let (_, gems) = get_json(app, "/api/gems?hours=72").await;
+        assert_eq!(gems["history"].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_openapi_describes_items_endpoint() {
+        let app = app(AppState::new(setup_db().await), RateLimiter::new(120), None);
+        let (status, doc) = get_json(app, "/openapi.json").await;
+        assert_eq!(status, StatusCode::OK);
+
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+        let params = doc["paths"]["/api/items"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        let names: Vec<&str> = params.iter().map(|p| p["name"].as_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "page",
+                "limit",
+                "search",
+                "min_profit",
+                "max_profit",
+                "min_roi",
+                "max_roi",
+                "tradeable",
+                "min_level",
+                "max_level",
+                "min_buy",
+                "max_buy",
+                "min_sell",
+                "max_sell",
+                "exclude_anomalies",
+                "sort",
+                "fields"
+            ]
+        );
+        assert!(params.iter().all(|p| p["in"] == "query"));
+        assert!(doc["components"]["schemas"]["DBItem"].is_object());
+    }
+
+    #[tokio::test]
+    async fn test_health_exempt_from_rate_limit() {
+        let app = app(AppState::new(setup_db().await), RateLimiter::new(1), None);
+
+        let (status, _) = get_json(app.clone(), "/api/items").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = get_json(app.clone(), "/api/items").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+        for _ in 0..3 {
+            let (status, _) = get_json(app.clone(), "/health").await;
+            assert_eq!(status, StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_key_required_except_health() {
+        let app = app(
+            AppState::new(setup_db().await),
+            RateLimiter::new(120),
+            Some("secret".into()),
+        );
+
+        let (status, _) = get_json(app.clone(), "/api/items").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = get_json(app, "/health").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_job_statuses() {
+        let db = setup_db().await;
+        sync_status::record(&db, sync_status::PRICE_SYNC, Some("upstream down".into()))
+            .await
+            .unwrap();
+        // A price sync that last succeeded two hours ago, and an item sync three days ago
+        db.query("UPDATE sync_status:price_sync SET last_success = time::now() - 2h;
+            UPSERT sync_status:item_sync CONTENT { job: 'item_sync', last_success: time::now() - 3d }")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        let jobs = |price_sync_interval: u64| {
+            let state = AppState {
+                admin_key: Some("root".into()),
+                price_sync_interval: std::time::Duration::from_secs(price_sync_interval),
+                ..AppState::new(db.clone())
+            };
+            let app = app(state, RateLimiter::new(120), Some("secret".into()));
+            async move {
+                let response = app
+                    .oneshot(
+                        Request::get("/api/admin/jobs")
+                            .header("authorization", "Bearer root")
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                serde_json::from_slice::<Vec<JobStatus>>(&body).unwrap()
+            }
+        };
+
+        // The API key doesn't open admin endpoints
+        let app = app(
+            AppState {
+                admin_key: Some("root".into()),
+                ..AppState::new(db.clone())
+            },
+            RateLimiter::new(120),
+            Some("secret".into()),
+        );
+        let response = app
+            .oneshot(
+                Request::get("/api/admin/jobs")
+                    .header("authorization", "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let statuses = jobs(gw2shinies_backend::DEFAULT_PRICE_SYNC_INTERVAL_SECS).await;
+        let job = |name: &str| statuses.iter().find(|j| j.job == name).unwrap();
+        let interval = std::time::Duration::from_secs(900);
+        assert_eq!(statuses.len(), sync_status::jobs(interval).len());
+        let price = job(sync_status::PRICE_SYNC);
+        assert_eq!(price.last_error.as_deref(), Some("upstream down"));
+        assert!(price.seconds_since_success.unwrap() >= 7200);
+        // Past four missed 15 minute syncs
+        assert!(price.stale);
+        let item = job(sync_status::ITEM_SYNC);
+        assert!(item.seconds_since_success.unwrap() >= 3 * 86400);
+        assert!(item.stale);
+        // Never ran at all
+        let pruning = job(sync_status::HISTORY_PRUNING);
+        assert_eq!(pruning.last_success, None);
+        assert!(pruning.stale);
+
+        // With hourly syncs two hours is within a few missed runs
+        let statuses = jobs(3600).await;
+        let price = statuses
+            .iter()
+            .find(|j| j.job == sync_status::PRICE_SYNC)
+            .unwrap();
+        assert!(!price.stale);
+    }
+}
@@ -1,9 +1,17 @@
 use clap::Parser;
-use gw2shinies_backend::history_pruning::HistoryPruning;
+use gw2shinies_backend::gem_sync::GemSync;
+use gw2shinies_backend::gw2_api::Gw2Client;
+use gw2shinies_backend::history_pruning::{HistoryPruning, RetentionPolicy};
+use gw2shinies_backend::history_rollup::HistoryRollup;
 use gw2shinies_backend::item_sync::ItemSync;
-use gw2shinies_backend::price_sync::PriceSync;
+use gw2shinies_backend::price_sync::{PriceSync, PriceSyncConfig};
+use gw2shinies_backend::progress::SyncProgress;
+use gw2shinies_backend::recipe_sync::RecipeSync;
 use gw2shinies_backend::{Args, Database};
 
+/// Item definitions rarely change outside game patches.
+const ITEM_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(86400);
+
 #[tokio::main]
 async fn main() {
     // initialize tracing
@@ -11,20 +19,79 @@ async fn main() {
 
     let args = Args::parse();
 
-    let database = Database::init(&args.surreal_uri, &args.surreal_user, &args.surreal_pass)
+    let config = args
+        .database_config()
+        .expect("Failed to read the SurrealDB password file");
+    let database = Database::init(&config)
         .await
         .expect("Failed to initialize database");
 
     let token = tokio_util::sync::CancellationToken::new();
 
     // Orderly Background Startup
-    let item_sync = ItemSync::new(database.db.clone());
-    let price_sync = PriceSync::new(database.db.clone());
-    let history_pruning = HistoryPruning::new(database.db.clone());
+    // Progress from every worker funnels into one log line per event
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel(64);
+    tokio::spawn(async move {
+        while let Some(progress) = progress_rx.recv().await {
+            let SyncProgress {
+                phase,
+                current,
+                total,
+            } = progress;
+            println!("[progress] {:?}: {}/{}", phase, current, total);
+        }
+    });
+
+    // One client for every worker, so they share the GW2 API rate limit
+    // The id list outlives one item sync interval, so a scheduled run reuses the
+    // list the previous run fetched instead of always downloading it again
+    let gw2 = Gw2Client::builder()
+        .lang(args.lang.clone())
+        .item_ids_ttl(ITEM_SYNC_INTERVAL + std::time::Duration::from_secs(3600))
+        .build();
+    let item_sync = ItemSync::new(database.db.clone())
+        .with_client(gw2.clone())
+        .with_progress(progress_tx.clone())
+        .with_dry_run(args.dry_run);
+    let price_sync = PriceSync::new(database.db.clone())
+        .with_client(gw2.clone())
+        .with_progress(progress_tx)
+        .with_config(PriceSyncConfig {
+            chunk_size: args.price_sync_chunk_size,
+            concurrency: args.price_sync_concurrency,
+            dry_run: args.dry_run,
+            ..PriceSyncConfig::default()
+        });
+    let history_pruning = HistoryPruning::new(database.db.clone(), RetentionPolicy::default())
+        .with_dry_run(args.dry_run)
+        .with_index_rebuild(args.prune_rebuild_indexes);
+    let recipe_sync = RecipeSync::new(database.db.clone()).with_client(gw2.clone());
+    let gem_sync = GemSync::new(database.db.clone())
+        .with_client(gw2)
+        .with_dry_run(args.dry_run);
+
+    // Query timings for Prometheus; the API serves its own at /metrics
+    if let Some(addr) = args.metrics_addr {
+        let metrics = axum::Router::new().route(
+            "/metrics",
+            axum::routing::get(|| async { gw2shinies_backend::metrics::render() }),
+        );
+        match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => {
+                println!("Serving metrics on {}", addr);
+                tokio::spawn(async move {
+                    if let Err(e) = axum::serve(listener, metrics).await {
+                        eprintln!("Metrics server failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to bind metrics address {}: {}", addr, e),
+        }
+    }
 
     // 1. Initial Item Sync (Crucial for other tasks)
     println!("Performing initial item sync...");
-    if let Err(e) = item_sync.run_sync().await {
+    if let Err(e) = item_sync.run_startup_sync().await {
         eprintln!("Initial item sync failed: {}", e);
     }
 
@@ -33,7 +100,10 @@ async fn main() {
     let token_periodic = token.clone();
     let handle_periodic = tokio::spawn(async move {
         price_sync_periodic
-            .spawn(std::time::Duration::from_secs(900), token_periodic)
+            .spawn(
+                std::time::Duration::from_secs(args.price_sync_interval_secs),
+                token_periodic,
+            )
             .await;
     });
 
@@ -54,12 +124,39 @@ async fn main() {
             .await;
     });
 
+    let history_rollup = HistoryRollup::new(database.db.clone());
+    let token_rollup = token.clone();
+    let dry_run = args.dry_run;
+    let handle_rollup = tokio::spawn(async move {
+        // Each run rolls up the hour that just ended; it only writes, so a dry run skips it
+        if !dry_run {
+            history_rollup
+                .spawn(std::time::Duration::from_secs(3600), token_rollup)
+                .await;
+        }
+    });
+
     // 3. Keep Item Sync running daily
     let item_sync_worker = item_sync.clone();
     let token_item = token.clone();
     let handle_item = tokio::spawn(async move {
-        item_sync_worker
-            .spawn(std::time::Duration::from_secs(86400), token_item)
+        item_sync_worker.spawn(ITEM_SYNC_INTERVAL, token_item).await;
+    });
+
+    // Recipes rarely change; the price sync keeps craft costs in step with prices
+    let token_recipe = token.clone();
+    let handle_recipe = tokio::spawn(async move {
+        if !dry_run {
+            recipe_sync
+                .spawn(std::time::Duration::from_secs(86400), token_recipe)
+                .await;
+        }
+    });
+
+    let token_gems = token.clone();
+    let handle_gems = tokio::spawn(async move {
+        gem_sync
+            .spawn(std::time::Duration::from_secs(900), token_gems)
             .await;
     });
 
@@ -75,7 +172,13 @@ async fn main() {
         handle_periodic,
         handle_recovery,
         handle_pruning,
-        handle_item
+        handle_rollup,
+        handle_item,
+        handle_recipe,
+        handle_gems
     );
     println!("All workers shut down. Exiting.");
+    if let Err(e) = database.close().await {
+        eprintln!("Failed to close the database session: {}", e);
+    }
 }
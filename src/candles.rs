@@ -0,0 +1,136 @@
+use chrono::{DateTime, DurationRound, Utc};
+
+/// One OHLC candle of the sell price. `volume` is the mean sell quantity listed
+/// during the bucket, since the trading post doesn't publish traded volume.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct Candle {
+    /// Bucket start
+    pub t: DateTime<Utc>,
+    pub open: i64,
+    pub high: i64,
+    pub low: i64,
+    pub close: i64,
+    pub volume: f64,
+    /// History points behind the candle, to weight `volume` when merging
+    #[serde(skip)]
+    pub samples: u64,
+}
+
+impl Candle {
+    /// A single history point as a candle of its own.
+    pub fn from_point(t: DateTime<Utc>, price: i64, quantity: i64) -> Self {
+        Self {
+            t,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: quantity as f64,
+            samples: 1,
+        }
+    }
+
+    fn merge(&mut self, later: &Candle) {
+        self.high = self.high.max(later.high);
+        self.low = self.low.min(later.low);
+        self.close = later.close;
+        let samples = self.samples + later.samples;
+        if samples > 0 {
+            self.volume = (self.volume * self.samples as f64 + later.volume * later.samples as f64)
+                / samples as f64;
+        }
+        self.samples = samples;
+    }
+}
+
+/// Merges chronologically ordered candles (or single points) into buckets of
+/// `width`, aligned to the Unix epoch like SurrealDB's `time::floor`. Buckets
+/// without input are left out.
+pub fn bucket(
+    candles: impl IntoIterator<Item = Candle>,
+    width: chrono::Duration,
+) -> Result<Vec<Candle>, chrono::RoundingError> {
+    let mut buckets: Vec<Candle> = Vec::new();
+    for mut candle in candles {
+        let t = candle.t.duration_trunc(width)?;
+        match buckets.last_mut() {
+            Some(last) if last.t == t => last.merge(&candle),
+            _ => {
+                candle.t = t;
+                buckets.push(candle);
+            }
+        }
+    }
+    Ok(buckets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+            + chrono::Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn test_bucket_ohlc() {
+        let points = [
+            (0, 100, 10),
+            (15, 140, 20),
+            (30, 80, 30),
+            (45, 120, 40),
+            (70, 90, 5),
+        ]
+        .map(|(minutes, price, quantity)| Candle::from_point(at(minutes), price, quantity));
+
+        let candles = bucket(points, chrono::Duration::hours(1)).unwrap();
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[0].t, at(0));
+        assert_eq!(
+            (
+                candles[0].open,
+                candles[0].high,
+                candles[0].low,
+                candles[0].close
+            ),
+            (100, 140, 80, 120)
+        );
+        assert_eq!(candles[0].volume, 25.0);
+        assert_eq!(candles[1].t, at(60));
+        assert_eq!((candles[1].open, candles[1].close), (90, 90));
+    }
+
+    #[test]
+    fn test_bucket_merges_candles_by_samples() {
+        let hourly = [
+            Candle {
+                samples: 3,
+                ..Candle::from_point(at(0), 100, 10)
+            },
+            Candle {
+                high: 300,
+                low: 50,
+                close: 60,
+                samples: 1,
+                ..Candle::from_point(at(60), 200, 30)
+            },
+        ];
+
+        let candles = bucket(hourly, chrono::Duration::hours(6)).unwrap();
+        assert_eq!(candles.len(), 1);
+        assert_eq!(
+            (
+                candles[0].open,
+                candles[0].high,
+                candles[0].low,
+                candles[0].close
+            ),
+            (100, 300, 50, 60)
+        );
+        assert_eq!(candles[0].volume, 15.0);
+        assert_eq!(candles[0].samples, 4);
+    }
+}
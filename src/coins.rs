@@ -0,0 +1,85 @@
+use std::fmt;
+
+/// An amount of coin in copper, the unit every price is stored in. 100 copper
+/// make a silver and 100 silver a gold.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(transparent)]
+pub struct Coins(pub i64);
+
+impl Coins {
+    /// Whole gold pieces of the absolute amount
+    pub fn gold(self) -> u64 {
+        self.0.unsigned_abs() / 10_000
+    }
+
+    /// Silver left over after the gold, 0 to 99
+    pub fn silver(self) -> u64 {
+        self.0.unsigned_abs() / 100 % 100
+    }
+
+    /// Copper left over after the silver, 0 to 99
+    pub fn copper(self) -> u64 {
+        self.0.unsigned_abs() % 100
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl From<i64> for Coins {
+    fn from(copper: i64) -> Self {
+        Self(copper)
+    }
+}
+
+/// `1g 23s 45c`, with a leading `-` for losses. Leading zero units are left
+/// out (`5s 0c`, `0c`), so the largest unit shown is never zero.
+impl fmt::Display for Coins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_negative() {
+            f.write_str("-")?;
+        }
+        if self.gold() > 0 {
+            write!(f, "{}g {}s {}c", self.gold(), self.silver(), self.copper())
+        } else if self.silver() > 0 {
+            write!(f, "{}s {}c", self.silver(), self.copper())
+        } else {
+            write!(f, "{}c", self.copper())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coins_breakdown() {
+        let coins = Coins(12_345);
+        assert_eq!((coins.gold(), coins.silver(), coins.copper()), (1, 23, 45));
+        assert_eq!(coins.to_string(), "1g 23s 45c");
+
+        assert_eq!(Coins(0).to_string(), "0c");
+        assert_eq!(Coins(99).to_string(), "99c");
+        assert_eq!(Coins(100).to_string(), "1s 0c");
+        assert_eq!(Coins(9_999).to_string(), "99s 99c");
+        assert_eq!(Coins(10_000).to_string(), "1g 0s 0c");
+        assert_eq!(Coins(10_001).to_string(), "1g 0s 1c");
+        assert_eq!(Coins(1_234_560_000).to_string(), "123456g 0s 0c");
+    }
+
+    #[test]
+    fn test_coins_negative() {
+        let loss = Coins(-12_345);
+        assert!(loss.is_negative());
+        assert_eq!((loss.gold(), loss.silver(), loss.copper()), (1, 23, 45));
+        assert_eq!(loss.to_string(), "-1g 23s 45c");
+        assert_eq!(Coins(-1).to_string(), "-1c");
+        assert_eq!(Coins(-100).to_string(), "-1s 0c");
+        assert_eq!(Coins(i64::MIN).gold(), 922_337_203_685_477);
+        assert!(!Coins(0).is_negative());
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/// Response of `/v2/commerce/exchange/{coins,gems}`: what you'd receive for the
+/// requested `quantity` and the effective rate.
+#[derive(Debug, Deserialize)]
+pub struct RawExchange {
+    pub coins_per_gem: u32,
+    pub quantity: u64,
+}
+
+/// Current gem/gold exchange rates in both directions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GemExchange {
+    /// Coins paid per gem when converting gold to gems
+    pub coins_per_gem: u32,
+    /// Coins received per gem when converting gems to gold
+    pub coins_per_gem_sold: u32,
+}
+
+impl GemExchange {
+    /// Gems bought per coin, the inverse of `coins_per_gem`.
+    pub fn gems_per_coin(&self) -> f64 {
+        if self.coins_per_gem == 0 {
+            return 0.0;
+        }
+        1.0 / self.coins_per_gem as f64
+    }
+}
+
+/// One recorded gem exchange quote, as stored in `gem_history`.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct GemRate {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub coins_per_gem: u32,
+    pub coins_per_gem_sold: u32,
+    pub gems_per_coin: f64,
+}
+
+impl GemRate {
+    pub fn new(exchange: &GemExchange, timestamp: chrono::DateTime<chrono::Utc>) -> Self {
+        Self {
+            timestamp,
+            coins_per_gem: exchange.coins_per_gem,
+            coins_per_gem_sold: exchange.coins_per_gem_sold,
+            gems_per_coin: exchange.gems_per_coin(),
+        }
+    }
+}
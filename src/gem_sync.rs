@@ -0,0 +1,69 @@
+use crate::exchange::GemRate;
+use crate::gw2_api::Gw2Client;
+use surrealdb::Surreal;
+use surrealdb::engine::any::Any;
+use tokio_util::sync::CancellationToken;
+
+/// Records the gem exchange rate into `gem_history` on every run.
+#[derive(Clone)]
+pub struct GemSync {
+    db: Surreal<Any>,
+    gw2: Gw2Client,
+    dry_run: bool,
+}
+
+impl GemSync {
+    pub fn new(db: Surreal<Any>) -> Self {
+        Self {
+            db,
+            gw2: Gw2Client::new(),
+            dry_run: false,
+        }
+    }
+
+    /// Uses `gw2` instead of a default client, e.g. to share its rate limit.
+    pub fn with_client(mut self, gw2: Gw2Client) -> Self {
+        self.gw2 = gw2;
+        self
+    }
+
+    /// Fetches the rate as usual but doesn't record it.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub async fn run_sync(&self) -> Result<GemRate, Box<dyn std::error::Error>> {
+        let exchange = self.gw2.fetch_gem_exchange().await?;
+        let rate = GemRate::new(&exchange, chrono::Utc::now());
+        if !self.dry_run {
+            self.db
+                .query("CREATE gem_history CONTENT $rate RETURN NONE")
+                .bind(("rate", rate.clone()))
+                .await?
+                .check()?;
+        }
+        println!(
+            "Gem exchange: {} coins per gem bought, {} per gem sold.",
+            rate.coins_per_gem, rate.coins_per_gem_sold
+        );
+        Ok(rate)
+    }
+
+    pub async fn spawn(self, interval_duration: std::time::Duration, token: CancellationToken) {
+        let mut interval = tokio::time::interval(interval_duration);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.run_sync().await {
+                        eprintln!("Gem sync error: {}", e);
+                    }
+                }
+                _ = token.cancelled() => {
+                    println!("Gem sync worker shutting down...");
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -1,135 +1,860 @@
+use crate::history_record::BltcColumns;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// The official API allows roughly 600 requests per minute per IP.
+pub const DEFAULT_REQUESTS_PER_MINUTE: u32 = 600;
+
+/// gw2bltc publishes no limit; this matches the 100ms pacing history recovery
+/// always used.
+pub const DEFAULT_BLTC_REQUESTS_PER_MINUTE: u32 = 600;
+
+/// The API rejects `ids` lists longer than this.
+pub const MAX_IDS_PER_REQUEST: usize = 200;
+
+/// How many chunk requests the sync workers keep in flight at once. The shared
+/// throttle still bounds the overall request rate.
+pub const CHUNK_CONCURRENCY: usize = 4;
+
+/// Splits `ids` into owned chunks of at most `size` (at least 1) ids. Owned
+/// chunks keep the fetch futures built from them `Send`, so sync workers can
+/// run them on spawned tasks.
+pub fn chunk_ids(ids: &[u32], size: usize) -> Vec<Vec<u32>> {
+    ids.chunks(size.max(1)).map(<[u32]>::to_vec).collect()
+}
+
+/// Why a request to the GW2 API (or gw2bltc) failed.
+#[derive(Debug, thiserror::Error)]
+pub enum Gw2Error {
+    /// Transport failure (connect, timeout) or an error status not covered below
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// Still throttled upstream after exhausting retries
+    #[error("rate limited upstream (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<Duration> },
+    /// The body wasn't the JSON shape we expected
+    #[error("failed to decode response: {0}")]
+    Decode(#[from] serde_json::Error),
+    #[error("not found")]
+    NotFound,
+    #[error("unexpected response: {0}")]
+    Unexpected(String),
+    /// Too many consecutive failures; requests are paused for a cooldown
+    #[error("circuit open, GW2 API requests paused")]
+    CircuitOpen,
+}
+
+/// A chunk fetch that failed partway. `partial` holds what the earlier requests
+/// returned, with every id that wasn't fetched added to its `failed` list.
+#[derive(Debug, thiserror::Error)]
+#[error("{source}")]
+pub struct ChunkError<T> {
+    pub partial: T,
+    pub source: Gw2Error,
+}
+
+impl Gw2Error {
+    /// True when upstream answered with an error status, rather than failing to
+    /// answer or sending an unreadable body. Rate limiting is excluded so callers
+    /// still back off.
+    pub fn is_status(&self) -> bool {
+        match self {
+            Gw2Error::Http(e) => e.status().is_some(),
+            Gw2Error::NotFound | Gw2Error::Unexpected(_) => true,
+            Gw2Error::RateLimited { .. } | Gw2Error::Decode(_) | Gw2Error::CircuitOpen => false,
+        }
+    }
+}
+
+/// Maps a final (post-retry) response onto `Gw2Error`.
+fn check_status(response: reqwest::Response) -> Result<reqwest::Response, Gw2Error> {
+    match response.status() {
+        reqwest::StatusCode::NOT_FOUND => Err(Gw2Error::NotFound),
+        reqwest::StatusCode::TOO_MANY_REQUESTS => {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            Err(Gw2Error::RateLimited { retry_after })
+        }
+        status if status.is_success() => Ok(response),
+        status if status.is_client_error() || status.is_server_error() => {
+            Err(response.error_for_status().unwrap_err().into())
+        }
+        status => Err(Gw2Error::Unexpected(format!("status {}", status))),
+    }
+}
+
+async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, Gw2Error> {
+    let body = response.bytes().await?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Longest we honour a gw2bltc `Retry-After` before retrying inline.
+const MAX_BLTC_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How transient failures (timeouts, connection errors, 5xx and 429) are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each subsequent one
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        // Up to one base delay of jitter so parallel workers don't retry in lockstep
+        let jitter_ms = rand::random_range(0..=self.base_delay.as_millis() as u64);
+        backoff + Duration::from_millis(jitter_ms)
+    }
+}
+
+/// Token bucket shared by every clone of a client, so all workers draw from one budget.
+/// Bursts are capped at a tenth of the per-minute budget.
+struct Throttle {
+    state: Mutex<(f64, Instant)>,
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+impl Throttle {
+    fn new(requests_per_minute: u32) -> Self {
+        let per_minute = requests_per_minute.max(1) as f64;
+        let capacity = (per_minute / 10.0).max(1.0);
+        Self {
+            state: Mutex::new((capacity, Instant::now())),
+            capacity,
+            refill_per_sec: per_minute / 60.0,
+        }
+    }
+
+    /// Waits until a request may be sent. Waiters queue on the lock, so they are served in order.
+    async fn acquire(&self) {
+        let mut state = self.state.lock().await;
+        let (tokens, last_refill) = &mut *state;
+
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*last_refill).as_secs_f64() * self.refill_per_sec)
+            .min(self.capacity);
+        *last_refill = now;
+
+        if *tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - *tokens) / self.refill_per_sec);
+            tokio::time::sleep(wait).await;
+            *tokens = 1.0;
+            *last_refill = Instant::now();
+        }
+        *tokens -= 1.0;
+    }
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    /// Set while open; once passed the breaker is half-open
+    open_until: Option<Instant>,
+}
+
+/// Stops calling an API that keeps failing (e.g. during maintenance). After
+/// `threshold` consecutive failures requests fail fast for `cooldown`; after that
+/// the next failure reopens it immediately and a success closes it.
+struct CircuitBreaker {
+    state: std::sync::Mutex<BreakerState>,
+    threshold: u32,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            state: std::sync::Mutex::new(BreakerState::default()),
+            threshold: threshold.max(1),
+            cooldown,
+        }
+    }
+
+    fn check(&self) -> Result<(), Gw2Error> {
+        match self.state.lock().unwrap().open_until {
+            Some(until) if Instant::now() < until => Err(Gw2Error::CircuitOpen),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::default();
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        // A failure while half-open reopens straight away
+        if state.open_until.is_some() || state.consecutive_failures >= self.threshold {
+            eprintln!(
+                "GW2 API circuit opened after {} consecutive failures, pausing for {:?}",
+                state.consecutive_failures, self.cooldown
+            );
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+/// Identifies the scraper to upstream APIs, with a contact URL per GW2 API community norms.
+pub const DEFAULT_USER_AGENT: &str = concat!(
+    "gw2shinies-backend/",
+    env!("CARGO_PKG_VERSION"),
+    " (+",
+    env!("CARGO_PKG_REPOSITORY"),
+    ")"
+);
+
+/// Result of an item chunk fetch.
+#[derive(Debug, Default)]
+pub struct ItemChunk {
+    pub items: Vec<crate::item_definition::ItemDefinition>,
+    /// Requested ids that didn't come back as a valid item (omitted, malformed,
+    /// or the whole chunk was rejected)
+    pub failed: Vec<u32>,
+    /// The subset of `failed` missing from the response altogether, typically
+    /// items removed between the id list fetch and this request
+    pub omitted: Vec<u32>,
+}
+
+impl ItemChunk {
+    pub fn is_partial(&self) -> bool {
+        !self.failed.is_empty()
+    }
+}
+
+/// Result of a price chunk fetch.
+#[derive(Debug, Default)]
+pub struct PriceChunk {
+    pub records: Vec<crate::history_record::HistoryRecord>,
+    /// Requested ids the API returned no price for
+    pub missing: Vec<u32>,
+    /// Ids whose price entry couldn't be parsed or wasn't fetched, worth retrying
+    pub failed: Vec<u32>,
+}
+
+/// Result of a recipe chunk fetch.
+#[derive(Debug, Default)]
+pub struct RecipeChunk {
+    pub recipes: Vec<crate::recipe::Recipe>,
+    /// Requested ids that didn't come back as a usable recipe
+    pub failed: Vec<u32>,
+}
+
+/// Configures a `Gw2Client`. Every setting is optional:
+///
+/// | setting | default |
+/// |---|---|
+/// | base URLs | api.guildwars2.com, www.gw2bltc.com |
+/// | connect timeout | 10s |
+/// | request timeout | 30s (whole request, including the body) |
+/// | user agent | `DEFAULT_USER_AGENT` |
+/// | retry policy | `RetryPolicy::default()` |
+/// | rate limit | `DEFAULT_REQUESTS_PER_MINUTE` |
+/// | gw2bltc rate limit | `DEFAULT_BLTC_REQUESTS_PER_MINUTE` |
+/// | lang | none (API default, English) |
+/// | chunk size | `MAX_IDS_PER_REQUEST` |
+/// | item id cache TTL | 3h |
+/// | gw2bltc columns | `BltcColumns::default()` |
+/// | circuit breaker | open after 5 consecutive failures, 60s cooldown |
+#[derive(Clone, Debug)]
+pub struct Gw2ClientBuilder {
+    gw2_url: String,
+    bltc_url: String,
+    connect_timeout: Duration,
+    request_timeout: Duration,
+    user_agent: String,
+    retry: RetryPolicy,
+    requests_per_minute: u32,
+    bltc_requests_per_minute: u32,
+    lang: Option<String>,
+    chunk_size: usize,
+    item_ids_ttl: Duration,
+    bltc_columns: BltcColumns,
+    breaker_threshold: u32,
+    breaker_cooldown: Duration,
+}
+
+impl Default for Gw2ClientBuilder {
+    fn default() -> Self {
+        Self {
+            gw2_url: "https://api.guildwars2.com".to_string(),
+            bltc_url: "https://www.gw2bltc.com".to_string(),
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(30),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            retry: RetryPolicy::default(),
+            requests_per_minute: DEFAULT_REQUESTS_PER_MINUTE,
+            bltc_requests_per_minute: DEFAULT_BLTC_REQUESTS_PER_MINUTE,
+            lang: None,
+            chunk_size: MAX_IDS_PER_REQUEST,
+            item_ids_ttl: Duration::from_secs(3 * 3600),
+            bltc_columns: BltcColumns::default(),
+            breaker_threshold: 5,
+            breaker_cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Gw2ClientBuilder {
+    /// Points the client at other hosts, e.g. a self-hosted API mirror, a caching
+    /// proxy or a mock server. URLs are bases without a trailing slash.
+    pub fn base_urls(mut self, gw2_url: String, bltc_url: String) -> Self {
+        self.gw2_url = gw2_url;
+        self.bltc_url = bltc_url;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Caps outgoing GW2 API requests across all clones of the built client.
+    pub fn rate_limit(mut self, requests_per_minute: u32) -> Self {
+        self.requests_per_minute = requests_per_minute;
+        self
+    }
+
+    /// Caps gw2bltc requests separately, so history recovery doesn't spend the
+    /// official API's budget.
+    pub fn bltc_rate_limit(mut self, requests_per_minute: u32) -> Self {
+        self.bltc_requests_per_minute = requests_per_minute;
+        self
+    }
+
+    /// Requests localized item names (en, de, fr, es, zh).
+    pub fn lang(mut self, lang: Option<String>) -> Self {
+        self.lang = lang;
+        self
+    }
+
+    /// Ids per upstream request, clamped to `1..=MAX_IDS_PER_REQUEST`.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.clamp(1, MAX_IDS_PER_REQUEST);
+        self
+    }
+
+    /// How long `fetch_all_item_ids` reuses the last id list. The list only
+    /// changes with game patches. Set it above the item sync interval, or every
+    /// scheduled sync misses the cache.
+    pub fn item_ids_ttl(mut self, ttl: Duration) -> Self {
+        self.item_ids_ttl = ttl;
+        self
+    }
+
+    /// Column layout of gw2bltc chart rows, in case the site changes it.
+    pub fn bltc_columns(mut self, columns: BltcColumns) -> Self {
+        self.bltc_columns = columns;
+        self
+    }
+
+    /// Fail fast for `cooldown` after `threshold` consecutive failed GW2 API
+    /// requests (after retries). Shared by all clones of the built client.
+    pub fn circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.breaker_threshold = threshold;
+        self.breaker_cooldown = cooldown;
+        self
+    }
+
+    pub fn build(self) -> Gw2Client {
+        let client = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .timeout(self.request_timeout)
+            .user_agent(&self.user_agent)
+            // Bulk /v2/items bodies shrink several-fold compressed
+            .gzip(true)
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Gw2Client {
+            client,
+            gw2_url: self.gw2_url,
+            bltc_url: self.bltc_url,
+            retry: self.retry,
+            throttle: Arc::new(Throttle::new(self.requests_per_minute)),
+            bltc_throttle: Arc::new(Throttle::new(self.bltc_requests_per_minute)),
+            lang: self.lang,
+            chunk_size: self.chunk_size,
+            item_ids_ttl: self.item_ids_ttl,
+            item_ids: Arc::new(Mutex::new(None)),
+            bltc_columns: self.bltc_columns,
+            breaker: Arc::new(CircuitBreaker::new(
+                self.breaker_threshold,
+                self.breaker_cooldown,
+            )),
+        }
+    }
+}
+
+/// Last fetched id list and when it was fetched.
+type CachedIds = (Instant, Vec<u32>);
+
 #[derive(Clone)]
 pub struct Gw2Client {
     client: reqwest::Client,
     gw2_url: String,
     bltc_url: String,
+    retry: RetryPolicy,
+    throttle: Arc<Throttle>,
+    bltc_throttle: Arc<Throttle>,
+    lang: Option<String>,
+    chunk_size: usize,
+    item_ids_ttl: Duration,
+    item_ids: Arc<Mutex<Option<CachedIds>>>,
+    bltc_columns: BltcColumns,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl Default for Gw2Client {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Gw2Client {
+    /// A client with every default; see `Gw2ClientBuilder` to customize.
     pub fn new() -> Self {
-        Self {
-            client: reqwest::Client::new(),
-            gw2_url: "https://api.guildwars2.com".to_string(),
-            bltc_url: "https://www.gw2bltc.com".to_string(),
-        }
+        Self::builder().build()
+    }
+
+    pub fn builder() -> Gw2ClientBuilder {
+        Gw2ClientBuilder::default()
     }
 
-    #[cfg(test)]
+    /// Shorthand for a default client against other hosts; see `Gw2ClientBuilder::base_urls`.
     pub fn with_urls(gw2_url: String, bltc_url: String) -> Self {
-        Self {
-            client: reqwest::Client::new(),
-            gw2_url,
-            bltc_url,
+        Self::builder().base_urls(gw2_url, bltc_url).build()
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Sends an idempotent GET, retrying timeouts, connection errors, 5xx and 429
+    /// with exponential backoff. Non-retriable responses are returned as-is.
+    async fn send_with_retry(&self, url: &str) -> Result<reqwest::Response, Gw2Error> {
+        let mut attempt = 0;
+        loop {
+            self.throttle.acquire().await;
+            let retriable = match self.client.get(url).send().await {
+                Ok(response)
+                    if response.status().is_server_error()
+                        || response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS =>
+                {
+                    if attempt >= self.retry.max_retries {
+                        return Ok(response);
+                    }
+                    response.status().to_string()
+                }
+                Ok(response) => return Ok(response),
+                Err(e)
+                    if (e.is_timeout() || e.is_connect()) && attempt < self.retry.max_retries =>
+                {
+                    e.to_string()
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            let delay = self.retry.delay(attempt);
+            attempt += 1;
+            eprintln!(
+                "GW2 request to {} failed ({}), retry {}/{} in {:?}",
+                url, retriable, attempt, self.retry.max_retries, delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, Gw2Error> {
+        self.breaker.check()?;
+        let result = self.send_with_retry(url).await;
+        match &result {
+            Ok(response)
+                if !response.status().is_server_error()
+                    && response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS =>
+            {
+                self.breaker.record_success()
+            }
+            _ => self.breaker.record_failure(),
         }
+        let response = check_status(result?)?;
+        decode(response).await
+    }
+
+    /// Returns every item id, reusing the cached list while it is younger than the TTL.
+    pub async fn fetch_all_item_ids(&self) -> Result<Vec<u32>, Gw2Error> {
+        self.item_ids(false).await
+    }
+
+    /// Like `fetch_all_item_ids`, but always downloads a fresh list.
+    pub async fn refresh_all_item_ids(&self) -> Result<Vec<u32>, Gw2Error> {
+        self.item_ids(true).await
     }
 
-    pub async fn fetch_all_item_ids(&self) -> Result<Vec<u32>, reqwest::Error> {
+    async fn item_ids(&self, force_refresh: bool) -> Result<Vec<u32>, Gw2Error> {
+        // Held across the fetch so concurrent callers wait for one download
+        let mut cache = self.item_ids.lock().await;
+        if !force_refresh
+            && let Some((fetched_at, ids)) = &*cache
+            && fetched_at.elapsed() < self.item_ids_ttl
+        {
+            return Ok(ids.clone());
+        }
+
         let url = format!("{}/v2/items", self.gw2_url);
-        let ids = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .json::<Vec<u32>>()
-            .await?;
+        let ids: Vec<u32> = self.get_json(&url).await?;
+        *cache = Some((Instant::now(), ids.clone()));
         Ok(ids)
     }
 
-    pub async fn fetch_items_chunk(
-        &self,
-        ids: &[u32],
-    ) -> Result<Vec<crate::item_definition::ItemDefinition>, reqwest::Error> {
-        if ids.is_empty() {
-            return Ok(vec![]);
+    /// Fetches item definitions for `ids`, tolerating bad items: malformed entries
+    /// are skipped and an error status yields an empty chunk, with the affected
+    /// ids listed in `ItemChunk::failed`.
+    /// Oversized slices are split into requests of at most `chunk_size` ids; if
+    /// one of those fails, the items fetched so far come back in the error.
+    pub async fn fetch_items_chunk(&self, ids: &[u32]) -> Result<ItemChunk, ChunkError<ItemChunk>> {
+        let mut result = ItemChunk::default();
+        for (n, request) in ids.chunks(self.chunk_size).enumerate() {
+            match self.fetch_items_request(request).await {
+                Ok(chunk) => {
+                    result.items.extend(chunk.items);
+                    result.failed.extend(chunk.failed);
+                    result.omitted.extend(chunk.omitted);
+                }
+                Err(source) => {
+                    result.failed.extend_from_slice(&ids[n * self.chunk_size..]);
+                    return Err(ChunkError {
+                        partial: result,
+                        source,
+                    });
+                }
+            }
         }
+        Ok(result)
+    }
+
+    async fn fetch_items_request(&self, ids: &[u32]) -> Result<ItemChunk, Gw2Error> {
         let ids_str = ids
             .iter()
             .map(|id| id.to_string())
             .collect::<Vec<String>>()
             .join(",");
-        let url = format!("{}/v2/items?ids={}", self.gw2_url, ids_str);
-        let items = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .json::<Vec<crate::item_definition::RawItem>>()
-            .await?;
+        let mut url = format!("{}/v2/items?ids={}", self.gw2_url, ids_str);
+        if let Some(lang) = &self.lang {
+            url.push_str(&format!("&lang={}", lang));
+        }
+        let values: Vec<serde_json::Value> = match self.get_json(&url).await {
+            Ok(values) => values,
+            Err(e) if e.is_status() => {
+                eprintln!("Item chunk of {} ids rejected: {}", ids.len(), e);
+                return Ok(ItemChunk {
+                    items: vec![],
+                    failed: ids.to_vec(),
+                    omitted: vec![],
+                });
+            }
+            Err(e) => return Err(e),
+        };
+
+        let returned: std::collections::HashSet<u64> = values
+            .iter()
+            .filter_map(|v| v.get("id")?.as_u64())
+            .collect();
+        let omitted: Vec<u32> = ids
+            .iter()
+            .copied()
+            .filter(|id| !returned.contains(&(*id as u64)))
+            .collect();
+
+        // Parse items one by one so a single bad entry doesn't sink the chunk
+        let mut items = Vec::with_capacity(values.len());
+        for value in values {
+            let id = value.get("id").and_then(|v| v.as_u64());
+            match serde_json::from_value::<crate::item_definition::RawItem>(value) {
+                Ok(raw) => items.push(crate::item_definition::ItemDefinition::from(raw)),
+                Err(e) => eprintln!("Skipping malformed item {:?}: {}", id, e),
+            }
+        }
+
+        let parsed: std::collections::HashSet<u32> =
+            items.iter().map(|i| i.gw2_id as u32).collect();
+        let failed = ids
+            .iter()
+            .copied()
+            .filter(|id| !parsed.contains(id))
+            .collect();
 
-        Ok(items.into_iter().map(|i| i.into()).collect())
+        Ok(ItemChunk {
+            items,
+            failed,
+            omitted,
+        })
     }
 
-    pub async fn fetch_all_price_ids(&self) -> Result<Vec<u32>, reqwest::Error> {
+    pub async fn fetch_all_recipe_ids(&self) -> Result<Vec<u32>, Gw2Error> {
+        let url = format!("{}/v2/recipes", self.gw2_url);
+        self.get_json(&url).await
+    }
+
+    /// Fetches recipes for `ids`. Malformed entries and recipes that can't be
+    /// priced (see `Recipe::from_raw`) are skipped and listed in
+    /// `RecipeChunk::failed`. Oversized slices are split into requests of at
+    /// most `chunk_size` ids.
+    pub async fn fetch_recipes_chunk(&self, ids: &[u32]) -> Result<RecipeChunk, Gw2Error> {
+        let mut result = RecipeChunk::default();
+        for ids in ids.chunks(self.chunk_size) {
+            let ids_str = ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(",");
+            let url = format!("{}/v2/recipes?ids={}", self.gw2_url, ids_str);
+            let values: Vec<serde_json::Value> = match self.get_json(&url).await {
+                Ok(values) => values,
+                Err(Gw2Error::NotFound) => vec![],
+                Err(e) => return Err(e),
+            };
+
+            // Parse recipes one by one so a single bad entry doesn't sink the chunk
+            let mut parsed = std::collections::HashSet::new();
+            for value in values {
+                let id = value.get("id").and_then(|v| v.as_u64());
+                match serde_json::from_value::<crate::recipe::RawRecipe>(value) {
+                    Ok(raw) => {
+                        if let Some(recipe) = crate::recipe::Recipe::from_raw(raw) {
+                            parsed.insert(recipe.gw2_id);
+                            result.recipes.push(recipe);
+                        }
+                    }
+                    Err(e) => eprintln!("Skipping malformed recipe {:?}: {}", id, e),
+                }
+            }
+            result
+                .failed
+                .extend(ids.iter().copied().filter(|id| !parsed.contains(id)));
+        }
+        Ok(result)
+    }
+
+    pub async fn fetch_all_price_ids(&self) -> Result<Vec<u32>, Gw2Error> {
         let url = format!("{}/v2/commerce/prices", self.gw2_url);
-        let ids = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .json::<Vec<u32>>()
-            .await?;
-        Ok(ids)
+        self.get_json(&url).await
     }
 
+    /// Fetches current prices for `ids`. Ids without a trading-post listing are
+    /// omitted by the API and reported in `PriceChunk::missing`. Oversized slices
+    /// are split into requests of at most `chunk_size` ids; if one of those
+    /// fails, the prices fetched so far come back in the error.
     pub async fn fetch_prices_chunk(
         &self,
         ids: &[u32],
-    ) -> Result<Vec<crate::history_record::HistoryRecord>, reqwest::Error> {
-        if ids.is_empty() {
-            return Ok(vec![]);
+    ) -> Result<PriceChunk, ChunkError<PriceChunk>> {
+        let mut result = PriceChunk::default();
+        for (n, request) in ids.chunks(self.chunk_size).enumerate() {
+            match self.fetch_prices_request(request).await {
+                Ok(chunk) => {
+                    result.records.extend(chunk.records);
+                    result.missing.extend(chunk.missing);
+                    result.failed.extend(chunk.failed);
+                }
+                Err(source) => {
+                    result.failed.extend_from_slice(&ids[n * self.chunk_size..]);
+                    return Err(ChunkError {
+                        partial: result,
+                        source,
+                    });
+                }
+            }
         }
+        Ok(result)
+    }
+
+    async fn fetch_prices_request(&self, ids: &[u32]) -> Result<PriceChunk, Gw2Error> {
         let ids_str = ids
             .iter()
             .map(|id| id.to_string())
             .collect::<Vec<String>>()
             .join(",");
         let url = format!("{}/v2/commerce/prices?ids={}", self.gw2_url, ids_str);
-        let prices = self
-            .client
-            .get(url)
-            .send()
-            .await?
-            .json::<Vec<crate::history_record::RawPrice>>()
-            .await?;
+        let values: Vec<serde_json::Value> = match self.get_json(&url).await {
+            Ok(values) => values,
+            // The API answers 404 when none of the requested ids are listed
+            Err(Gw2Error::NotFound) => vec![],
+            Err(e) => return Err(e),
+        };
 
+        // Parse prices one by one so a single bad entry doesn't sink the chunk
         let now = chrono::Utc::now();
-        Ok(prices
-            .into_iter()
-            .map(|p| crate::history_record::HistoryRecord::from_raw(p, now))
-            .collect())
+        let mut chunk = PriceChunk::default();
+        let mut returned = std::collections::HashSet::new();
+        for value in values {
+            let id = value.get("id").and_then(|v| v.as_u64()).map(|id| id as u32);
+            if let Some(id) = id {
+                returned.insert(id);
+            }
+            match serde_json::from_value::<crate::history_record::RawPrice>(value) {
+                Ok(raw) => chunk
+                    .records
+                    .push(crate::history_record::HistoryRecord::from_raw(raw, now)),
+                Err(e) => {
+                    eprintln!("Skipping malformed price {:?}: {}", id, e);
+                    chunk.failed.extend(id);
+                }
+            }
+        }
+        chunk.missing = ids
+            .iter()
+            .copied()
+            .filter(|id| !returned.contains(id))
+            .collect();
+
+        Ok(chunk)
     }
 
-    pub async fn fetch_item_history(
+    /// Fetches one item's current price, or `None` if it has no trading-post listing.
+    pub async fn fetch_price(
         &self,
         id: u32,
-    ) -> Result<Vec<crate::history_record::HistoryRecord>, reqwest::Error> {
-        let url = format!("{}/api/tp/chart/{}", self.bltc_url, id);
-        let response = self.client.get(url).send().await?;
+    ) -> Result<Option<crate::history_record::HistoryRecord>, Gw2Error> {
+        let url = format!("{}/v2/commerce/prices/{}", self.gw2_url, id);
+        match self.get_json(&url).await {
+            Ok(raw) => Ok(Some(crate::history_record::HistoryRecord::from_raw(
+                raw,
+                chrono::Utc::now(),
+            ))),
+            Err(Gw2Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
 
-        if response.status() == reqwest::StatusCode::NOT_FOUND {
-            return Ok(vec![]);
+    /// Fetches the full buy/sell order books for `ids`. Unlisted ids are omitted.
+    pub async fn fetch_listings_chunk(
+        &self,
+        ids: &[u32],
+    ) -> Result<Vec<crate::listing::RawListing>, Gw2Error> {
+        let mut listings = Vec::with_capacity(ids.len());
+        for ids in ids.chunks(self.chunk_size) {
+            let ids_str = ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<String>>()
+                .join(",");
+            let url = format!("{}/v2/commerce/listings?ids={}", self.gw2_url, ids_str);
+            match self.get_json::<Vec<crate::listing::RawListing>>(&url).await {
+                Ok(chunk) => listings.extend(chunk),
+                Err(Gw2Error::NotFound) => {}
+                Err(e) => return Err(e),
+            }
         }
+        Ok(listings)
+    }
 
-        let raw_history = response.error_for_status()?.json::<Vec<Vec<i64>>>().await?;
+    /// Fetches the gem exchange rates. Rates depend on the amount traded, so
+    /// they are quoted for 100 gold and 100 gems respectively.
+    pub async fn fetch_gem_exchange(&self) -> Result<crate::exchange::GemExchange, Gw2Error> {
+        let coins_url = format!(
+            "{}/v2/commerce/exchange/coins?quantity=1000000",
+            self.gw2_url
+        );
+        let gems_url = format!("{}/v2/commerce/exchange/gems?quantity=100", self.gw2_url);
+        let (buy, sell): (crate::exchange::RawExchange, crate::exchange::RawExchange) =
+            tokio::try_join!(self.get_json(&coins_url), self.get_json(&gems_url))?;
 
-        Ok(raw_history
-            .into_iter()
-            .filter_map(|data| crate::history_record::HistoryRecord::from_bltc(id, &data))
-            .collect())
+        Ok(crate::exchange::GemExchange {
+            coins_per_gem: buy.coins_per_gem,
+            coins_per_gem_sold: sell.coins_per_gem,
+        })
+    }
+
+    pub async fn fetch_item_history(
+        &self,
+        id: u32,
+    ) -> Result<Vec<crate::history_record::HistoryRecord>, Gw2Error> {
+        let url = format!("{}/api/tp/chart/{}", self.bltc_url, id);
+        let mut retried = false;
+        let response = loop {
+            self.bltc_throttle.acquire().await;
+            match check_status(self.client.get(&url).send().await?) {
+                Ok(response) => break response,
+                // bltc has no chart for items that were never listed
+                Err(Gw2Error::NotFound) => return Ok(vec![]),
+                // Wait out one 429 (capped); a second one is left to the caller
+                Err(Gw2Error::RateLimited { retry_after }) if !retried => {
+                    let wait = retry_after
+                        .unwrap_or(self.retry.base_delay)
+                        .min(MAX_BLTC_RETRY_AFTER);
+                    eprintln!("gw2bltc rate limited item {}, retrying in {:?}", id, wait);
+                    tokio::time::sleep(wait).await;
+                    retried = true;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        let raw_history: Vec<Vec<i64>> = decode(response).await?;
+
+        let history: Vec<_> = raw_history
+            .iter()
+            .filter_map(|data| {
+                crate::history_record::HistoryRecord::from_bltc_with(id, data, &self.bltc_columns)
+            })
+            .collect();
+        if history.is_empty() && !raw_history.is_empty() {
+            // Every row was rejected, most likely because the column layout changed
+            return Err(Gw2Error::Unexpected(format!(
+                "no usable gw2bltc rows for item {} (row length {})",
+                id,
+                raw_history[0].len()
+            )));
+        }
+        Ok(history)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use wiremock::matchers::{method, path};
+    use wiremock::matchers::{header, method, path, query_param};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
     async fn test_fetch_all_item_ids() {
         let server = MockServer::start().await;
         let mock_ids = vec![1, 2, 3];
-        
+
         Mock::given(method("GET"))
             .and(path("/v2/items"))
             .respond_with(ResponseTemplate::new(200).set_body_json(&mock_ids))
@@ -138,17 +863,204 @@ mod tests {
 
         let client = Gw2Client::with_urls(server.uri(), "".to_string());
         let ids = client.fetch_all_item_ids().await.unwrap();
-        
+
         assert_eq!(ids, mock_ids);
     }
 
+    fn fast_retry(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_server_errors() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![7]))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .retry_policy(fast_retry(3))
+            .build();
+        let ids = client.fetch_all_price_ids().await.unwrap();
+
+        assert_eq!(ids, vec![7]);
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .retry_policy(fast_retry(2))
+            .build();
+        let err = client.fetch_all_item_ids().await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            Gw2Error::Http(e) if e.status() == Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR)
+        ));
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .retry_policy(fast_retry(3))
+            .build();
+        assert!(client.fetch_all_item_ids().await.is_err());
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle_paces_after_burst() {
+        // 60/min: burst of 6, then one token per second
+        let throttle = Throttle::new(60);
+        let start = Instant::now();
+
+        for _ in 0..6 {
+            throttle.acquire().await;
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        throttle.acquire().await;
+        throttle.acquire().await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(2) && elapsed < Duration::from_millis(2100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bltc_has_its_own_throttle() {
+        let client = Gw2Client::builder().rate_limit(60).build();
+        let start = Instant::now();
+        // Spend the whole GW2 API burst; gw2bltc requests still go out at once
+        for _ in 0..6 {
+            client.throttle.acquire().await;
+        }
+        client.bltc_throttle.acquire().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_fires() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/tp/chart/1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(Vec::<Vec<i64>>::new())
+                    .set_delay(Duration::from_secs(5)),
+            )
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls("".to_string(), server.uri())
+            .request_timeout(Duration::from_millis(100))
+            .build();
+        let err = client.fetch_item_history(1).await.unwrap_err();
+
+        assert!(matches!(err, Gw2Error::Http(e) if e.is_timeout()));
+    }
+
+    #[tokio::test]
+    async fn test_sends_user_agent() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(header("user-agent", DEFAULT_USER_AGENT))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(header("user-agent", "my-mirror/1.0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![2]))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+        assert_eq!(client.fetch_all_item_ids().await.unwrap(), vec![1]);
+        assert!(DEFAULT_USER_AGENT.starts_with("gw2shinies-backend/"));
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .user_agent("my-mirror/1.0")
+            .build();
+        assert_eq!(client.fetch_all_price_ids().await.unwrap(), vec![2]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_items_chunk_lang() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(query_param("ids", "1"))
+            .and(query_param("lang", "de"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(vec![serde_json::json!({
+                    "id": 1,
+                    "name": "Großes Schwert",
+                    "type": "Weapon",
+                    "level": 80,
+                    "rarity": "Exotic",
+                    "vendor_value": 100,
+                    "flags": [],
+                    "game_types": [],
+                    "restrictions": [],
+                    "chat_link": "[&AgH1AAA=]"
+                })]),
+            )
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .lang(Some("de".to_string()))
+            .build();
+        let chunk = client.fetch_items_chunk(&[1]).await.unwrap();
+
+        assert_eq!(chunk.items[0].name, "Großes Schwert");
+    }
+
     #[tokio::test]
     async fn test_fetch_item_history() {
         let server = MockServer::start().await;
         let item_id = 19684;
-        let mock_data = vec![
-            vec![1735689600, 60, 50, 200, 100],
-        ];
+        let mock_data = vec![vec![1735689600, 60, 50, 200, 100]];
 
         Mock::given(method("GET"))
             .and(path(format!("/api/tp/chart/{}", item_id)))
@@ -158,7 +1070,7 @@ mod tests {
 
         let client = Gw2Client::with_urls("".to_string(), server.uri());
         let history = client.fetch_item_history(item_id).await.unwrap();
-        
+
         assert_eq!(history.len(), 1);
         assert_eq!(history[0].sell_price, 60);
     }
@@ -176,7 +1088,546 @@ mod tests {
 
         let client = Gw2Client::with_urls("".to_string(), server.uri());
         let history = client.fetch_item_history(item_id).await.unwrap();
-        
+
         assert!(history.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_error_variants() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(query_param("ids", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "30"))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .retry_policy(fast_retry(0))
+            .build();
+
+        assert!(matches!(
+            client.fetch_all_item_ids().await,
+            Err(Gw2Error::NotFound)
+        ));
+        assert!(matches!(
+            client.fetch_prices_chunk(&[1]).await,
+            Err(ChunkError {
+                source: Gw2Error::Decode(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            client.fetch_all_price_ids().await,
+            Err(Gw2Error::RateLimited { retry_after: Some(d) }) if d == Duration::from_secs(30)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_prices_chunk_reports_missing_ids() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(query_param("ids", "1,2,3"))
+            .respond_with(
+                ResponseTemplate::new(206).set_body_json(vec![serde_json::json!({
+                    "id": 2,
+                    "buys": { "quantity": 10, "unit_price": 5 },
+                    "sells": { "quantity": 20, "unit_price": 8 }
+                })]),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(query_param("ids", "4,5"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+
+        let chunk = client.fetch_prices_chunk(&[1, 2, 3]).await.unwrap();
+        assert_eq!(chunk.records.len(), 1);
+        assert_eq!(chunk.records[0].sell_price, 8);
+        assert_eq!(chunk.missing, vec![1, 3]);
+
+        let chunk = client.fetch_prices_chunk(&[4, 5]).await.unwrap();
+        assert!(chunk.records.is_empty());
+        assert_eq!(chunk.missing, vec![4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_items_chunk_skips_malformed_items() {
+        let server = MockServer::start().await;
+        let item = |id: u32| {
+            serde_json::json!({
+                "id": id,
+                "name": format!("Item {id}"),
+                "type": "Trophy",
+                "level": 0,
+                "rarity": "Basic",
+                "vendor_value": 0,
+                "flags": [],
+                "game_types": [],
+                "restrictions": [],
+                "chat_link": ""
+            })
+        };
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(query_param("ids", "1,2,3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![
+                item(1),
+                serde_json::json!({ "id": 2, "name": "No type or level" }),
+                item(3),
+            ]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(query_param("ids", "4,5"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .retry_policy(fast_retry(0))
+            .build();
+
+        let chunk = client.fetch_items_chunk(&[1, 2, 3]).await.unwrap();
+        let ids: Vec<i32> = chunk.items.iter().map(|i| i.gw2_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(chunk.failed, vec![2]);
+        assert!(chunk.is_partial());
+        assert!(chunk.omitted.is_empty());
+
+        let chunk = client.fetch_items_chunk(&[4, 5]).await.unwrap();
+        assert!(chunk.items.is_empty());
+        assert_eq!(chunk.failed, vec![4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_listings_chunk() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/listings"))
+            .and(query_param("ids", "19684"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(vec![serde_json::json!({
+                    "id": 19684,
+                    "buys": [
+                        { "listings": 3, "unit_price": 120, "quantity": 500 },
+                        { "listings": 1, "unit_price": 119, "quantity": 250 }
+                    ],
+                    "sells": [
+                        { "listings": 2, "unit_price": 135, "quantity": 400 }
+                    ]
+                })]),
+            )
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+        let listings = client.fetch_listings_chunk(&[19684]).await.unwrap();
+
+        assert_eq!(listings.len(), 1);
+        assert_eq!(listings[0].id, 19684);
+        assert_eq!(listings[0].buys.len(), 2);
+        assert_eq!(
+            listings[0].sells[0],
+            crate::listing::ListingTier {
+                unit_price: 135,
+                listings: 2,
+                quantity: 400
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_recipes_chunk() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/recipes"))
+            .and(query_param("ids", "1,2,3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {
+                    "id": 1,
+                    "output_item_id": 100,
+                    "output_item_count": 5,
+                    "ingredients": [{ "type": "Item", "id": 10, "count": 2 }]
+                },
+                {
+                    "id": 2,
+                    "output_item_id": 200,
+                    "output_item_count": 1,
+                    "ingredients": [{ "type": "GuildUpgrade", "id": 20, "count": 1 }]
+                },
+                { "id": 3, "output_item_id": "broken" }
+            ])))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+        let chunk = client.fetch_recipes_chunk(&[1, 2, 3]).await.unwrap();
+
+        assert_eq!(chunk.recipes.len(), 1);
+        assert_eq!(chunk.recipes[0].output_item_count, 5);
+        assert_eq!(chunk.failed, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_gem_exchange() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/exchange/coins"))
+            .and(query_param("quantity", "1000000"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "coins_per_gem": 2500, "quantity": 400 })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/exchange/gems"))
+            .and(query_param("quantity", "100"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(
+                    serde_json::json!({ "coins_per_gem": 1900, "quantity": 190000 }),
+                ),
+            )
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+        let exchange = client.fetch_gem_exchange().await.unwrap();
+
+        assert_eq!(exchange.coins_per_gem, 2500);
+        assert_eq!(exchange.coins_per_gem_sold, 1900);
+        assert_eq!(exchange.gems_per_coin(), 0.0004);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_chunks_are_split() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Vec::<u32>::new()))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+        let ids: Vec<u32> = (1..=250).collect();
+        let chunk = client.fetch_prices_chunk(&ids).await.unwrap();
+        assert_eq!(chunk.missing.len(), 250);
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        let sizes: Vec<usize> = requests
+            .iter()
+            .map(|r| {
+                let (_, ids) = r.url.query_pairs().find(|(k, _)| k == "ids").unwrap();
+                ids.split(',').count()
+            })
+            .collect();
+        assert_eq!(sizes, vec![200, 50]);
+
+        assert_eq!(
+            Gw2Client::builder().chunk_size(1000).build().chunk_size(),
+            MAX_IDS_PER_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decodes_gzip_bodies() {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+
+        let server = MockServer::start().await;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"[1,2,3]").unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(header("accept-encoding", "gzip"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .insert_header("content-type", "application/json")
+                    .set_body_bytes(encoder.finish().unwrap()),
+            )
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+        assert_eq!(client.fetch_all_item_ids().await.unwrap(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_builder_applies_every_setting() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(query_param("lang", "fr"))
+            .and(header("user-agent", "custom/2.0"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(query_param("lang", "fr"))
+            .and(header("user-agent", "custom/2.0"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Vec::<u32>::new()))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .connect_timeout(Duration::from_secs(1))
+            .request_timeout(Duration::from_secs(5))
+            .user_agent("custom/2.0")
+            .retry_policy(fast_retry(1))
+            .rate_limit(6000)
+            .lang(Some("fr".to_string()))
+            .chunk_size(2)
+            .build();
+        assert_eq!(client.chunk_size(), 2);
+
+        // 3 ids at chunk size 2 is two requests, the first retried once after a 503
+        let chunk = client.fetch_items_chunk(&[1, 2, 3]).await.unwrap();
+        assert_eq!(chunk.failed, vec![1, 2, 3]);
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_item_ids_are_cached() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2]))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+        assert_eq!(client.fetch_all_item_ids().await.unwrap(), vec![1, 2]);
+        assert_eq!(
+            client.clone().fetch_all_item_ids().await.unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+        client.refresh_all_item_ids().await.unwrap();
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+
+        // An expired cache is refetched
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .item_ids_ttl(Duration::ZERO)
+            .build();
+        client.fetch_all_item_ids().await.unwrap();
+        client.fetch_all_item_ids().await.unwrap();
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_item_history_waits_out_429() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/api/tp/chart/1"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "1"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/tp/chart/1"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(vec![vec![1735689600, 60, 50, 200, 100]]),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/tp/chart/2"))
+            .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "0"))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls("".to_string(), server.uri());
+        let start = std::time::Instant::now();
+        let history = client.fetch_item_history(1).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+
+        // Only one retry; a persistent 429 is surfaced
+        assert!(matches!(
+            client.fetch_item_history(2).await,
+            Err(Gw2Error::RateLimited { .. })
+        ));
+        assert_eq!(server.received_requests().await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_prices_chunk_reports_malformed_ids() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(query_param("ids", "1,2,3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![
+                serde_json::json!({
+                    "id": 1,
+                    "buys": { "quantity": 10, "unit_price": 5 },
+                    "sells": { "quantity": 20, "unit_price": 8 }
+                }),
+                serde_json::json!({ "id": 2, "buys": "oops" }),
+                serde_json::json!({
+                    "id": 3,
+                    "buys": { "quantity": 1, "unit_price": 2 },
+                    "sells": { "quantity": 3, "unit_price": 4 }
+                }),
+            ]))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+        let chunk = client.fetch_prices_chunk(&[1, 2, 3]).await.unwrap();
+
+        assert_eq!(chunk.records.len(), 2);
+        assert_eq!(chunk.failed, vec![2]);
+        assert!(chunk.missing.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_request_keeps_earlier_results() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(query_param("ids", "1"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(vec![serde_json::json!({
+                    "id": 1,
+                    "buys": { "quantity": 10, "unit_price": 5 },
+                    "sells": { "quantity": 20, "unit_price": 8 }
+                })]),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(query_param("ids", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .chunk_size(1)
+            .build();
+        let err = client.fetch_prices_chunk(&[1, 2, 3]).await.unwrap_err();
+
+        assert!(matches!(err.source, Gw2Error::Decode(_)));
+        assert_eq!(err.partial.records.len(), 1);
+        assert_eq!(err.partial.failed, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_price() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices/19684"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": 19684,
+                "buys": { "quantity": 100, "unit_price": 50 },
+                "sells": { "quantity": 200, "unit_price": 60 }
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices/1"))
+            .respond_with(
+                ResponseTemplate::new(404)
+                    .set_body_json(serde_json::json!({ "text": "no such id" })),
+            )
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::with_urls(server.uri(), "".to_string());
+
+        let record = client.fetch_price(19684).await.unwrap().unwrap();
+        assert_eq!(record.buy_price, 50);
+        assert_eq!(record.sell_quantity, 200);
+
+        assert!(client.fetch_price(1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_and_recovers() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1]))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .retry_policy(fast_retry(0))
+            .circuit_breaker(2, Duration::from_millis(100))
+            .build();
+
+        assert!(matches!(
+            client.refresh_all_item_ids().await,
+            Err(Gw2Error::Http(_))
+        ));
+        assert!(matches!(
+            client.refresh_all_item_ids().await,
+            Err(Gw2Error::Http(_))
+        ));
+
+        // Open: fails fast without touching upstream
+        assert!(matches!(
+            client.refresh_all_item_ids().await,
+            Err(Gw2Error::CircuitOpen)
+        ));
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+
+        // Half-open after the cooldown; the trial succeeds and closes the circuit
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(client.refresh_all_item_ids().await.unwrap(), vec![1]);
+        assert_eq!(client.refresh_all_item_ids().await.unwrap(), vec![1]);
+    }
 }
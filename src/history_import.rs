@@ -0,0 +1,305 @@
+use crate::history_record::HistoryRecord;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use std::collections::HashSet;
+use surrealdb::engine::any::Any;
+use surrealdb::{RecordId, Surreal};
+
+/// Rows written to `item_history` per round-trip.
+pub const IMPORT_BATCH_ROWS: usize = 1000;
+
+/// Longest line accepted; a real row is well under 100 bytes.
+pub const MAX_LINE_BYTES: usize = 1024;
+
+/// Invalid rows described in the report; the rest are only counted.
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// What a history import did.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ImportReport {
+    /// Data rows read, header excluded
+    pub rows: usize,
+    pub inserted: usize,
+    /// Valid rows whose item already had history in that minute
+    pub duplicates: usize,
+    pub invalid: usize,
+    /// Why the first invalid rows were rejected, by line number
+    pub errors: Vec<String>,
+}
+
+/// One CSV row: `gw2_id,timestamp,buy,sell,buy_qty,sell_qty`.
+#[derive(serde::Deserialize)]
+struct ImportRow {
+    gw2_id: u32,
+    timestamp: DateTime<Utc>,
+    buy: i64,
+    sell: i64,
+    buy_qty: i64,
+    sell_qty: i64,
+}
+
+impl ImportRow {
+    fn into_record(self) -> Result<HistoryRecord, &'static str> {
+        if self.buy < 0 || self.sell < 0 {
+            return Err("negative price");
+        }
+        if self.buy_qty < 0 || self.sell_qty < 0 {
+            return Err("negative quantity");
+        }
+        if self.timestamp > Utc::now() {
+            return Err("timestamp in the future");
+        }
+        Ok(HistoryRecord {
+            item: RecordId::from(("item", self.gw2_id.to_string())),
+            timestamp: self.timestamp,
+            buy_price: self.buy,
+            sell_price: self.sell,
+            buy_quantity: self.buy_qty,
+            sell_quantity: self.sell_qty,
+        })
+    }
+}
+
+/// Imports history CSV fed to it in arbitrary chunks, e.g. straight off a
+/// request body. Only the current partial line and one batch of rows are held
+/// in memory. An optional header line is skipped.
+///
+/// Rows are written like recovered gw2bltc history: one per item and minute,
+/// only into minutes with no row yet, and replaced by a live price later.
+pub struct HistoryImporter {
+    db: Surreal<Any>,
+    buffer: Vec<u8>,
+    /// The rest of an overlong line is being dropped
+    overlong: bool,
+    /// Lines consumed so far, to number errors
+    lines: u64,
+    /// Valid rows with their line numbers and gw2 ids
+    pending: Vec<(u64, u32, HistoryRecord)>,
+    report: ImportReport,
+}
+
+impl HistoryImporter {
+    pub fn new(db: Surreal<Any>) -> Self {
+        Self {
+            db,
+            buffer: Vec::new(),
+            overlong: false,
+            lines: 0,
+            pending: Vec::new(),
+            report: ImportReport::default(),
+        }
+    }
+
+    pub async fn push(&mut self, mut chunk: &[u8]) -> surrealdb::Result<()> {
+        if self.overlong {
+            let Some(end) = chunk.iter().position(|b| *b == b'\n') else {
+                return Ok(());
+            };
+            chunk = &chunk[end + 1..];
+            self.overlong = false;
+        }
+        self.buffer.extend_from_slice(chunk);
+        if let Some(end) = self.buffer.iter().rposition(|b| *b == b'\n') {
+            let complete: Vec<u8> = self.buffer.drain(..=end).collect();
+            self.parse(&complete);
+        }
+        if self.buffer.len() > MAX_LINE_BYTES {
+            // Reject the line now and skip to its end rather than buffer it
+            self.buffer.clear();
+            self.overlong = true;
+            self.lines += 1;
+            self.report.rows += 1;
+            self.reject(self.lines, "line too long".to_string());
+        }
+        if self.pending.len() >= IMPORT_BATCH_ROWS {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Imports whatever is left, including a last line without a newline.
+    pub async fn finish(mut self) -> surrealdb::Result<ImportReport> {
+        let rest = std::mem::take(&mut self.buffer);
+        self.parse(&rest);
+        self.flush().await?;
+        Ok(self.report)
+    }
+
+    fn parse(&mut self, lines: &[u8]) {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(lines);
+        let mut record = csv::StringRecord::new();
+        let first_line = self.lines;
+        loop {
+            let line = first_line + reader.position().line();
+            match reader.read_record(&mut record) {
+                Ok(false) => break,
+                Ok(true) => {}
+                Err(e) => {
+                    self.reject(line, e.to_string());
+                    continue;
+                }
+            }
+            if line == 1 && record.get(0).is_some_and(|f| f.parse::<u32>().is_err()) {
+                continue;
+            }
+            self.report.rows += 1;
+            let row = record
+                .deserialize::<ImportRow>(None)
+                .map_err(|e| e.to_string())
+                .and_then(|row| {
+                    let gw2_id = row.gw2_id;
+                    row.into_record()
+                        .map(|history| (line, gw2_id, history))
+                        .map_err(str::to_string)
+                });
+            match row {
+                Ok(pending) => self.pending.push(pending),
+                Err(e) => self.reject(line, e),
+            }
+        }
+        self.lines = first_line + reader.position().line() - 1;
+    }
+
+    fn reject(&mut self, line: u64, error: String) {
+        self.report.invalid += 1;
+        if self.report.errors.len() < MAX_REPORTED_ERRORS {
+            self.report.errors.push(format!("line {}: {}", line, error));
+        }
+    }
+
+    /// Writes the pending rows of known items whose minute has no row in
+    /// `item_history` yet, nor earlier in the import.
+    async fn flush(&mut self) -> surrealdb::Result<()> {
+        let pending = std::mem::take(&mut self.pending);
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut items: Vec<RecordId> = pending.iter().map(|(_, _, r)| r.item.clone()).collect();
+        items.sort_by_key(|item| item.to_string());
+        items.dedup();
+        let known: Vec<RecordId> = self
+            .db
+            .query("SELECT VALUE id FROM $items")
+            .bind(("items", items.clone()))
+            .await?
+            .take(0)?;
+        let known: HashSet<String> = known.iter().map(RecordId::to_string).collect();
+        let mut rows = Vec::with_capacity(pending.len());
+        for (line, gw2_id, row) in pending {
+            if known.contains(&row.item.to_string()) {
+                rows.push(row);
+            } else {
+                self.reject(line, format!("unknown item {}", gw2_id));
+            }
+        }
+        let minute = |t: DateTime<Utc>| t.duration_trunc(TimeDelta::minutes(1)).unwrap_or(t);
+        let Some(from) = rows.iter().map(|r| minute(r.timestamp)).min() else {
+            return Ok(());
+        };
+        let to = rows
+            .iter()
+            .map(|r| minute(r.timestamp))
+            .max()
+            .unwrap_or(from)
+            + TimeDelta::minutes(1);
+
+        #[derive(serde::Deserialize)]
+        struct Existing {
+            item: RecordId,
+            recorded_at: DateTime<Utc>,
+        }
+        let existing: Vec<Existing> = self
+            .db
+            .query(
+                "SELECT item, recorded_at FROM item_history
+                    WHERE item IN $items AND recorded_at >= $from AND recorded_at < $to",
+            )
+            .bind(("items", items))
+            .bind(("from", surrealdb::Datetime::from(from)))
+            .bind(("to", surrealdb::Datetime::from(to)))
+            .await?
+            .take(0)?;
+        let mut seen: HashSet<(String, DateTime<Utc>)> = existing
+            .into_iter()
+            .map(|e| (e.item.to_string(), minute(e.recorded_at)))
+            .collect();
+
+        let count = rows.len();
+        rows.retain(|r| seen.insert((r.item.to_string(), minute(r.timestamp))));
+        self.report.duplicates += count - rows.len();
+        if !rows.is_empty() {
+            self.report.inserted += rows.len();
+            self.db
+                .query(crate::price_sync::RECOVERED_HISTORY_INSERT)
+                .bind(("records", rows))
+                .await?
+                .check()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use surrealdb::engine::any::connect;
+
+    #[tokio::test]
+    async fn test_import_in_chunks() {
+        let db = connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        crate::migrate(&db).await.unwrap();
+        db.query("CREATE item:⟨1⟩; CREATE item:⟨2⟩; CREATE item:⟨3⟩;
+            CREATE item_history SET item = item:⟨1⟩, timestamp = '2025-01-01T00:00:00Z', buy_price = 1, sell_price = 2, buy_quantity = 3, sell_quantity = 4")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let csv = format!(
+            "gw2_id,timestamp,buy,sell,buy_qty,sell_qty\r\n\
+            1,2025-01-01T00:00:00Z,100,120,5,6\n\
+            1,2025-01-01T01:00:00Z,110,130,5,6\n\
+            2,2025-01-01T00:00:00Z,7,9,1,1\n\
+            2,yesterday,7,9,1,1\n\
+            2,2025-01-01T02:00:00Z,-7,9,1,1\n\
+            2,2025-01-01T00:00:45Z,7,9,1,1\n\
+            4,2025-01-01T00:00:00Z,7,9,1,1\n\
+            3,{}\n\
+            3,2025-01-01T00:00:00Z,50,60,1,1",
+            "9".repeat(2 * MAX_LINE_BYTES)
+        );
+        let mut importer = HistoryImporter::new(db.clone());
+        // Chunk boundaries fall mid-line
+        for chunk in csv.as_bytes().chunks(7) {
+            importer.push(chunk).await.unwrap();
+        }
+        let report = importer.finish().await.unwrap();
+
+        assert_eq!(report.rows, 9);
+        assert_eq!(report.inserted, 3);
+        // Same timestamp, and same minute, as a row already there
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(report.invalid, 4);
+        assert!(
+            report.errors[0].starts_with("line 5:"),
+            "{:?}",
+            report.errors
+        );
+        assert_eq!(report.errors[1], "line 6: negative price");
+        assert_eq!(report.errors[2], "line 9: line too long");
+        assert_eq!(report.errors[3], "line 8: unknown item 4");
+
+        let count: Option<serde_json::Value> = db
+            .query("SELECT count() FROM item_history GROUP ALL")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(count.unwrap()["count"], 4);
+    }
+}
@@ -1,58 +1,473 @@
+use crate::sync_status;
+use crate::timed_query;
 use std::time::Duration;
 use surrealdb::Surreal;
 use surrealdb::engine::any::Any;
 use tokio::time::interval;
 use tokio_util::sync::CancellationToken;
 
+/// Rows a prune statement removes at most (item buckets, in aggregate mode), so
+/// no single statement holds its locks long enough to stall price-sync writes.
+pub const DEFAULT_BATCH_SIZE: usize = 10_000;
+
+/// One retention tier: history aged between `older_than` and `younger_than`
+/// (unbounded when `None`) is thinned to the earliest row per `bucket`.
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionTier {
+    pub older_than: Duration,
+    pub younger_than: Option<Duration>,
+    pub bucket: Duration,
+}
+
+/// What happens to the rows of a bucket that a tier thins out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PruneMode {
+    /// Keep the earliest raw row of each bucket and drop the rest
+    #[default]
+    Sample,
+    /// Like `Sample`, but also keep the rows with the lowest and highest sell
+    /// price of each bucket, so intra-bucket spikes survive. Ties go to the
+    /// earliest row, so a flat bucket still shrinks to one row.
+    Extremes,
+    /// Fold every raw row of a bucket into one OHLC candle in `item_history_agg`,
+    /// then drop them all. Candles are written once, at the first tier that
+    /// reaches a bucket, and aren't coarsened by later tiers.
+    Aggregate,
+}
+
+/// How history is thinned as it ages. Rows younger than every tier are kept as is.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub tiers: Vec<RetentionTier>,
+    pub mode: PruneMode,
+    /// Each item's most recent rows that no tier touches, however old, so newly
+    /// tracked or rarely listed items keep some history
+    pub keep_recent: usize,
+}
+
+impl Default for RetentionPolicy {
+    /// Hourly after 3 days, 3-hourly after a week, 6-hourly after two weeks.
+    fn default() -> Self {
+        const HOUR: u64 = 3600;
+        const DAY: u64 = 24 * HOUR;
+        let tier = |older_days, younger_days: Option<u64>, bucket_hours| RetentionTier {
+            older_than: Duration::from_secs(older_days * DAY),
+            younger_than: younger_days.map(|d| Duration::from_secs(d * DAY)),
+            bucket: Duration::from_secs(bucket_hours * HOUR),
+        };
+        Self {
+            tiers: vec![tier(3, Some(7), 1), tier(7, Some(14), 3), tier(14, None, 6)],
+            mode: PruneMode::Sample,
+            keep_recent: 50,
+        }
+    }
+}
+
+impl RetentionTier {
+    /// Rows this tier may prune: in its age range and, with a `keep_recent` floor,
+    /// older than their item's cutoff in `$keep_before` (see `keep_before`). The
+    /// range edges are floored to whole buckets, so a bucket is never split across
+    /// two passes.
+    fn range_condition(&self, keep_recent: usize) -> String {
+        let mut condition = if self.younger_than.is_some() {
+            "<datetime>timestamp < time::floor(time::now() - type::duration($older_than), type::duration($bucket)) AND <datetime>timestamp >= time::floor(time::now() - type::duration($younger_than), type::duration($bucket))".to_string()
+        } else {
+            "<datetime>timestamp < time::floor(time::now() - type::duration($older_than), type::duration($bucket))".to_string()
+        };
+        // Items without a cutoff have too few rows to prune; comparing with NONE is false.
+        // The planner evaluates the condition without a row, hence the `OR ''` guard.
+        if keep_recent > 0 {
+            condition.push_str(" AND <datetime>timestamp < $keep_before[type::string(item OR '')]");
+        }
+        condition
+    }
+
+    // One candle per (item, bucket), keyed by both: open/close are the first/last
+    // row by time, volumes are the mean listed quantity over the bucket. Rows of a
+    // bucket that already has a candle (ones `keep_recent` held back) are merged
+    // into it. Handles up to `$batch` buckets and returns the raw rows it folded.
+    fn aggregate_query(&self, keep_recent: usize) -> String {
+        let range = self.range_condition(keep_recent);
+        format!(
+            "LET $groups = (SELECT item, time::floor(<datetime>timestamp, type::duration($bucket)) AS bucket_start, count() AS samples FROM item_history WHERE {range} GROUP BY item, bucket_start LIMIT $batch);
+            FOR $g IN $groups {{
+                LET $rows = (SELECT id, buy_price, sell_price, buy_quantity, sell_quantity, <datetime>timestamp AS ts FROM item_history
+                    WHERE item = $g.item AND {range}
+                        AND time::floor(<datetime>timestamp, type::duration($bucket)) = $g.bucket_start
+                    ORDER BY ts);
+                UPSERT type::thing('item_history_agg', [$g.item, $g.bucket_start]) SET
+                    item = $g.item,
+                    bucket_start = $g.bucket_start,
+                    bucket = type::duration($bucket),
+                    buy_open = IF samples != NONE THEN buy_open ELSE $rows[0].buy_price END,
+                    buy_high = math::max(array::concat(IF samples != NONE THEN [buy_high] ELSE [] END, $rows.buy_price)),
+                    buy_low = math::min(array::concat(IF samples != NONE THEN [buy_low] ELSE [] END, $rows.buy_price)),
+                    buy_close = array::last($rows).buy_price,
+                    sell_open = IF samples != NONE THEN sell_open ELSE $rows[0].sell_price END,
+                    sell_high = math::max(array::concat(IF samples != NONE THEN [sell_high] ELSE [] END, $rows.sell_price)),
+                    sell_low = math::min(array::concat(IF samples != NONE THEN [sell_low] ELSE [] END, $rows.sell_price)),
+                    sell_close = array::last($rows).sell_price,
+                    buy_quantity = ((buy_quantity OR 0) * (samples OR 0) + math::sum($rows.buy_quantity)) / ((samples OR 0) + array::len($rows)),
+                    sell_quantity = ((sell_quantity OR 0) * (samples OR 0) + math::sum($rows.sell_quantity)) / ((samples OR 0) + array::len($rows)),
+                    samples = (samples OR 0) + array::len($rows);
+                FOR $row IN $rows {{ DELETE $row.id; }};
+            }};
+            RETURN math::sum($groups.samples);"
+        )
+    }
+
+    // Strategy: Instead of strict minute-based rules (which fail with sync jitters
+    // or external imports), we keep the EARLIEST record in each time bucket.
+    // This ensures at least one data point per period even if it's "late".
+    // The inner select filters on `item` alone so it can use the (item, timestamp)
+    // index; the `<datetime>` casts would otherwise force a table scan per row.
+    fn sample_condition(&self, keep_recent: usize) -> String {
+        format!(
+            "{} AND {} > 0",
+            self.range_condition(keep_recent),
+            bucket_rows(EARLIER)
+        )
+    }
+
+    // A row is the bucket's low (high) unless another row sells lower (higher),
+    // or as low (high) but earlier. Probes are summed rather than OR-ed: a
+    // parenthesized group would rebind `$parent`.
+    fn extremes_condition(&self, keep_recent: usize) -> String {
+        let same_price_earlier =
+            bucket_rows(&format!("sell_price = $parent.sell_price AND {EARLIER}"));
+        format!(
+            "{} AND {} + {same_price_earlier} > 0 AND {} + {same_price_earlier} > 0",
+            self.sample_condition(keep_recent),
+            bucket_rows("sell_price < $parent.sell_price"),
+            bucket_rows("sell_price > $parent.sell_price"),
+        )
+    }
+
+    /// Counts the raw rows a prune with `mode` removes from this tier.
+    fn count_query(&self, mode: PruneMode, keep_recent: usize) -> String {
+        let condition = match mode {
+            PruneMode::Sample => self.sample_condition(keep_recent),
+            PruneMode::Extremes => self.extremes_condition(keep_recent),
+            // Every raw row in range is folded into a candle
+            PruneMode::Aggregate => self.range_condition(keep_recent),
+        };
+        format!("SELECT count() FROM item_history WHERE {condition} GROUP ALL")
+    }
+
+    /// One batch of the prune, returning the raw rows it removed.
+    fn prune_query(&self, mode: PruneMode, keep_recent: usize) -> String {
+        match mode {
+            // Resolve the doomed ids before deleting: evaluated inside the DELETE, the
+            // "earlier row in bucket" probe can see siblings the same statement already
+            // removed and spare rows the count query said would go.
+            PruneMode::Sample => delete_batch_query(&self.sample_condition(keep_recent)),
+            PruneMode::Extremes => delete_batch_query(&self.extremes_condition(keep_recent)),
+            PruneMode::Aggregate => self.aggregate_query(keep_recent),
+        }
+    }
+
+    fn binds(&self) -> Vec<(&'static str, String)> {
+        let mut binds = vec![
+            ("older_than", surreal_duration(self.older_than)),
+            ("bucket", surreal_duration(self.bucket)),
+        ];
+        if let Some(younger_than) = self.younger_than {
+            binds.push(("younger_than", surreal_duration(younger_than)));
+        }
+        binds
+    }
+}
+
+const EARLIER: &str = "<datetime>timestamp < <datetime>$parent.timestamp";
+
+/// 1 if another row of the current row's item and bucket matches `filter`, in
+/// which `$parent` is the current row, else 0.
+fn bucket_rows(filter: &str) -> String {
+    format!(
+        "count(SELECT id FROM (SELECT id, timestamp, sell_price FROM item_history WHERE item = $parent.item) WHERE time::floor(<datetime>timestamp, type::duration($bucket)) = time::floor(<datetime>$parent.timestamp, type::duration($bucket)) AND {filter} LIMIT 1)"
+    )
+}
+
+/// Deletes up to `$batch` history rows matching `condition`, returning how many.
+fn delete_batch_query(condition: &str) -> String {
+    format!(
+        "LET $doomed = (SELECT VALUE id FROM item_history WHERE {condition} LIMIT $batch);
+        DELETE $doomed;
+        RETURN array::len($doomed);"
+    )
+}
+
+/// Per-item pruning cutoffs, keyed by the item record as a string.
+type KeepBefore = std::collections::BTreeMap<String, surrealdb::Datetime>;
+
+const ORPHAN_CONDITION: &str = "record::exists(item) = false";
+
+/// SurrealQL duration literal, for `type::duration`.
+fn surreal_duration(duration: Duration) -> String {
+    format!("{}s", duration.as_secs())
+}
+
+/// What a `HistoryPruning::run_pruning` pass removed, or would remove in dry-run mode.
+#[derive(Debug, Clone, Default)]
+pub struct PruneReport {
+    /// Raw history rows removed by each policy tier, in policy order
+    pub deleted: Vec<usize>,
+    /// Rows whose item no longer exists
+    pub orphans_deleted: usize,
+    /// Whether cancellation stopped the pass early; the counts cover what ran
+    pub interrupted: bool,
+    /// Whether the history indexes were rebuilt afterwards
+    pub indexes_rebuilt: bool,
+    pub elapsed: Duration,
+}
+
+impl PruneReport {
+    pub fn total_deleted(&self) -> usize {
+        self.deleted.iter().sum::<usize>() + self.orphans_deleted
+    }
+}
+
 #[derive(Clone)]
 pub struct HistoryPruning {
     db: Surreal<Any>,
+    policy: RetentionPolicy,
+    dry_run: bool,
+    batch_size: usize,
+    token: CancellationToken,
+    rebuild_indexes: bool,
 }
 
 impl HistoryPruning {
-    pub fn new(db: Surreal<Any>) -> Self {
-        Self { db }
+    pub fn new(db: Surreal<Any>, policy: RetentionPolicy) -> Self {
+        Self {
+            db,
+            policy,
+            dry_run: false,
+            batch_size: DEFAULT_BATCH_SIZE,
+            token: CancellationToken::new(),
+            rebuild_indexes: false,
+        }
     }
 
-    pub async fn run_pruning(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Counts what each tier would remove without deleting (or aggregating) anything.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Stops a pass between batches once `token` is cancelled. `spawn` sets this.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Rebuilds the history indexes after a pass that removed rows, dropping the
+    /// entries left behind by mass deletes. Rebuilding reads the whole table, hence
+    /// opt-in. It pays off on the persistent engines (`rocksdb://`, `surrealkv://`,
+    /// or a server backed by them), which reclaim the freed space in their own
+    /// background compaction; SurrealQL has no VACUUM. Pointless on `mem://`.
+    pub fn with_index_rebuild(mut self, rebuild_indexes: bool) -> Self {
+        self.rebuild_indexes = rebuild_indexes;
+        self
+    }
+
+    pub async fn run_pruning(&self) -> Result<PruneReport, Box<dyn std::error::Error>> {
+        let result = self.prune().await;
+        if self.dry_run {
+            return result;
+        }
+        sync_status::record_outcome(&self.db, sync_status::HISTORY_PRUNING, result).await
+    }
+
+    async fn prune(&self) -> Result<PruneReport, Box<dyn std::error::Error>> {
         println!("Starting history pruning...");
+        let started = std::time::Instant::now();
+        let mut report = PruneReport::default();
+
+        let RetentionPolicy {
+            mode, keep_recent, ..
+        } = self.policy;
+        // Pruning only removes rows older than the cutoffs, so they hold for the whole pass
+        let keep_before = if keep_recent > 0 {
+            Some(self.keep_before(keep_recent).await?)
+        } else {
+            None
+        };
+        for tier in &self.policy.tiers {
+            let deleted = if self.dry_run {
+                self.count(
+                    &tier.count_query(mode, keep_recent),
+                    tier.binds(),
+                    keep_before.as_ref(),
+                )
+                .await?
+            } else {
+                self.delete_in_batches(
+                    &tier.prune_query(mode, keep_recent),
+                    tier.binds(),
+                    keep_before.as_ref(),
+                )
+                .await?
+            };
+            report.deleted.push(deleted);
+            if self.token.is_cancelled() {
+                report.interrupted = true;
+                break;
+            }
+        }
 
-        // Strategy: Instead of strict minute-based rules (which fail with sync jitters
-        // or external imports), we keep the EARLIEST record in each time bucket.
-        // This ensures at least one data point per period even if it's "late".
+        if !report.interrupted {
+            report.orphans_deleted = self.prune_orphans().await?;
+            report.interrupted = self.token.is_cancelled();
+        }
+
+        if self.rebuild_indexes
+            && !self.dry_run
+            && !report.interrupted
+            && report.total_deleted() > 0
+        {
+            timed_query(
+                "pruning_rebuild_index",
+                self.db.query(
+                    "REBUILD INDEX IF EXISTS item_history_item_ts_idx ON TABLE item_history",
+                ),
+            )
+            .await?
+            .check()?;
+            report.indexes_rebuilt = true;
+        }
 
-        // 1. Older than 3 days, keep 1 per hour
-        let q1 = "DELETE item_history WHERE 
-            <datetime>timestamp < (time::now() - 3d) AND 
-            <datetime>timestamp >= (time::now() - 7d) AND 
-            count(SELECT id FROM item_history WHERE item = $parent.item AND time::floor(<datetime>timestamp, 1h) = time::floor(<datetime>$parent.timestamp, 1h) AND <datetime>timestamp < <datetime>$parent.timestamp LIMIT 1) > 0";
+        report.elapsed = started.elapsed();
+        println!(
+            "History pruning {}: {} rows ({:?} by tier, {} orphaned) in {:?}.",
+            if self.dry_run {
+                "dry run would remove"
+            } else if report.interrupted {
+                "interrupted after removing"
+            } else {
+                "complete, removed"
+            },
+            report.total_deleted(),
+            report.deleted,
+            report.orphans_deleted,
+            report.elapsed
+        );
+        Ok(report)
+    }
 
-        // 2. Older than 1 week, keep 1 per 3 hours
-        let q2 = "DELETE item_history WHERE 
-            <datetime>timestamp < (time::now() - 7d) AND 
-            <datetime>timestamp >= (time::now() - 14d) AND 
-            count(SELECT id FROM item_history WHERE item = $parent.item AND time::floor(<datetime>timestamp, 3h) = time::floor(<datetime>$parent.timestamp, 3h) AND <datetime>timestamp < <datetime>$parent.timestamp LIMIT 1) > 0";
+    /// Removes history of items that no longer exist. Skipped while the item table
+    /// is empty (e.g. a fresh database mid-restore) so it can't wipe all history.
+    async fn prune_orphans(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let items = self
+            .count("SELECT count() FROM item GROUP ALL", Vec::new(), None)
+            .await?;
+        if items == 0 {
+            println!("Item table is empty; not pruning orphaned history.");
+            return Ok(0);
+        }
 
-        // 3. Older than 2 weeks, keep 1 per 6 hours
-        let q3 = "DELETE item_history WHERE 
-            <datetime>timestamp < (time::now() - 14d) AND 
-            count(SELECT id FROM item_history WHERE item = $parent.item AND time::floor(<datetime>timestamp, 6h) = time::floor(<datetime>$parent.timestamp, 6h) AND <datetime>timestamp < <datetime>$parent.timestamp LIMIT 1) > 0";
+        if self.dry_run {
+            let query =
+                format!("SELECT count() FROM item_history WHERE {ORPHAN_CONDITION} GROUP ALL");
+            self.count(&query, Vec::new(), None).await
+        } else {
+            self.delete_in_batches(&delete_batch_query(ORPHAN_CONDITION), Vec::new(), None)
+                .await
+        }
+    }
+
+    /// Timestamp of each item's `keep_recent`-th newest row, keyed by item record.
+    /// Rows older than that may be pruned; items with fewer rows are left out.
+    async fn keep_before(
+        &self,
+        keep_recent: usize,
+    ) -> Result<KeepBefore, Box<dyn std::error::Error>> {
+        #[derive(serde::Deserialize)]
+        struct Cutoff {
+            item: String,
+            cutoff: Option<surrealdb::Datetime>,
+        }
+        let cutoffs: Vec<Cutoff> = timed_query(
+            "pruning_keep_recent",
+            self.db
+                .query(
+                    "SELECT type::string(item) AS item, (SELECT <datetime>timestamp AS ts FROM item_history WHERE item = $parent.item ORDER BY ts DESC LIMIT 1 START $skip)[0].ts AS cutoff FROM (SELECT item FROM item_history GROUP BY item)",
+                )
+                .bind(("skip", keep_recent - 1)),
+        )
+        .await?
+        .check()?
+        .take(0)?;
+        Ok(cutoffs
+            .into_iter()
+            .filter_map(|c| Some((c.item, c.cutoff?)))
+            .collect())
+    }
 
-        // Execute queries
-        self.db.query(q1).await?.check()?;
-        self.db.query(q2).await?.check()?;
-        self.db.query(q3).await?.check()?;
+    /// Runs a `SELECT count() ... GROUP ALL` query.
+    async fn count(
+        &self,
+        query: &str,
+        binds: Vec<(&'static str, String)>,
+        keep_before: Option<&KeepBefore>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut query = self.db.query(query);
+        for bind in binds {
+            query = query.bind(bind);
+        }
+        if let Some(keep_before) = keep_before {
+            query = query.bind(("keep_before", keep_before.clone()));
+        }
+        Ok(timed_query("pruning_count", query)
+            .await?
+            .check()?
+            .take::<Option<serde_json::Value>>(0)?
+            .and_then(|v| v.get("count")?.as_u64())
+            .unwrap_or(0) as usize)
+    }
 
-        println!("History pruning complete.");
-        Ok(())
+    /// Repeats a batch query, whose last statement returns the rows it removed,
+    /// until a batch removes nothing or the pass is cancelled. Each batch is its
+    /// own statement, so concurrent writers get a turn in between.
+    async fn delete_in_batches(
+        &self,
+        batch_query: &str,
+        binds: Vec<(&'static str, String)>,
+        keep_before: Option<&KeepBefore>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut deleted = 0;
+        while !self.token.is_cancelled() {
+            let mut query = self.db.query(batch_query).bind(("batch", self.batch_size));
+            for bind in binds.iter().cloned() {
+                query = query.bind(bind);
+            }
+            if let Some(keep_before) = keep_before {
+                query = query.bind(("keep_before", keep_before.clone()));
+            }
+            let mut response = timed_query("pruning_delete_batch", query).await?.check()?;
+            let last = response.num_statements() - 1;
+            let removed = response.take::<Option<u64>>(last)?.unwrap_or(0) as usize;
+            if removed == 0 {
+                break;
+            }
+            deleted += removed;
+            tokio::task::yield_now().await;
+        }
+        Ok(deleted)
     }
 
     pub async fn spawn(self, interval_duration: Duration, token: CancellationToken) {
+        let this = self.with_cancellation(token.clone());
         let mut interval = interval(interval_duration);
         loop {
             tokio::select! {
                 _ = interval.tick() => {
-                    if let Err(e) = self.run_pruning().await {
+                    if let Err(e) = this.run_pruning().await {
                         eprintln!("History pruning error: {}", e);
                     }
                 }
@@ -69,7 +484,7 @@ impl HistoryPruning {
 mod tests {
     use super::*;
     use crate::history_record::HistoryRecord;
-    use chrono::{Duration as ChronoDuration, Utc};
+    use chrono::{Duration as ChronoDuration, DurationRound, Utc};
     use surrealdb::engine::any::connect;
 
     async fn setup_db() -> Surreal<Any> {
@@ -77,22 +492,52 @@ mod tests {
         db.use_ns("test").use_db("test").await.unwrap();
         db.query(
             "DEFINE TABLE item SCHEMALESS; 
-                 DEFINE TABLE item_history SCHEMALESS;
-                 DEFINE INDEX item_history_item_ts_idx ON TABLE item_history COLUMNS item, timestamp;",
+                 DEFINE TABLE item_history SCHEMALESS;",
         )
         .await
         .unwrap();
+        crate::migrate(&db).await.unwrap();
         db
     }
 
+    // Spelled out rather than `RetentionPolicy::default()`, so the tests pin the tiers.
+    // No recent-rows floor, so a couple of seeded rows are enough to exercise a tier.
+    fn test_policy() -> RetentionPolicy {
+        let days = |d: u64| Duration::from_secs(d * 86400);
+        let hours = |h: u64| Duration::from_secs(h * 3600);
+        RetentionPolicy {
+            tiers: vec![
+                RetentionTier {
+                    older_than: days(3),
+                    younger_than: Some(days(7)),
+                    bucket: hours(1),
+                },
+                RetentionTier {
+                    older_than: days(7),
+                    younger_than: Some(days(14)),
+                    bucket: hours(3),
+                },
+                RetentionTier {
+                    older_than: days(14),
+                    younger_than: None,
+                    bucket: hours(6),
+                },
+            ],
+            mode: PruneMode::Sample,
+            keep_recent: 0,
+        }
+    }
+
     #[tokio::test]
     async fn test_pruning_1h_bucket() {
         let db = setup_db().await;
-        let pruner = HistoryPruning::new(db.clone());
+        let pruner = HistoryPruning::new(db.clone(), test_policy());
         let now = Utc::now();
 
         // Older than 3 days, same hour. One should be deleted.
-        let t1 = now - ChronoDuration::days(4);
+        let t1 = (now - ChronoDuration::days(4))
+            .duration_trunc(ChronoDuration::hours(1))
+            .unwrap();
         let t2 = t1 + ChronoDuration::minutes(10);
 
         db.query("CREATE item_history SET item = item:123, timestamp = <datetime>$t, buy_price = 10, sell_price = 11, buy_quantity = 100, sell_quantity = 100").bind(("t", t1)).await.unwrap();
@@ -112,13 +557,15 @@ mod tests {
     #[tokio::test]
     async fn test_pruning_3h_bucket() {
         let db = setup_db().await;
-        let pruner = HistoryPruning::new(db.clone());
+        let pruner = HistoryPruning::new(db.clone(), test_policy());
         let now = Utc::now();
         db.query("CREATE item:123").await.unwrap();
 
         // Older than 7 days, same 3h bucket.
         // We use a small offset (1 min) to stay within the same 3h block.
-        let t1 = now - ChronoDuration::days(8);
+        let t1 = (now - ChronoDuration::days(8))
+            .duration_trunc(ChronoDuration::hours(3))
+            .unwrap();
         let t2 = t1 + ChronoDuration::minutes(1);
 
         db.query("CREATE item_history SET item = item:123, timestamp = <datetime>$t, buy_price = 10, sell_price = 11, buy_quantity = 100, sell_quantity = 100").bind(("t", t1)).await.unwrap();
@@ -138,12 +585,14 @@ mod tests {
     #[tokio::test]
     async fn test_pruning_6h_bucket() {
         let db = setup_db().await;
-        let pruner = HistoryPruning::new(db.clone());
+        let pruner = HistoryPruning::new(db.clone(), test_policy());
         let now = Utc::now();
         db.query("CREATE item:123").await.unwrap();
 
         // Older than 14 days, same 6h bucket.
-        let t1 = now - ChronoDuration::days(15);
+        let t1 = (now - ChronoDuration::days(15))
+            .duration_trunc(ChronoDuration::hours(6))
+            .unwrap();
         let t2 = t1 + ChronoDuration::minutes(1);
 
         db.query("CREATE item_history SET item = item:123, timestamp = <datetime>$t, buy_price = 10, sell_price = 11, buy_quantity = 100, sell_quantity = 100").bind(("t", t1)).await.unwrap();
@@ -163,7 +612,7 @@ mod tests {
     #[tokio::test]
     async fn test_pruning_retention() {
         let db = setup_db().await;
-        let pruner = HistoryPruning::new(db.clone());
+        let pruner = HistoryPruning::new(db.clone(), test_policy());
         let now = Utc::now();
 
         // Within 3 days. None should be deleted even if in same hour.
@@ -182,4 +631,389 @@ mod tests {
         let remaining: Vec<HistoryRecord> = res.take(0).unwrap();
         assert_eq!(remaining.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_pruning_aggregates_candles() {
+        let db = setup_db().await;
+        let pruner = HistoryPruning::new(
+            db.clone(),
+            RetentionPolicy {
+                mode: PruneMode::Aggregate,
+                ..test_policy()
+            },
+        );
+
+        // Older than 3 days, all in one hour, with the low in the middle
+        let t1 = (Utc::now() - ChronoDuration::days(4))
+            .duration_trunc(ChronoDuration::hours(1))
+            .unwrap();
+        for (minutes, buy_price, sell_price) in
+            [(0, 10, 20), (10, 15, 30), (20, 8, 25), (30, 12, 22)]
+        {
+            db.query("CREATE item_history SET item = item:123, timestamp = <datetime>$t, buy_price = $buy, sell_price = $sell, buy_quantity = 100, sell_quantity = 200")
+                .bind(("t", t1 + ChronoDuration::minutes(minutes)))
+                .bind(("buy", buy_price))
+                .bind(("sell", sell_price))
+                .await
+                .unwrap();
+        }
+
+        pruner.run_pruning().await.unwrap();
+
+        let candles: Vec<serde_json::Value> = db
+            .query("SELECT buy_open, buy_high, buy_low, buy_close, sell_high, sell_low, samples, buy_quantity FROM item_history_agg")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(candles.len(), 1);
+        let candle = &candles[0];
+        assert_eq!(candle["buy_open"], 10);
+        assert_eq!(candle["buy_high"], 15);
+        assert_eq!(candle["buy_low"], 8);
+        assert_eq!(candle["buy_close"], 12);
+        assert_eq!(candle["sell_high"], 30);
+        assert_eq!(candle["sell_low"], 20);
+        assert_eq!(candle["samples"], 4);
+        assert_eq!(candle["buy_quantity"].as_f64(), Some(100.0));
+
+        let remaining: Vec<HistoryRecord> = db
+            .query("SELECT * FROM item_history")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert!(remaining.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pruning_aggregates_whole_buckets_once() {
+        let db = setup_db().await;
+        let pruner = HistoryPruning::new(
+            db.clone(),
+            RetentionPolicy {
+                mode: PruneMode::Aggregate,
+                ..test_policy()
+            },
+        );
+        let insert = |t: chrono::DateTime<Utc>, buy_price: u32| {
+            let db = db.clone();
+            async move {
+                db.query("CREATE item_history SET item = item:123, timestamp = <datetime>$t, buy_price = $buy, sell_price = 50, buy_quantity = 100, sell_quantity = 200")
+                    .bind(("t", t))
+                    .bind(("buy", buy_price))
+                    .await
+                    .unwrap();
+            }
+        };
+        let candles = || {
+            let db = db.clone();
+            async move {
+                let candles: Vec<serde_json::Value> = db
+                    .query("SELECT buy_open, buy_high, buy_close, samples FROM item_history_agg")
+                    .await
+                    .unwrap()
+                    .take(0)
+                    .unwrap();
+                candles
+            }
+        };
+
+        // The hour the 3-day cutoff falls in isn't complete yet, so it's left alone
+        let cutoff_hour = (Utc::now() - ChronoDuration::days(3))
+            .duration_trunc(ChronoDuration::hours(1))
+            .unwrap();
+        let previous_hour = cutoff_hour - ChronoDuration::hours(1);
+        insert(previous_hour, 10).await;
+        insert(previous_hour + ChronoDuration::minutes(10), 12).await;
+        insert(cutoff_hour, 99).await;
+        pruner.run_pruning().await.unwrap();
+
+        let first = candles().await;
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0]["samples"], 2);
+        let remaining: Vec<HistoryRecord> = db
+            .query("SELECT * FROM item_history")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+
+        // A late row for an aggregated hour is merged into its candle
+        insert(previous_hour + ChronoDuration::minutes(20), 15).await;
+        pruner.run_pruning().await.unwrap();
+
+        let merged = candles().await;
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0]["samples"], 3);
+        assert_eq!(merged[0]["buy_open"], 10);
+        assert_eq!(merged[0]["buy_high"], 15);
+        assert_eq!(merged[0]["buy_close"], 15);
+    }
+
+    #[tokio::test]
+    async fn test_pruning_report_matches_deletions() {
+        let db = setup_db().await;
+        let now = Utc::now();
+
+        // Three rows in one old hour (two prunable), two in a 3h bucket a week back
+        // (one prunable), and two recent rows that stay
+        let hour = (now - ChronoDuration::days(4))
+            .duration_trunc(ChronoDuration::hours(1))
+            .unwrap();
+        let three_hours = (now - ChronoDuration::days(8))
+            .duration_trunc(ChronoDuration::hours(3))
+            .unwrap();
+        let recent = now - ChronoDuration::days(1);
+        for t in [
+            hour,
+            hour + ChronoDuration::minutes(10),
+            hour + ChronoDuration::minutes(20),
+            three_hours,
+            three_hours + ChronoDuration::minutes(1),
+            recent,
+            recent + ChronoDuration::minutes(1),
+        ] {
+            db.query("CREATE item_history SET item = item:123, timestamp = <datetime>$t, buy_price = 10, sell_price = 11, buy_quantity = 100, sell_quantity = 100")
+                .bind(("t", t))
+                .await
+                .unwrap();
+        }
+        let count = || async {
+            db.query("SELECT count() FROM item_history GROUP ALL")
+                .await
+                .unwrap()
+                .take::<Option<serde_json::Value>>(0)
+                .unwrap()
+                .and_then(|v| v.get("count")?.as_u64())
+                .unwrap_or(0) as usize
+        };
+
+        let pruner = HistoryPruning::new(db.clone(), test_policy());
+        let dry_run = pruner
+            .clone()
+            .with_dry_run(true)
+            .run_pruning()
+            .await
+            .unwrap();
+        assert_eq!(dry_run.deleted, vec![2, 1, 0]);
+        assert_eq!(count().await, 7);
+
+        let report = pruner.run_pruning().await.unwrap();
+        assert_eq!(report.deleted, dry_run.deleted);
+        assert_eq!(count().await, 7 - report.total_deleted());
+    }
+
+    #[tokio::test]
+    async fn test_pruning_removes_orphaned_history() {
+        let db = setup_db().await;
+        let recent = Utc::now() - ChronoDuration::hours(1);
+        for item in ["item:1", "item:2"] {
+            db.query(format!("CREATE item_history SET item = {item}, timestamp = <datetime>$t, buy_price = 10, sell_price = 11, buy_quantity = 100, sell_quantity = 100"))
+                .bind(("t", recent))
+                .await
+                .unwrap();
+        }
+        let pruner = HistoryPruning::new(db.clone(), test_policy());
+
+        // Without any items, orphans aren't trusted to be orphans
+        let report = pruner.run_pruning().await.unwrap();
+        assert_eq!(report.orphans_deleted, 0);
+
+        // Item 2 was deleted, item 1 still exists
+        db.query("CREATE item:1").await.unwrap();
+        let report = pruner.run_pruning().await.unwrap();
+        assert_eq!(report.orphans_deleted, 1);
+
+        let remaining: Vec<HistoryRecord> = db
+            .query("SELECT * FROM item_history")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].item, surrealdb::RecordId::from(("item", 1)));
+    }
+
+    // 150 items with a row a minute for 20 minutes, 4 days old: the 1h tier keeps
+    // one row per item. Returns (seeded, kept).
+    async fn seed_minutely_history(db: &Surreal<Any>) -> (usize, usize) {
+        let start = (Utc::now() - ChronoDuration::days(4))
+            .duration_trunc(ChronoDuration::hours(1))
+            .unwrap();
+        db.query("FOR $item IN 1..=150 {
+                LET $id = type::thing('item', $item);
+                CREATE $id;
+                FOR $i IN 0..20 { CREATE item_history SET item = $id, timestamp = <datetime>$start + type::duration(string::concat($i, 'm')), buy_price = 10, sell_price = 11, buy_quantity = 100, sell_quantity = 100 };
+            }")
+            .bind(("start", start))
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        (3000, 150)
+    }
+
+    async fn history_count(db: &Surreal<Any>) -> usize {
+        db.query("SELECT count() FROM item_history GROUP ALL")
+            .await
+            .unwrap()
+            .take::<Option<serde_json::Value>>(0)
+            .unwrap()
+            .and_then(|v| v.get("count")?.as_u64())
+            .unwrap_or(0) as usize
+    }
+
+    #[tokio::test]
+    async fn test_pruning_deletes_in_batches() {
+        let db = setup_db().await;
+        let (seeded, kept) = seed_minutely_history(&db).await;
+        let pruner = HistoryPruning::new(db.clone(), test_policy()).with_batch_size(500);
+
+        let report = pruner.run_pruning().await.unwrap();
+        assert!(!report.interrupted);
+        assert_eq!(report.deleted, vec![seeded - kept, 0, 0]);
+        assert_eq!(history_count(&db).await, kept);
+    }
+
+    #[tokio::test]
+    async fn test_pruning_stops_when_cancelled() {
+        let db = setup_db().await;
+        let (seeded, _) = seed_minutely_history(&db).await;
+        let token = CancellationToken::new();
+        token.cancel();
+        let pruner = HistoryPruning::new(db.clone(), test_policy())
+            .with_batch_size(500)
+            .with_cancellation(token);
+
+        let report = pruner.run_pruning().await.unwrap();
+        assert!(report.interrupted);
+        assert_eq!(report.total_deleted(), 0);
+        assert_eq!(history_count(&db).await, seeded);
+    }
+
+    #[tokio::test]
+    async fn test_pruning_keeps_recent_rows_per_item() {
+        let db = setup_db().await;
+        let pruner = HistoryPruning::new(
+            db.clone(),
+            RetentionPolicy {
+                keep_recent: 3,
+                ..test_policy()
+            },
+        );
+
+        // Both items only have history from one hour 4 days ago: item 1 is sparse,
+        // item 2 has rows past the floor
+        let t = (Utc::now() - ChronoDuration::days(4))
+            .duration_trunc(ChronoDuration::hours(1))
+            .unwrap();
+        for (item, rows) in [(1, 4), (2, 10)] {
+            for minute in 0..rows {
+                db.query("CREATE item_history SET item = type::thing('item', $item), timestamp = <datetime>$t, buy_price = 10, sell_price = 11, buy_quantity = 100, sell_quantity = 100")
+                    .bind(("item", item))
+                    .bind(("t", t + ChronoDuration::minutes(minute)))
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let report = pruner.run_pruning().await.unwrap();
+        // Item 1 keeps its earliest row and the 3 newest; item 2 loses the 6 between
+        assert_eq!(report.deleted, vec![6, 0, 0]);
+
+        let remaining: Vec<HistoryRecord> = db
+            .query("SELECT * FROM item_history WHERE item = item:2")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        let mut minutes: Vec<i64> = remaining
+            .iter()
+            .map(|r| (r.timestamp - t).num_minutes())
+            .collect();
+        minutes.sort();
+        assert_eq!(minutes, vec![0, 7, 8, 9]);
+        assert_eq!(history_count(&db).await, 8);
+    }
+
+    #[tokio::test]
+    async fn test_pruning_rebuilds_indexes_when_enabled() {
+        let db = setup_db().await;
+        let hour = (Utc::now() - ChronoDuration::days(4))
+            .duration_trunc(ChronoDuration::hours(1))
+            .unwrap();
+        for minutes in [0, 10] {
+            db.query("CREATE item_history SET item = item:123, timestamp = <datetime>$t, buy_price = 10, sell_price = 11, buy_quantity = 100, sell_quantity = 100")
+                .bind(("t", hour + ChronoDuration::minutes(minutes)))
+                .await
+                .unwrap();
+        }
+
+        let pruner = HistoryPruning::new(db.clone(), test_policy()).with_index_rebuild(true);
+        let report = pruner.run_pruning().await.unwrap();
+        assert_eq!(report.total_deleted(), 1);
+        assert!(report.indexes_rebuilt);
+
+        // Nothing left to remove, so nothing to rebuild
+        let report = pruner.run_pruning().await.unwrap();
+        assert!(!report.indexes_rebuilt);
+
+        let remaining: Vec<HistoryRecord> = db
+            .query("SELECT * FROM item_history WHERE item = item:123")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_pruning_keeps_bucket_extremes() {
+        let db = setup_db().await;
+        let pruner = HistoryPruning::new(
+            db.clone(),
+            RetentionPolicy {
+                mode: PruneMode::Extremes,
+                ..test_policy()
+            },
+        );
+
+        // One old hour with a spike up at :20 and a dip at :40, matched again at :50,
+        // between flat prices
+        let hour = (Utc::now() - ChronoDuration::days(4))
+            .duration_trunc(ChronoDuration::hours(1))
+            .unwrap();
+        for (minutes, sell_price) in [
+            (0, 100),
+            (10, 100),
+            (20, 180),
+            (30, 100),
+            (40, 60),
+            (50, 60),
+        ] {
+            db.query("CREATE item_history SET item = item:123, timestamp = <datetime>$t, buy_price = 50, sell_price = $sell, buy_quantity = 100, sell_quantity = 100")
+                .bind(("t", hour + ChronoDuration::minutes(minutes)))
+                .bind(("sell", sell_price))
+                .await
+                .unwrap();
+        }
+
+        let report = pruner.run_pruning().await.unwrap();
+        assert_eq!(report.deleted, vec![3, 0, 0]);
+
+        let remaining: Vec<HistoryRecord> = db
+            .query("SELECT * FROM item_history")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        let mut kept: Vec<(i64, i64)> = remaining
+            .iter()
+            .map(|r| ((r.timestamp - hour).num_minutes(), r.sell_price))
+            .collect();
+        kept.sort();
+        assert_eq!(kept, vec![(0, 100), (20, 180), (40, 60)]);
+    }
 }
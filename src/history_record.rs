@@ -1,8 +1,12 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use surrealdb::RecordId;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Records order chronologically: by `timestamp` first, then by `item`, so a
+/// plain `sort()` puts history in time order. The prices only break ties, which
+/// keeps the ordering consistent with `==`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct HistoryRecord {
     // This is the link! It points to "item:⟨19684⟩"
     pub item: RecordId,
@@ -13,6 +17,24 @@ pub struct HistoryRecord {
     pub sell_quantity: i64,
 }
 
+impl Ord for HistoryRecord {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.timestamp
+            .cmp(&other.timestamp)
+            .then_with(|| self.item.cmp(&other.item))
+            .then_with(|| self.buy_price.cmp(&other.buy_price))
+            .then_with(|| self.sell_price.cmp(&other.sell_price))
+            .then_with(|| self.buy_quantity.cmp(&other.buy_quantity))
+            .then_with(|| self.sell_quantity.cmp(&other.sell_quantity))
+    }
+}
+
+impl PartialOrd for HistoryRecord {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RawPrice {
     pub id: u32,
@@ -38,28 +60,60 @@ impl HistoryRecord {
         }
     }
 
+    /// Parses a gw2bltc chart row using the current column layout.
     pub fn from_bltc(id: u32, data: &[i64]) -> Option<Self> {
-        if data.len() < 5 {
+        Self::from_bltc_with(id, data, &BltcColumns::default())
+    }
+
+    /// Parses a gw2bltc chart row laid out as `columns`. Rows of the wrong length
+    /// (the layout changed) or with negative values are rejected.
+    pub fn from_bltc_with(id: u32, data: &[i64], columns: &BltcColumns) -> Option<Self> {
+        if data.len() != columns.row_len {
             return None;
         }
-        // Index 0: timestamp (Unix Epoch Seconds)
-        // Index 1: sell_price
-        // Index 2: buy_price
-        // Index 3: supply (sell_quantity)
-        // Index 4: demand (buy_quantity)
+        let value = |index: usize| data.get(index).copied().filter(|v| *v >= 0);
 
-        let timestamp = DateTime::from_timestamp(data[0], 0)?;
+        let timestamp = DateTime::from_timestamp(value(columns.timestamp)?, 0)?;
 
         Some(Self {
             item: RecordId::from(("item", id.to_string())),
             timestamp,
-            sell_price: data[1],
-            buy_price: data[2],
-            sell_quantity: data[3],
-            buy_quantity: data[4],
+            sell_price: value(columns.sell_price)?,
+            buy_price: value(columns.buy_price)?,
+            sell_quantity: value(columns.sell_quantity)?,
+            buy_quantity: value(columns.buy_quantity)?,
         })
     }
 }
+
+/// Where each field sits in a gw2bltc `/api/tp/chart/{id}` row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BltcColumns {
+    /// Exact number of columns in a row
+    pub row_len: usize,
+    /// Unix epoch seconds
+    pub timestamp: usize,
+    pub sell_price: usize,
+    pub buy_price: usize,
+    /// Supply
+    pub sell_quantity: usize,
+    /// Demand
+    pub buy_quantity: usize,
+}
+
+impl Default for BltcColumns {
+    /// `[timestamp, sell_price, buy_price, supply, demand]`
+    fn default() -> Self {
+        Self {
+            row_len: 5,
+            timestamp: 0,
+            sell_price: 1,
+            buy_price: 2,
+            sell_quantity: 3,
+            buy_quantity: 4,
+        }
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,6 +143,41 @@ mod tests {
         assert_eq!(record.timestamp, now);
     }
 
+    #[test]
+    fn test_history_records_sort_chronologically() {
+        let record = |id: u32, secs: i64| HistoryRecord {
+            item: RecordId::from(("item", id.to_string())),
+            timestamp: Utc.timestamp_opt(secs, 0).unwrap(),
+            buy_price: 50,
+            sell_price: 60,
+            buy_quantity: 1,
+            sell_quantity: 1,
+        };
+        let mut records = [
+            record(2, 300),
+            record(1, 100),
+            record(3, 200),
+            record(1, 300),
+            record(2, 100),
+        ];
+        records.sort();
+
+        let order: Vec<(i64, String)> = records
+            .iter()
+            .map(|r| (r.timestamp.timestamp(), r.item.to_string()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                (100, "item:⟨1⟩".to_string()),
+                (100, "item:⟨2⟩".to_string()),
+                (200, "item:⟨3⟩".to_string()),
+                (300, "item:⟨1⟩".to_string()),
+                (300, "item:⟨2⟩".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn test_history_record_from_bltc() {
         let id = 19684;
@@ -111,4 +200,30 @@ mod tests {
         let record = HistoryRecord::from_bltc(id, &data);
         assert!(record.is_none());
     }
+
+    #[test]
+    fn test_history_record_from_bltc_rejects_changed_layout() {
+        let id = 19684;
+        // An extra column means the layout changed; don't guess at the mapping
+        let data = vec![1735689600, 60, 50, 200, 100, 7];
+        assert!(HistoryRecord::from_bltc(id, &data).is_none());
+
+        // ...unless told how to read it
+        let columns = BltcColumns {
+            row_len: 6,
+            ..BltcColumns::default()
+        };
+        let record = HistoryRecord::from_bltc_with(id, &data, &columns).unwrap();
+        assert_eq!(record.sell_price, 60);
+        assert_eq!(record.buy_quantity, 100);
+    }
+
+    #[test]
+    fn test_history_record_from_bltc_rejects_negative_values() {
+        let data = vec![1735689600, -60, 50, 200, 100];
+        assert!(HistoryRecord::from_bltc(19684, &data).is_none());
+
+        let data = vec![-1, 60, 50, 200, 100];
+        assert!(HistoryRecord::from_bltc(19684, &data).is_none());
+    }
 }
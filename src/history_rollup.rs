@@ -0,0 +1,308 @@
+use crate::sync_status;
+use chrono::{DateTime, Duration as ChronoDuration, DurationRound, Utc};
+use std::time::Duration;
+use surrealdb::Surreal;
+use surrealdb::engine::any::Any;
+use tokio::time::interval;
+use tokio_util::sync::CancellationToken;
+
+// One candle per item for the hour, keyed by [item, hour] so a rerun overwrites it.
+// Open/close are the first/last row by time, volumes the mean listed quantity.
+const ROLLUP_QUERY: &str = "
+    LET $start = <datetime>$hour;
+    LET $end = $start + 1h;
+    LET $groups = (SELECT item FROM item_history WHERE recorded_at >= $start AND recorded_at < $end GROUP BY item);
+    FOR $g IN $groups {
+        LET $rows = (SELECT buy_price, sell_price, buy_quantity, sell_quantity, <datetime>timestamp AS ts
+            FROM item_history
+            WHERE item = $g.item AND recorded_at >= $start AND recorded_at < $end
+            ORDER BY ts);
+        UPSERT type::thing('item_history_1h', [$g.item, $start]) CONTENT {
+            item: $g.item,
+            bucket_start: $start,
+            buy_open: $rows[0].buy_price,
+            buy_high: math::max($rows.buy_price),
+            buy_low: math::min($rows.buy_price),
+            buy_close: array::last($rows).buy_price,
+            sell_open: $rows[0].sell_price,
+            sell_high: math::max($rows.sell_price),
+            sell_low: math::min($rows.sell_price),
+            sell_close: array::last($rows).sell_price,
+            buy_quantity: math::mean($rows.buy_quantity),
+            sell_quantity: math::mean($rows.sell_quantity),
+            samples: array::len($rows),
+        };
+    };
+    RETURN array::len($groups);";
+
+/// Hours one run rolls up at most when catching up after downtime. After a
+/// longer outage the hours skipped are recorded as a gap in the coverage; raw
+/// history answers for them.
+const MAX_CATCH_UP_HOURS: i64 = 7 * 24;
+
+/// The hours `item_history_1h` holds candles for, from `from` up to (not
+/// including) `to`, less the `gaps` skipped after long outages. Hours outside it
+/// have to be read from raw history.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct RollupCoverage {
+    #[serde(rename = "rolled_up_from")]
+    pub from: DateTime<Utc>,
+    #[serde(rename = "rolled_up_to")]
+    pub to: DateTime<Utc>,
+    #[serde(rename = "rolled_up_gaps", default)]
+    pub gaps: Vec<RollupGap>,
+}
+
+/// Hours from `from` up to (not including) `to` the rollup never covered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RollupGap {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+/// The span the rollup has covered so far, or `None` before its first run.
+pub async fn coverage(db: &Surreal<Any>) -> surrealdb::Result<Option<RollupCoverage>> {
+    let mut covered: Vec<RollupCoverage> = db
+        .query(
+            "SELECT <datetime>rolled_up_from AS rolled_up_from, <datetime>rolled_up_to AS rolled_up_to,
+                    rolled_up_gaps OR [] AS rolled_up_gaps
+                FROM type::thing('sync_status', $job) WHERE rolled_up_to != NONE",
+        )
+        .bind(("job", sync_status::HISTORY_ROLLUP))
+        .await?
+        .take(0)?;
+    Ok(covered.pop())
+}
+
+/// Rolls raw `item_history` up into hourly OHLC candles in `item_history_1h`, so
+/// long-range charts don't have to read raw rows. Raw history is left alone.
+#[derive(Clone)]
+pub struct HistoryRollup {
+    db: Surreal<Any>,
+}
+
+impl HistoryRollup {
+    pub fn new(db: Surreal<Any>) -> Self {
+        Self { db }
+    }
+
+    /// Rolls up every completed hour since the last run, so downtime leaves no
+    /// holes in the candles.
+    pub async fn run_rollup(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let result = self.catch_up(Utc::now()).await;
+        sync_status::record_outcome(&self.db, sync_status::HISTORY_ROLLUP, result).await
+    }
+
+    /// Rolls up the hours after the covered span up to the last one completed
+    /// before `now`, oldest first, extending the span after each. The first run
+    /// starts with the last completed hour alone; hours beyond the catch-up
+    /// window are skipped and recorded as a gap.
+    async fn catch_up(&self, now: DateTime<Utc>) -> Result<usize, Box<dyn std::error::Error>> {
+        let last = now.duration_trunc(ChronoDuration::hours(1))? - ChronoDuration::hours(1);
+        let earliest = last - ChronoDuration::hours(MAX_CATCH_UP_HOURS - 1);
+        let (from, mut hour, gaps) = match coverage(&self.db).await? {
+            Some(covered) if covered.to >= earliest => (covered.from, covered.to, covered.gaps),
+            Some(mut covered) => {
+                covered.gaps.push(RollupGap {
+                    from: covered.to,
+                    to: earliest,
+                });
+                (covered.from, earliest, covered.gaps)
+            }
+            None => (last, last, Vec::new()),
+        };
+
+        let mut candles = 0;
+        while hour <= last {
+            candles += self.rollup_hour(hour).await?;
+            hour += ChronoDuration::hours(1);
+            self.db
+                .query(
+                    "UPSERT type::thing('sync_status', $job) MERGE {
+                        job: $job, rolled_up_from: <datetime>$from, rolled_up_to: <datetime>$to,
+                        rolled_up_gaps: $gaps
+                    }",
+                )
+                .bind(("job", sync_status::HISTORY_ROLLUP))
+                .bind(("from", from))
+                .bind(("to", hour))
+                .bind(("gaps", gaps.clone()))
+                .await?
+                .check()?;
+        }
+        Ok(candles)
+    }
+
+    /// Writes the candles of the hour starting at `hour`, replacing any written
+    /// before. Returns how many items had history in that hour.
+    pub async fn rollup_hour(
+        &self,
+        hour: DateTime<Utc>,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let hour = hour.duration_trunc(ChronoDuration::hours(1))?;
+        let mut response = self
+            .db
+            .query(ROLLUP_QUERY)
+            .bind(("hour", hour))
+            .await?
+            .check()?;
+        let last = response.num_statements() - 1;
+        let candles = response.take::<Option<u64>>(last)?.unwrap_or(0) as usize;
+        println!("History rollup: {} candles for {}.", candles, hour);
+        Ok(candles)
+    }
+
+    pub async fn spawn(self, interval_duration: Duration, token: CancellationToken) {
+        let mut interval = interval(interval_duration);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.run_rollup().await {
+                        eprintln!("History rollup error: {}", e);
+                    }
+                }
+                _ = token.cancelled() => {
+                    println!("History rollup worker shutting down...");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use surrealdb::engine::any::connect;
+
+    async fn setup_db() -> Surreal<Any> {
+        let db = connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        crate::migrate(&db).await.unwrap();
+        db
+    }
+
+    async fn seed(db: &Surreal<Any>, t: DateTime<Utc>, buy_price: i64, sell_price: i64) {
+        db.query("CREATE item_history SET item = item:123, timestamp = $t, buy_price = $buy, sell_price = $sell, buy_quantity = 100, sell_quantity = 200")
+            .bind(("t", t))
+            .bind(("buy", buy_price))
+            .bind(("sell", sell_price))
+            .await
+            .unwrap();
+    }
+
+    async fn candles(db: &Surreal<Any>) -> Vec<serde_json::Value> {
+        db.query("SELECT buy_open, buy_high, buy_low, buy_close, sell_open, sell_close, sell_high, sell_low, samples, buy_quantity FROM item_history_1h")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rollup_aggregates_hour() {
+        let db = setup_db().await;
+        let hour =
+            Utc::now().duration_trunc(ChronoDuration::hours(1)).unwrap() - ChronoDuration::hours(2);
+        // The low in the middle, plus a row in the next hour that stays out
+        for (minutes, buy_price, sell_price) in [
+            (0, 10, 20),
+            (10, 15, 30),
+            (20, 8, 25),
+            (30, 12, 22),
+            (60, 99, 99),
+        ] {
+            seed(
+                &db,
+                hour + ChronoDuration::minutes(minutes),
+                buy_price,
+                sell_price,
+            )
+            .await;
+        }
+
+        let written = HistoryRollup::new(db.clone())
+            .rollup_hour(hour + ChronoDuration::minutes(5))
+            .await
+            .unwrap();
+        assert_eq!(written, 1);
+
+        let candles = candles(&db).await;
+        assert_eq!(candles.len(), 1);
+        let candle = &candles[0];
+        assert_eq!(candle["buy_open"], 10);
+        assert_eq!(candle["buy_high"], 15);
+        assert_eq!(candle["buy_low"], 8);
+        assert_eq!(candle["buy_close"], 12);
+        assert_eq!(candle["sell_open"], 20);
+        assert_eq!(candle["sell_close"], 22);
+        assert_eq!(candle["sell_high"], 30);
+        assert_eq!(candle["sell_low"], 20);
+        assert_eq!(candle["samples"], 4);
+        assert_eq!(candle["buy_quantity"].as_f64(), Some(100.0));
+    }
+
+    #[tokio::test]
+    async fn test_rollup_rerun_overwrites() {
+        let db = setup_db().await;
+        let rollup = HistoryRollup::new(db.clone());
+        let hour =
+            Utc::now().duration_trunc(ChronoDuration::hours(1)).unwrap() - ChronoDuration::hours(1);
+        seed(&db, hour, 10, 20).await;
+        rollup.rollup_hour(hour).await.unwrap();
+
+        // A late row for the same hour lands, e.g. from history recovery
+        seed(&db, hour + ChronoDuration::minutes(30), 5, 40).await;
+        rollup.rollup_hour(hour).await.unwrap();
+
+        let candles = candles(&db).await;
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0]["samples"], 2);
+        assert_eq!(candles[0]["buy_low"], 5);
+        assert_eq!(candles[0]["sell_close"], 40);
+    }
+
+    #[tokio::test]
+    async fn test_rollup_catches_up_missed_hours() {
+        let db = setup_db().await;
+        let rollup = HistoryRollup::new(db.clone());
+        let at = |t: &str| t.parse::<DateTime<Utc>>().unwrap();
+        seed(&db, at("2025-01-01T02:10:00Z"), 10, 20).await;
+        seed(&db, at("2025-01-01T05:10:00Z"), 10, 20).await;
+
+        // The first run only rolls up the last completed hour
+        let written = rollup.catch_up(at("2025-01-01T06:30:00Z")).await.unwrap();
+        assert_eq!(written, 1);
+        let covered = coverage(&db).await.unwrap().unwrap();
+        assert_eq!(covered.from, at("2025-01-01T05:00:00Z"));
+        assert_eq!(covered.to, at("2025-01-01T06:00:00Z"));
+
+        // The scraper was down for three runs; every missed hour is filled in
+        seed(&db, at("2025-01-01T06:10:00Z"), 11, 21).await;
+        seed(&db, at("2025-01-01T08:10:00Z"), 12, 22).await;
+        let written = rollup.catch_up(at("2025-01-01T09:30:00Z")).await.unwrap();
+        assert_eq!(written, 2);
+        let covered = coverage(&db).await.unwrap().unwrap();
+        assert_eq!(covered.from, at("2025-01-01T05:00:00Z"));
+        assert_eq!(covered.to, at("2025-01-01T09:00:00Z"));
+        assert_eq!(candles(&db).await.len(), 3);
+
+        // Nothing left to do until the next hour completes
+        let written = rollup.catch_up(at("2025-01-01T09:59:00Z")).await.unwrap();
+        assert_eq!(written, 0);
+
+        // After a longer outage the span keeps its start, and the hours before the
+        // catch-up window are recorded as a gap
+        rollup.catch_up(at("2025-02-01T00:30:00Z")).await.unwrap();
+        let covered = coverage(&db).await.unwrap().unwrap();
+        assert_eq!(covered.from, at("2025-01-01T05:00:00Z"));
+        assert_eq!(covered.to, at("2025-02-01T00:00:00Z"));
+        assert_eq!(
+            covered.gaps,
+            vec![RollupGap {
+                from: at("2025-01-01T09:00:00Z"),
+                to: at("2025-02-01T00:00:00Z") - ChronoDuration::hours(MAX_CATCH_UP_HOURS),
+            }]
+        );
+    }
+}
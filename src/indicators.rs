@@ -0,0 +1,159 @@
+// Technical indicators over price series, oldest value first.
+
+/// Simple moving average: the mean of each value and the `window - 1` before
+/// it. The first `window - 1` positions have no full window and are `None`, as
+/// is everything when `window` is 0.
+pub fn sma(values: &[f64], window: usize) -> Vec<Option<f64>> {
+    let mut averages = Vec::with_capacity(values.len());
+    let mut sum = 0.0;
+    for (i, value) in values.iter().enumerate() {
+        sum += value;
+        if window > 0 && i >= window {
+            sum -= values[i - window];
+        }
+        averages.push((window > 0 && i + 1 >= window).then(|| sum / window as f64));
+    }
+    averages
+}
+
+/// Exponential moving average with smoothing `2 / (window + 1)`, seeded with
+/// the SMA of the first full window so the leading `None`s line up with `sma`.
+pub fn ema(values: &[f64], window: usize) -> Vec<Option<f64>> {
+    if window == 0 || values.len() < window {
+        return vec![None; values.len()];
+    }
+    let alpha = 2.0 / (window as f64 + 1.0);
+    let mut averages = vec![None; window - 1];
+    let mut current = values[..window].iter().sum::<f64>() / window as f64;
+    averages.push(Some(current));
+    for value in &values[window..] {
+        current += alpha * (value - current);
+        averages.push(Some(current));
+    }
+    averages
+}
+
+/// Fewer points than this say nothing useful about how a price swings.
+pub const MIN_VOLATILITY_SAMPLES: usize = 3;
+
+/// Population standard deviation divided by the mean, so items at different
+/// price levels compare. `None` below `MIN_VOLATILITY_SAMPLES` points or for a
+/// non-positive mean.
+pub fn coefficient_of_variation(values: &[f64]) -> Option<f64> {
+    if values.len() < MIN_VOLATILITY_SAMPLES {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if mean <= 0.0 {
+        return None;
+    }
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    Some(variance.sqrt() / mean)
+}
+
+/// Fewer trailing points than this can't tell a spike from ordinary movement.
+pub const MIN_ANOMALY_SAMPLES: usize = 10;
+
+/// How many standard deviations `latest` lies above (positive) or below the
+/// mean of `trailing`. Prices move in whole copper, so the deviation is floored
+/// at 1 and a jump off a flat series still measures. `None` below
+/// `MIN_ANOMALY_SAMPLES` trailing points.
+pub fn z_score(trailing: &[f64], latest: f64) -> Option<f64> {
+    if trailing.len() < MIN_ANOMALY_SAMPLES {
+        return None;
+    }
+    let n = trailing.len() as f64;
+    let mean = trailing.iter().sum::<f64>() / n;
+    let variance = trailing.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+    Some((latest - mean) / variance.sqrt().max(1.0))
+}
+
+/// Volume-weighted average price: each price counts in proportion to the
+/// quantity listed at it, so zero-quantity samples add nothing. `None` when the
+/// whole series has no volume.
+pub fn vwap(prices: &[f64], quantities: &[f64]) -> Option<f64> {
+    let (weighted, volume) =
+        prices
+            .iter()
+            .zip(quantities)
+            .fold((0.0, 0.0), |(weighted, volume), (price, quantity)| {
+                let quantity = quantity.max(0.0);
+                (weighted + price * quantity, volume + quantity)
+            });
+    (volume > 0.0).then(|| weighted / volume)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[Option<f64>], expected: &[Option<f64>]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            match (a, e) {
+                (Some(a), Some(e)) => assert!((a - e).abs() < 1e-9, "{} != {}", a, e),
+                _ => assert_eq!(a, e),
+            }
+        }
+    }
+
+    #[test]
+    fn test_sma() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert_close(
+            &sma(&values, 3),
+            &[None, None, Some(2.0), Some(3.0), Some(4.0), Some(5.0)],
+        );
+        assert_close(&sma(&values, 1), &values.map(Some));
+        // Windows longer than the series never fill
+        assert_close(&sma(&values[..2], 3), &[None, None]);
+        assert_close(&sma(&values[..2], 0), &[None, None]);
+    }
+
+    #[test]
+    fn test_ema() {
+        // alpha = 0.5, seeded with (2 + 4 + 6) / 3 = 4
+        let values = [2.0, 4.0, 6.0, 8.0, 4.0];
+        assert_close(
+            &ema(&values, 3),
+            &[None, None, Some(4.0), Some(6.0), Some(5.0)],
+        );
+        assert_close(&ema(&values[..2], 3), &[None, None]);
+        assert!(ema(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn test_coefficient_of_variation() {
+        let flat = [500.0; 10];
+        assert!(coefficient_of_variation(&flat).unwrap() < 1e-9);
+
+        // Mean 100, population std dev 80
+        let swinging = [20.0, 180.0, 20.0, 180.0];
+        assert!((coefficient_of_variation(&swinging).unwrap() - 0.8).abs() < 1e-9);
+
+        assert_eq!(coefficient_of_variation(&[100.0, 200.0]), None);
+        assert_eq!(coefficient_of_variation(&[0.0, 0.0, 0.0]), None);
+    }
+
+    #[test]
+    fn test_z_score() {
+        // Mean 100, population std dev 10
+        let trailing = [90.0, 110.0].repeat(5);
+        assert!((z_score(&trailing, 105.0).unwrap() - 0.5).abs() < 1e-9);
+        assert!((z_score(&trailing, 60.0).unwrap() + 4.0).abs() < 1e-9);
+
+        // A flat series still measures a jump, one copper per deviation
+        assert_eq!(z_score(&[500.0; 10], 520.0), Some(20.0));
+        assert_eq!(z_score(&[500.0; 9], 5000.0), None);
+    }
+
+    #[test]
+    fn test_vwap() {
+        // (100 * 10 + 200 * 30 + 400 * 0) / 40
+        let prices = [100.0, 200.0, 400.0];
+        assert_eq!(vwap(&prices, &[10.0, 30.0, 0.0]), Some(175.0));
+        assert_eq!(vwap(&prices, &[0.0, 0.0, 0.0]), None);
+        assert_eq!(vwap(&[], &[]), None);
+    }
+}
@@ -1,19 +1,201 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::hash::{DefaultHasher, Hasher};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ItemDefinition {
     pub gw2_id: i32,
     pub name: String,
 
+    // Display
+    pub icon: Option<String>,
+    pub chat_link: String,
+    pub description: Option<String>,
+
     // AI Features (Categorical)
-    pub type_: String,
-    pub rarity: String,
+    pub type_: ItemType,
+    pub rarity: Rarity,
     pub level: i32,
     pub vendor_value: i64,
 
+    // Stats (Weapon and Armor only, empty for other types)
+    pub min_power: Option<i32>,
+    pub max_power: Option<i32>,
+    pub defense: Option<i32>,
+    pub infusion_slots: Option<u32>,
+    pub attributes: Vec<ItemAttribute>,
+
     // Logic Filters (Booleans are faster than String Arrays)
     pub is_tradeable: bool, // Computed from 'flags' during ingest
+
+    // Kept verbatim for filters the boolean can't express (binding kind, profession)
+    pub flags: Vec<String>,
+    pub restrictions: Vec<String>,
+
+    // Upgrade paths (e.g. attuning or infusing ascended gear), as linked item ids
+    pub upgrades_into: Vec<ItemUpgrade>,
+    pub upgrades_from: Vec<ItemUpgrade>,
+
+    // Hash of every other field, so item sync can skip unchanged definitions
+    pub content_hash: String,
+}
+
+/// Item quality as the GW2 API names it. Values outside the known set
+/// deserialize as `Unknown` instead of failing the item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, utoipa::ToSchema)]
+pub enum Rarity {
+    Junk,
+    Basic,
+    Fine,
+    Masterwork,
+    Rare,
+    Exotic,
+    Ascended,
+    Legendary,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Item type as the GW2 API names it, serialized as that same string. Types
+/// added to the API later are kept verbatim in `Other`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ItemType {
+    Armor,
+    Back,
+    Bag,
+    Consumable,
+    Container,
+    CraftingMaterial,
+    Gathering,
+    Gizmo,
+    JadeTechModule,
+    Key,
+    MiniPet,
+    PowerCore,
+    Relic,
+    Tool,
+    Trait,
+    Trinket,
+    Trophy,
+    UpgradeComponent,
+    Weapon,
+    Other(String),
+}
+
+impl ItemType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Armor => "Armor",
+            Self::Back => "Back",
+            Self::Bag => "Bag",
+            Self::Consumable => "Consumable",
+            Self::Container => "Container",
+            Self::CraftingMaterial => "CraftingMaterial",
+            Self::Gathering => "Gathering",
+            Self::Gizmo => "Gizmo",
+            Self::JadeTechModule => "JadeTechModule",
+            Self::Key => "Key",
+            Self::MiniPet => "MiniPet",
+            Self::PowerCore => "PowerCore",
+            Self::Relic => "Relic",
+            Self::Tool => "Tool",
+            Self::Trait => "Trait",
+            Self::Trinket => "Trinket",
+            Self::Trophy => "Trophy",
+            Self::UpgradeComponent => "UpgradeComponent",
+            Self::Weapon => "Weapon",
+            Self::Other(other) => other,
+        }
+    }
+}
+
+impl From<String> for ItemType {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "Armor" => Self::Armor,
+            "Back" => Self::Back,
+            "Bag" => Self::Bag,
+            "Consumable" => Self::Consumable,
+            "Container" => Self::Container,
+            "CraftingMaterial" => Self::CraftingMaterial,
+            "Gathering" => Self::Gathering,
+            "Gizmo" => Self::Gizmo,
+            "JadeTechModule" => Self::JadeTechModule,
+            "Key" => Self::Key,
+            "MiniPet" => Self::MiniPet,
+            "PowerCore" => Self::PowerCore,
+            "Relic" => Self::Relic,
+            "Tool" => Self::Tool,
+            "Trait" => Self::Trait,
+            "Trinket" => Self::Trinket,
+            "Trophy" => Self::Trophy,
+            "UpgradeComponent" => Self::UpgradeComponent,
+            "Weapon" => Self::Weapon,
+            _ => Self::Other(name),
+        }
+    }
+}
+
+impl From<ItemType> for String {
+    fn from(item_type: ItemType) -> Self {
+        match item_type {
+            ItemType::Other(other) => other,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemAttribute {
+    pub attribute: String,
+    pub modifier: i32,
+}
+
+/// One edge of an item's upgrade graph, pointing at the other item by gw2 id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemUpgrade {
+    /// "Attunement" or "Infusion"
+    pub upgrade: String,
+    pub item_id: u32,
+}
+
+impl ItemUpgrade {
+    // Unknown shapes are dropped rather than failing the item
+    fn parse_list(value: Option<Value>) -> Vec<Self> {
+        value
+            .and_then(|v| Vec::<Self>::deserialize(v).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// The stat-bearing part of a Weapon or Armor `details` payload. Weapons carry
+/// power and (usually zero) defense, armor only defense.
+#[derive(Debug, Default, Deserialize)]
+struct StatDetails {
+    min_power: Option<i32>,
+    max_power: Option<i32>,
+    defense: Option<i32>,
+    #[serde(default)]
+    infusion_slots: Vec<Value>,
+    infix_upgrade: Option<InfixUpgrade>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InfixUpgrade {
+    #[serde(default)]
+    attributes: Vec<ItemAttribute>,
+}
+
+impl StatDetails {
+    // Other item types keep their details unparsed; a malformed payload is
+    // treated the same rather than failing the whole item
+    fn parse(type_: &ItemType, details: Option<&Value>) -> Option<Self> {
+        match (type_, details) {
+            (ItemType::Weapon | ItemType::Armor, Some(details)) => Self::deserialize(details).ok(),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -21,9 +203,9 @@ pub struct RawItem {
     pub id: u32,
     pub name: String,
     pub description: Option<String>,
-    pub r#type: String,
+    pub r#type: ItemType,
     pub level: u32,
-    pub rarity: String,
+    pub rarity: Rarity,
     pub vendor_value: u32,
     pub default_skin: Option<u32>,
     pub game_types: Vec<String>,
@@ -42,16 +224,51 @@ impl From<RawItem> for ItemDefinition {
             .flags
             .iter()
             .any(|f| f == "AccountBound" || f == "SoulbindOnAcquire" || f == "NoSell");
+        if item.rarity == Rarity::Unknown {
+            eprintln!("Item {} has an unknown rarity, stored as Unknown.", item.id);
+        }
+        let stats = StatDetails::parse(&item.r#type, item.details.as_ref());
+        let infusion_slots = stats.as_ref().map(|s| s.infusion_slots.len() as u32);
+        let stats = stats.unwrap_or_default();
 
-        Self {
+        let mut def = Self {
             gw2_id: item.id as i32,
             name: item.name,
+            icon: item.icon,
+            chat_link: item.chat_link,
+            description: item.description,
             type_: item.r#type,
             rarity: item.rarity,
             level: item.level as i32,
             vendor_value: item.vendor_value as i64,
+            min_power: stats.min_power,
+            max_power: stats.max_power,
+            defense: stats.defense,
+            infusion_slots,
+            attributes: stats
+                .infix_upgrade
+                .map(|u| u.attributes)
+                .unwrap_or_default(),
             is_tradeable,
-        }
+            flags: item.flags,
+            restrictions: item.restrictions,
+            upgrades_into: ItemUpgrade::parse_list(item.upgrades_into),
+            upgrades_from: ItemUpgrade::parse_list(item.upgrades_from),
+            content_hash: String::new(),
+        };
+        def.content_hash = def.compute_hash();
+        def
+    }
+}
+
+impl ItemDefinition {
+    // Hashes the serialized definition (with an empty hash) so new fields are
+    // covered automatically. A changed hasher between builds only costs one full resync.
+    fn compute_hash(&self) -> String {
+        let json = serde_json::to_vec(self).expect("ItemDefinition always serializes");
+        let mut hasher = DefaultHasher::new();
+        hasher.write(&json);
+        format!("{:016x}", hasher.finish())
     }
 }
 #[cfg(test)]
@@ -64,9 +281,9 @@ mod tests {
             id: 123,
             name: "Test Item".to_string(),
             description: None,
-            r#type: "Weapon".to_string(),
+            r#type: ItemType::Weapon,
             level: 80,
-            rarity: "Exotic".to_string(),
+            rarity: Rarity::Exotic,
             vendor_value: 100,
             default_skin: None,
             game_types: vec!["PvE".to_string()],
@@ -82,10 +299,11 @@ mod tests {
         let def: ItemDefinition = raw.into();
         assert_eq!(def.gw2_id, 123);
         assert_eq!(def.name, "Test Item");
-        assert_eq!(def.type_, "Weapon");
-        assert_eq!(def.rarity, "Exotic");
+        assert_eq!(def.type_, ItemType::Weapon);
+        assert_eq!(def.rarity, Rarity::Exotic);
         assert_eq!(def.level, 80);
         assert_eq!(def.vendor_value, 100);
+        assert_eq!(def.chat_link, "[&AgH1AAA=]");
         assert!(def.is_tradeable);
     }
 
@@ -95,9 +313,9 @@ mod tests {
             id: 124,
             name: "Bound Item".to_string(),
             description: None,
-            r#type: "Armor".to_string(),
+            r#type: ItemType::Armor,
             level: 80,
-            rarity: "Ascended".to_string(),
+            rarity: Rarity::Ascended,
             vendor_value: 0,
             default_skin: None,
             game_types: vec!["PvE".to_string()],
@@ -120,9 +338,9 @@ mod tests {
             id: 125,
             name: "No Sell Item".to_string(),
             description: None,
-            r#type: "Trophy".to_string(),
+            r#type: ItemType::Trophy,
             level: 0,
-            rarity: "Basic".to_string(),
+            rarity: Rarity::Basic,
             vendor_value: 10,
             default_skin: None,
             game_types: vec!["PvE".to_string()],
@@ -138,4 +356,237 @@ mod tests {
         let def: ItemDefinition = raw.into();
         assert!(!def.is_tradeable);
     }
+
+    #[test]
+    fn test_flags_and_restrictions_round_trip() {
+        let raw = RawItem {
+            id: 128,
+            name: "Guardian Helm".to_string(),
+            description: None,
+            r#type: ItemType::Armor,
+            level: 80,
+            rarity: Rarity::Ascended,
+            vendor_value: 0,
+            default_skin: None,
+            game_types: vec![],
+            flags: vec!["SoulbindOnUse".to_string(), "HideSuffix".to_string()],
+            restrictions: vec!["Guardian".to_string()],
+            chat_link: "[&AgGAAAA=]".to_string(),
+            icon: None,
+            details: None,
+            upgrades_into: None,
+            upgrades_from: None,
+        };
+
+        let def: ItemDefinition = raw.into();
+        let stored: ItemDefinition =
+            serde_json::from_value(serde_json::to_value(&def).unwrap()).unwrap();
+        assert_eq!(stored.flags, vec!["SoulbindOnUse", "HideSuffix"]);
+        assert_eq!(stored.restrictions, vec!["Guardian"]);
+        // Bound on use is still tradeable until someone equips it
+        assert!(stored.is_tradeable);
+    }
+
+    #[test]
+    fn test_content_hash_tracks_changes() {
+        let raw = |name: &str| RawItem {
+            id: 126,
+            name: name.to_string(),
+            description: None,
+            r#type: ItemType::Trophy,
+            level: 0,
+            rarity: Rarity::Basic,
+            vendor_value: 10,
+            default_skin: None,
+            game_types: vec![],
+            flags: vec![],
+            restrictions: vec![],
+            chat_link: "[&AgH4AAA=]".to_string(),
+            icon: None,
+            details: None,
+            upgrades_into: None,
+            upgrades_from: None,
+        };
+
+        let a: ItemDefinition = raw("Shiny").into();
+        let b: ItemDefinition = raw("Shiny").into();
+        let renamed: ItemDefinition = raw("Shinier").into();
+        assert_eq!(a.content_hash, b.content_hash);
+        assert_ne!(a.content_hash, renamed.content_hash);
+    }
+
+    #[test]
+    fn test_rarity_known_variants() {
+        let known = [
+            ("Junk", Rarity::Junk),
+            ("Basic", Rarity::Basic),
+            ("Fine", Rarity::Fine),
+            ("Masterwork", Rarity::Masterwork),
+            ("Rare", Rarity::Rare),
+            ("Exotic", Rarity::Exotic),
+            ("Ascended", Rarity::Ascended),
+            ("Legendary", Rarity::Legendary),
+        ];
+        for (name, rarity) in known {
+            let parsed: Rarity = serde_json::from_value(Value::from(name)).unwrap();
+            assert_eq!(parsed, rarity);
+            assert_eq!(serde_json::to_value(rarity).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_rarity_unknown() {
+        let parsed: Rarity = serde_json::from_value(Value::from("Mythic")).unwrap();
+        assert_eq!(parsed, Rarity::Unknown);
+        // Case matters, as the API only sends capitalised names
+        let parsed: Rarity = serde_json::from_value(Value::from("exotic")).unwrap();
+        assert_eq!(parsed, Rarity::Unknown);
+        assert_eq!(serde_json::to_value(Rarity::Unknown).unwrap(), "Unknown");
+    }
+
+    #[test]
+    fn test_item_type_round_trip() {
+        let known = [
+            ("Weapon", ItemType::Weapon),
+            ("Armor", ItemType::Armor),
+            ("Trinket", ItemType::Trinket),
+            ("Consumable", ItemType::Consumable),
+            ("CraftingMaterial", ItemType::CraftingMaterial),
+            ("Container", ItemType::Container),
+            ("UpgradeComponent", ItemType::UpgradeComponent),
+        ];
+        for (name, item_type) in known {
+            let parsed: ItemType = serde_json::from_value(Value::from(name)).unwrap();
+            assert_eq!(parsed, item_type);
+            assert_eq!(item_type.as_str(), name);
+            assert_eq!(serde_json::to_value(item_type).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn test_item_type_other() {
+        let parsed: ItemType = serde_json::from_value(Value::from("Novelty")).unwrap();
+        assert_eq!(parsed, ItemType::Other("Novelty".to_string()));
+        assert_eq!(parsed.as_str(), "Novelty");
+        // Unrecognised types are written back exactly as the API sent them
+        assert_eq!(serde_json::to_value(parsed).unwrap(), "Novelty");
+    }
+
+    fn raw_with_details(r#type: &str, details: Value) -> RawItem {
+        RawItem {
+            id: 127,
+            name: "Stat Item".to_string(),
+            description: None,
+            r#type: r#type.to_string().into(),
+            level: 80,
+            rarity: Rarity::Exotic,
+            vendor_value: 330,
+            default_skin: None,
+            game_types: vec![],
+            flags: vec![],
+            restrictions: vec![],
+            chat_link: "[&AgH/AAA=]".to_string(),
+            icon: None,
+            details: Some(details),
+            upgrades_into: None,
+            upgrades_from: None,
+        }
+    }
+
+    #[test]
+    fn test_weapon_details() {
+        let def: ItemDefinition = raw_with_details(
+            "Weapon",
+            serde_json::json!({
+                "type": "Greatsword",
+                "damage_type": "Physical",
+                "min_power": 1045,
+                "max_power": 1155,
+                "defense": 0,
+                "infusion_slots": [],
+                "attribute_adjustment": 717.024,
+                "infix_upgrade": {
+                    "id": 161,
+                    "attributes": [
+                        { "attribute": "Power", "modifier": 179 },
+                        { "attribute": "Precision", "modifier": 128 }
+                    ]
+                },
+                "suffix_item_id": 24615
+            }),
+        )
+        .into();
+
+        assert_eq!(def.min_power, Some(1045));
+        assert_eq!(def.max_power, Some(1155));
+        assert_eq!(def.defense, Some(0));
+        assert_eq!(def.infusion_slots, Some(0));
+        assert_eq!(
+            def.attributes,
+            vec![
+                ItemAttribute {
+                    attribute: "Power".to_string(),
+                    modifier: 179
+                },
+                ItemAttribute {
+                    attribute: "Precision".to_string(),
+                    modifier: 128
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_armor_details() {
+        let def: ItemDefinition = raw_with_details(
+            "Armor",
+            serde_json::json!({
+                "type": "Coat",
+                "weight_class": "Heavy",
+                "defense": 363,
+                "infusion_slots": [{ "flags": ["Infusion"] }],
+                "attribute_adjustment": 242.04,
+                "infix_upgrade": {
+                    "id": 161,
+                    "attributes": [{ "attribute": "Power", "modifier": 67 }]
+                }
+            }),
+        )
+        .into();
+
+        assert_eq!(def.min_power, None);
+        assert_eq!(def.defense, Some(363));
+        assert_eq!(def.infusion_slots, Some(1));
+        assert_eq!(def.attributes.len(), 1);
+
+        // Other types keep no stats, even when their details look similar
+        let def: ItemDefinition =
+            raw_with_details("Trinket", serde_json::json!({ "defense": 5 })).into();
+        assert_eq!(def.defense, None);
+        assert_eq!(def.infusion_slots, None);
+        assert!(def.attributes.is_empty());
+    }
+
+    #[test]
+    fn test_upgrade_paths() {
+        let mut raw = raw_with_details("Armor", serde_json::json!({}));
+        raw.upgrades_into = Some(serde_json::json!([
+            { "upgrade": "Attunement", "item_id": 80254 }
+        ]));
+        raw.upgrades_from = Some(serde_json::json!([
+            { "upgrade": "Infusion", "item_id": 80248 },
+            { "unexpected": true }
+        ]));
+
+        let def: ItemDefinition = raw.into();
+        assert_eq!(
+            def.upgrades_into,
+            vec![ItemUpgrade {
+                upgrade: "Attunement".to_string(),
+                item_id: 80254
+            }]
+        );
+        // A malformed list is dropped as a whole
+        assert!(def.upgrades_from.is_empty());
+    }
 }
@@ -1,12 +1,44 @@
-use crate::gw2_api::Gw2Client;
+use crate::gw2_api::{CHUNK_CONCURRENCY, Gw2Client, chunk_ids};
+use crate::progress::{ProgressSender, SyncPhase, SyncProgress};
+use crate::sync_status;
+use futures::StreamExt;
 use surrealdb::Surreal;
 use surrealdb::engine::any::Any;
 use tokio_util::sync::CancellationToken;
 
+/// Share of the stored item count the API id list must reach before missing ids
+/// are trusted to mean removed items.
+const MIN_ID_LIST_SHARE: f64 = 0.9;
+
+/// Every this many scheduled runs is a full sync instead of an incremental one.
+const FULL_SYNC_EVERY: u32 = 7;
+
+/// What an `ItemSync` pass did, or would have done in dry-run mode.
+#[derive(Debug, Clone, Default)]
+pub struct ItemSyncReport {
+    /// Definitions fetched from the API
+    pub items_fetched: usize,
+    /// New or changed definitions written to the database
+    pub items_written: usize,
+}
+
+/// Order-independent fingerprint of an id list.
+fn id_checksum(ids: &[u32]) -> String {
+    let mut sorted = ids.to_vec();
+    sorted.sort_unstable();
+    let mut hasher = std::hash::DefaultHasher::new();
+    for id in sorted {
+        std::hash::Hasher::write_u32(&mut hasher, id);
+    }
+    format!("{:016x}", std::hash::Hasher::finish(&hasher))
+}
+
 #[derive(Clone)]
 pub struct ItemSync {
     db: Surreal<Any>,
     gw2: Gw2Client,
+    progress: ProgressSender,
+    dry_run: bool,
 }
 
 impl ItemSync {
@@ -14,57 +46,289 @@ impl ItemSync {
         Self {
             db,
             gw2: Gw2Client::new(),
+            progress: ProgressSender::default(),
+            dry_run: false,
         }
     }
 
-    pub async fn run_sync(&self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Fetches and diffs as usual but writes nothing, not even the sync status;
+    /// the returned report lists what would have been written.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Emits a `SyncProgress` event per synced chunk.
+    pub fn with_progress(mut self, sender: tokio::sync::mpsc::Sender<SyncProgress>) -> Self {
+        self.progress = ProgressSender::new(sender);
+        self
+    }
+
+    /// Uses `gw2` instead of a default client, e.g. to share its rate limit or
+    /// request localized names.
+    pub fn with_client(mut self, gw2: Gw2Client) -> Self {
+        self.gw2 = gw2;
+        self
+    }
+
+    /// Fetches definitions for ids not stored yet. Existing items aren't revisited;
+    /// see `run_full_sync`.
+    pub async fn run_sync(&self) -> Result<ItemSyncReport, Box<dyn std::error::Error>> {
+        self.sync(false, false).await
+    }
+
+    /// Fetches every definition and rewrites the ones whose hash changed, picking
+    /// up renames and other metadata corrections.
+    pub async fn run_full_sync(&self) -> Result<ItemSyncReport, Box<dyn std::error::Error>> {
+        self.sync(false, true).await
+    }
+
+    /// Like `run_full_sync`, but bypasses the client's cached id list.
+    pub async fn run_startup_sync(&self) -> Result<ItemSyncReport, Box<dyn std::error::Error>> {
+        self.sync(true, true).await
+    }
+
+    async fn sync(
+        &self,
+        force_refresh: bool,
+        full: bool,
+    ) -> Result<ItemSyncReport, Box<dyn std::error::Error>> {
+        let result = self.sync_items(force_refresh, full).await;
+        if self.dry_run {
+            return result;
+        }
+        sync_status::record_outcome(&self.db, sync_status::ITEM_SYNC, result).await
+    }
+
+    async fn sync_items(
+        &self,
+        force_refresh: bool,
+        full: bool,
+    ) -> Result<ItemSyncReport, Box<dyn std::error::Error>> {
         println!("Starting Item Sync...");
-        let all_ids = self.gw2.fetch_all_item_ids().await?;
+        let mut report = ItemSyncReport::default();
+        let all_ids = if force_refresh {
+            self.gw2.refresh_all_item_ids().await?
+        } else {
+            self.gw2.fetch_all_item_ids().await?
+        };
         println!("Found {} items.", all_ids.len());
 
-        // Check if we already have the same number of items in the database
-        let mut count_query = self.db.query("SELECT count() FROM item GROUP ALL").await?;
-        let db_count: Option<usize> = count_query
-            .take::<Option<serde_json::Value>>(0)?
-            .and_then(|v| v.get("count")?.as_u64())
-            .map(|c| c as usize);
-
-        if let Some(count) = db_count {
-            if count == all_ids.len() {
-                println!(
-                    "Skipping item upserts as count matches ({} items).",
-                    all_ids.len()
-                );
-                return Ok(());
+        // An unchanged id set means there's nothing new to fetch or flag as removed
+        let checksum = id_checksum(&all_ids);
+        if !full {
+            let stored = sync_status::fetch(&self.db, sync_status::ITEM_SYNC).await?;
+            if stored.and_then(|s| s.id_checksum).as_ref() == Some(&checksum) {
+                println!("Skipping item sync as the id list is unchanged.");
+                return Ok(report);
             }
         }
 
-        let chunks = all_ids.chunks(200);
-        for (i, chunk) in chunks.enumerate() {
+        let fetch_ids = if full {
+            all_ids.clone()
+        } else {
+            let stored: std::collections::HashSet<u32> = self
+                .db
+                .query("SELECT VALUE gw2_id FROM item WHERE gw2_id != NONE")
+                .await?
+                .take::<Vec<u32>>(0)?
+                .into_iter()
+                .collect();
+            let new_ids: Vec<u32> = all_ids
+                .iter()
+                .copied()
+                .filter(|id| !stored.contains(id))
+                .collect();
+            println!("{} items are new.", new_ids.len());
+            new_ids
+        };
+
+        // Chunks are fetched concurrently and upserted as they arrive; order doesn't matter
+        let chunks = chunk_ids(&fetch_ids, self.gw2.chunk_size());
+        let total = chunks.len();
+        let mut done = 0;
+        let mut complete = true;
+        let mut fetches = futures::stream::iter(chunks.into_iter().enumerate())
+            .map(|(i, chunk)| {
+                let gw2 = self.gw2.clone();
+                async move { (i, gw2.fetch_items_chunk(&chunk).await) }
+            })
+            .buffer_unordered(CHUNK_CONCURRENCY);
+        while let Some((i, chunk)) = fetches.next().await {
             if i % 10 == 0 {
                 println!("Syncing item chunk {}...", i + 1);
             }
-            let items = self.gw2.fetch_items_chunk(chunk).await?;
+            done += 1;
+            self.progress.report(SyncPhase::ItemSync, done, total);
+            // One bad chunk shouldn't stall the rest; what it fetched is stored and
+            // the rest of its ids are listed as failed
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    eprintln!("Item chunk {} failed: {}", i + 1, e);
+                    e.partial
+                }
+            };
+            if chunk.is_partial() {
+                complete = false;
+                // Missing ids have no stored hash, and new ids are fetched until they're
+                // stored, so these get retried next run
+                eprintln!(
+                    "Item chunk {} was partial: {} ids failed, {} of them omitted by the API.",
+                    i + 1,
+                    chunk.failed.len(),
+                    chunk.omitted.len()
+                );
+            }
+            report.items_fetched += chunk.items.len();
+            // Only definitions whose hash moved since the last sync are written
+            #[derive(serde::Deserialize)]
+            struct StoredHash {
+                gw2_id: i32,
+                content_hash: Option<String>,
+            }
+            let ids: Vec<i32> = chunk.items.iter().map(|item| item.gw2_id).collect();
+            let stored: std::collections::HashMap<i32, Option<String>> = self
+                .db
+                .query("SELECT gw2_id, content_hash FROM item WHERE gw2_id IN $ids")
+                .bind(("ids", ids))
+                .await?
+                .take::<Vec<StoredHash>>(0)?
+                .into_iter()
+                .map(|s| (s.gw2_id, s.content_hash))
+                .collect();
+            let items: Vec<_> = chunk
+                .items
+                .into_iter()
+                .filter(|item| stored.get(&item.gw2_id) != Some(&Some(item.content_hash.clone())))
+                .collect();
+
+            report.items_written += items.len();
 
             // Batch Upsert into SurrealDB
             // We use item:ID as the record ID
-            let _: surrealdb::Response = self
-                .db
-                .query("FOR $item IN $items { UPSERT type::thing('item', <string>$item.gw2_id) CONTENT $item; }")
-                .bind(("items", items))
-                .await?;
+            if !self.dry_run && !items.is_empty() {
+                let _: surrealdb::Response = self
+                    .db
+                    // MERGE keeps the price fields and first_seen of existing items
+                    .query(
+                        "FOR $item IN $items {
+                            UPSERT type::thing('item', <string>$item.gw2_id) MERGE $item;
+                            UPDATE type::thing('item', <string>$item.gw2_id) SET first_seen = first_seen OR $now;
+                        }",
+                    )
+                    .bind(("items", items))
+                    .bind(("now", chrono::Utc::now()))
+                    .await?;
+            }
+        }
+
+        if self.dry_run {
+            println!(
+                "Dry run: would write {} of {} fetched items.",
+                report.items_written, report.items_fetched
+            );
+            return Ok(report);
+        }
+        let flagged = self.flag_removed_items(&all_ids).await?;
+        // Only a complete pass may be skipped next time, or failed ids (or removals
+        // held back by the safety check) would never be retried
+        if complete && flagged {
+            sync_status::record_id_checksum(&self.db, sync_status::ITEM_SYNC, checksum).await?;
         }
 
         println!("Item sync complete.");
-        Ok(())
+        Ok(report)
+    }
+
+    /// Stamps `last_seen` on every listed item, and flags items the API no longer
+    /// lists as `is_removed` (clearing the flag on ones that came back). History is
+    /// kept, so nothing is deleted. Returns false when the id list looked too short
+    /// to trust and nothing was flagged.
+    async fn flag_removed_items(
+        &self,
+        all_ids: &[u32],
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        // Every listed item was seen, whether or not its definition changed. The
+        // records are addressed by id, so no row is checked against the whole list
+        let records = |ids: &[u32]| -> Vec<surrealdb::RecordId> {
+            ids.iter()
+                .map(|id| surrealdb::RecordId::from(("item", id.to_string())))
+                .collect()
+        };
+        self.db
+            .query("UPDATE $items SET last_seen = $now RETURN NONE")
+            .bind(("now", chrono::Utc::now()))
+            .bind(("items", records(all_ids)))
+            .await?
+            .check()?;
+
+        #[derive(serde::Deserialize)]
+        struct Listing {
+            gw2_id: u32,
+            is_removed: Option<bool>,
+        }
+        let stored: Vec<Listing> = self
+            .db
+            .query("SELECT gw2_id, is_removed FROM item WHERE gw2_id != NONE")
+            .await?
+            .take(0)?;
+
+        // A short or empty id list is far more likely an upstream glitch than
+        // ANet removing a large share of the game's items
+        if all_ids.is_empty() || (all_ids.len() as f64) < stored.len() as f64 * MIN_ID_LIST_SHARE {
+            eprintln!(
+                "API listed {} items against {} stored; not flagging removed items.",
+                all_ids.len(),
+                stored.len()
+            );
+            return Ok(false);
+        }
+
+        // Only items whose listing changed since the last sync are written
+        let listed: std::collections::HashSet<u32> = all_ids.iter().copied().collect();
+        let mut gone = Vec::new();
+        let mut back = Vec::new();
+        let mut removed = 0;
+        for item in stored {
+            let was_removed = item.is_removed == Some(true);
+            if !listed.contains(&item.gw2_id) {
+                removed += 1;
+                if !was_removed {
+                    gone.push(item.gw2_id);
+                }
+            } else if was_removed {
+                back.push(item.gw2_id);
+            }
+        }
+        if !gone.is_empty() || !back.is_empty() {
+            self.db
+                .query("UPDATE $gone SET is_removed = true RETURN NONE")
+                .query("UPDATE $back SET is_removed = false RETURN NONE")
+                .bind(("gone", records(&gone)))
+                .bind(("back", records(&back)))
+                .await?
+                .check()?;
+        }
+        if removed > 0 {
+            println!("{} items are no longer listed by the API.", removed);
+        }
+        Ok(true)
     }
 
     pub async fn spawn(self, interval_duration: std::time::Duration, token: CancellationToken) {
         let mut interval = tokio::time::interval(interval_duration);
+        let mut runs: u32 = 0;
         loop {
             tokio::select! {
                 _ = interval.tick() => {
-                    if let Err(e) = self.run_sync().await {
+                    runs = runs.wrapping_add(1);
+                    let result = if runs.is_multiple_of(FULL_SYNC_EVERY) {
+                        self.run_full_sync().await
+                    } else {
+                        self.run_sync().await
+                    };
+                    if let Err(e) = result {
                         eprintln!("Item sync error: {}", e);
                     }
                 }
@@ -107,7 +371,9 @@ mod tests {
                 "flags": ["Tradeable"],
                 "game_types": ["PvE"],
                 "restrictions": [],
-                "chat_link": "[&AgH1AAA=]"
+                "chat_link": "[&AgH1AAA=]",
+                "icon": "https://render.guildwars2.com/file/1.png",
+                "upgrades_into": [{ "upgrade": "Attunement", "item_id": 3 }]
             }),
             serde_json::json!({
                 "id": 2,
@@ -138,10 +404,7 @@ mod tests {
             .await;
 
         let gw2 = Gw2Client::with_urls(server.uri(), "".to_string());
-        let sync = ItemSync {
-            db: db.clone(),
-            gw2,
-        };
+        let sync = ItemSync::new(db.clone()).with_client(gw2);
 
         // 1. Run sync
         sync.run_sync().await.unwrap();
@@ -157,8 +420,557 @@ mod tests {
             .unwrap() as usize;
         assert_eq!(count, 2);
 
+        // Display fields are stored for the frontend
+        let item: Option<serde_json::Value> = db
+            .query("SELECT icon, chat_link, upgrades_into FROM ONLY item:⟨1⟩")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        let item = item.unwrap();
+        assert_eq!(item["icon"], "https://render.guildwars2.com/file/1.png");
+        assert_eq!(item["chat_link"], "[&AgH1AAA=]");
+        assert_eq!(
+            item["upgrades_into"],
+            serde_json::json!([{ "upgrade": "Attunement", "item_id": 3 }])
+        );
+
         // 3. Run again - should skip (verified by no more mock calls if we could, but here we just check it doesn't fail)
         sync.run_sync().await.unwrap();
         assert_eq!(count, 2);
     }
+
+    #[tokio::test]
+    async fn test_item_sync_flags_removed_items() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        let listed: Vec<u32> = (1..=10).collect();
+        let items: Vec<serde_json::Value> = listed
+            .iter()
+            .map(|id| {
+                serde_json::json!({
+                    "id": id,
+                    "name": format!("Item {}", id),
+                    "type": "Trophy",
+                    "level": 0,
+                    "rarity": "Basic",
+                    "vendor_value": 1,
+                    "flags": [],
+                    "game_types": [],
+                    "restrictions": [],
+                    "chat_link": "[&AgH1AAA=]"
+                })
+            })
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&listed))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_contains("ids", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(items))
+            .mount(&server)
+            .await;
+
+        let removed = |db: Surreal<Any>| async move {
+            let mut removed: Vec<i64> = db
+                .query("SELECT VALUE gw2_id FROM item WHERE is_removed = true")
+                .await
+                .unwrap()
+                .take(0)
+                .unwrap();
+            removed.sort();
+            removed
+        };
+        let sync = ItemSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+
+        // With a large share of the catalogue missing from the API, nothing is flagged
+        db.query("FOR $id IN 11..=30 { CREATE type::thing('item', <string>$id) SET gw2_id = $id, name = 'Gone' }")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        sync.run_sync().await.unwrap();
+        assert!(removed(db.clone()).await.is_empty());
+
+        // A single item the API stopped listing is flagged
+        db.query("DELETE item WHERE gw2_id > 11").await.unwrap();
+        sync.run_sync().await.unwrap();
+        assert_eq!(removed(db.clone()).await, vec![11]);
+
+        // Once the API lists it again, the flag is cleared
+        server.reset().await;
+        let relisted: Vec<u32> = (1..=11).collect();
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&relisted))
+            .mount(&server)
+            .await;
+        ItemSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()))
+            .run_sync()
+            .await
+            .unwrap();
+        assert!(removed(db.clone()).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_item_sync_fetches_only_new_ids() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        db.query("FOR $id IN 1..=8 { CREATE type::thing('item', <string>$id) SET gw2_id = $id, name = 'Known' }")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let item = |id: u32| {
+            serde_json::json!({
+                "id": id,
+                "name": format!("Item {}", id),
+                "type": "Trophy",
+                "level": 0,
+                "rarity": "Basic",
+                "vendor_value": 1,
+                "flags": [],
+                "game_types": [],
+                "restrictions": [],
+                "chat_link": "[&AgH1AAA=]"
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json((1..=10).collect::<Vec<u32>>()))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "9,10"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![item(9), item(10)]))
+            .expect(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        // Any other id request would mean stored items were fetched again
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_contains("ids", ""))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Vec::<u32>::new()))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let sync = ItemSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+        sync.run_sync().await.unwrap();
+
+        let count = db
+            .query("SELECT count() FROM item GROUP ALL")
+            .await
+            .unwrap()
+            .take::<Option<serde_json::Value>>(0)
+            .unwrap()
+            .and_then(|v| v.get("count")?.as_u64());
+        assert_eq!(count, Some(10));
+    }
+
+    #[tokio::test]
+    async fn test_item_sync_retries_omitted_items() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        let item = serde_json::json!({
+            "id": 1,
+            "name": "Item 1",
+            "type": "Trophy",
+            "level": 0,
+            "rarity": "Basic",
+            "vendor_value": 1,
+            "flags": [],
+            "game_types": [],
+            "restrictions": [],
+            "chat_link": "[&AgH1AAA=]"
+        });
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2]))
+            .mount(&server)
+            .await;
+        // Item 2 vanished between the id list and the detail request
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "1,2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![item]))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Vec::<u32>::new()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sync = ItemSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+        sync.run_sync().await.unwrap();
+
+        let count = db
+            .query("SELECT count() FROM item GROUP ALL")
+            .await
+            .unwrap()
+            .take::<Option<serde_json::Value>>(0)
+            .unwrap()
+            .and_then(|v| v.get("count")?.as_u64());
+        assert_eq!(count, Some(1));
+
+        // The stored count no longer matches, but the next run only asks for the gap
+        sync.run_sync().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_item_sync_continues_past_failed_chunk() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        let item = serde_json::json!({
+            "id": 1,
+            "name": "Item 1",
+            "type": "Trophy",
+            "level": 0,
+            "rarity": "Basic",
+            "vendor_value": 1,
+            "flags": [],
+            "game_types": [],
+            "restrictions": [],
+            "chat_link": "[&AgH1AAA=]"
+        });
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![item]))
+            .mount(&server)
+            .await;
+        // An unreadable body fails the whole chunk
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .mount(&server)
+            .await;
+
+        let client = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .chunk_size(1)
+            .build();
+        let sync = ItemSync::new(db.clone()).with_client(client);
+        sync.run_sync().await.unwrap();
+
+        let stored: Option<serde_json::Value> = db
+            .query("SELECT name FROM item WHERE gw2_id = 1")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(stored.unwrap()["name"], "Item 1");
+    }
+
+    #[tokio::test]
+    async fn test_item_sync_dry_run_writes_nothing() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        let item = serde_json::json!({
+            "id": 1,
+            "name": "Item 1",
+            "type": "Trophy",
+            "level": 0,
+            "rarity": "Basic",
+            "vendor_value": 1,
+            "flags": [],
+            "game_types": [],
+            "restrictions": [],
+            "chat_link": "[&AgH1AAA=]"
+        });
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![item]))
+            .mount(&server)
+            .await;
+
+        let sync = ItemSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()))
+            .with_dry_run(true);
+        let report = sync.run_sync().await.unwrap();
+        assert_eq!(report.items_fetched, 1);
+        assert_eq!(report.items_written, 1);
+
+        let tables: Option<serde_json::Value> =
+            db.query("INFO FOR DB").await.unwrap().take(0).unwrap();
+        assert_eq!(tables.unwrap()["tables"], serde_json::json!({}));
+    }
+
+    #[tokio::test]
+    async fn test_item_sync_notices_swapped_ids() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        let item = |id: u32| {
+            serde_json::json!({
+                "id": id,
+                "name": format!("Item {}", id),
+                "type": "Trophy",
+                "level": 0,
+                "rarity": "Basic",
+                "vendor_value": 1,
+                "flags": [],
+                "game_types": [],
+                "restrictions": [],
+                "chat_link": "[&AgH1AAA=]"
+            })
+        };
+        // Item 2 is replaced by item 3 after the first sync, keeping the count at 2
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2]))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 3]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "1,2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![item(1), item(2)]))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![item(3)]))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let gw2 = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .item_ids_ttl(std::time::Duration::ZERO)
+            .build();
+        let sync = ItemSync::new(db.clone()).with_client(gw2);
+        sync.run_sync().await.unwrap();
+        // Same id set: skipped without touching the detail endpoint
+        sync.run_sync().await.unwrap();
+        // Swapped id: only the new one is fetched
+        sync.run_sync().await.unwrap();
+
+        let mut ids: Vec<i64> = db
+            .query("SELECT VALUE gw2_id FROM item")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_item_sync_updates_changed_items() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        let item = |name: &str| {
+            serde_json::json!({
+                "id": 1,
+                "name": name,
+                "type": "Trophy",
+                "level": 0,
+                "rarity": "Basic",
+                "vendor_value": 1,
+                "flags": [],
+                "game_types": [],
+                "restrictions": [],
+                "chat_link": "[&AgH1AAA=]"
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![item("Old Name")]))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param("ids", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![item("New Name")]))
+            .mount(&server)
+            .await;
+
+        let sync = ItemSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+        sync.run_sync().await.unwrap();
+        let seen = |db: Surreal<Any>| async move {
+            let seen: Option<serde_json::Value> = db
+                .query("SELECT first_seen, last_seen FROM ONLY item:⟨1⟩")
+                .await
+                .unwrap()
+                .take(0)
+                .unwrap();
+            let seen = seen.unwrap();
+            (seen["first_seen"].clone(), seen["last_seen"].clone())
+        };
+        let (first_seen, last_seen) = seen(db.clone()).await;
+        assert!(first_seen.is_string());
+
+        // Same item count, but a full sync still picks up the rename
+        sync.run_full_sync().await.unwrap();
+
+        // The item keeps its provenance while last_seen moves on
+        let (resynced_first_seen, resynced_last_seen) = seen(db.clone()).await;
+        assert_eq!(resynced_first_seen, first_seen);
+        assert_ne!(resynced_last_seen, last_seen);
+
+        let name: Option<String> = db
+            .query("SELECT VALUE name FROM ONLY item:⟨1⟩")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("New Name"));
+    }
+
+    #[tokio::test]
+    async fn test_item_sync_fetches_all_chunks() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        let all_ids: Vec<u32> = (1..=450).collect();
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&all_ids))
+            .mount(&server)
+            .await;
+
+        // Answer every chunk request with one item per requested id
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_contains("ids", ","))
+            .respond_with(|req: &wiremock::Request| {
+                let ids = req
+                    .url
+                    .query_pairs()
+                    .find(|(k, _)| k == "ids")
+                    .map(|(_, v)| v.into_owned())
+                    .unwrap_or_default();
+                let items: Vec<_> = ids
+                    .split(',')
+                    .map(|id| {
+                        serde_json::json!({
+                            "id": id.parse::<u32>().unwrap(),
+                            "name": format!("Item {id}"),
+                            "type": "Trophy",
+                            "level": 0,
+                            "rarity": "Basic",
+                            "vendor_value": 0,
+                            "flags": [],
+                            "game_types": ["PvE"],
+                            "restrictions": [],
+                            "chat_link": ""
+                        })
+                    })
+                    .collect();
+                ResponseTemplate::new(200).set_body_json(items)
+            })
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        let sync = ItemSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+        sync.run_sync().await.unwrap();
+
+        let count = db
+            .query("SELECT count() FROM item GROUP ALL")
+            .await
+            .unwrap()
+            .take::<Option<serde_json::Value>>(0)
+            .unwrap()
+            .and_then(|v| v.get("count")?.as_u64())
+            .unwrap();
+        assert_eq!(count, 450);
+    }
+
+    #[tokio::test]
+    async fn test_item_sync_reports_progress() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2, 3]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/items"))
+            .and(wiremock::matchers::query_param_contains("ids", ""))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Vec::<u32>::new()))
+            .mount(&server)
+            .await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        let gw2 = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .chunk_size(2)
+            .build();
+        let sync = ItemSync::new(db).with_client(gw2).with_progress(tx);
+        sync.run_sync().await.unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push((event.phase, event.current, event.total));
+        }
+        assert_eq!(
+            events,
+            vec![(SyncPhase::ItemSync, 1, 2), (SyncPhase::ItemSync, 2, 2)]
+        );
+    }
 }
@@ -0,0 +1,153 @@
+use crate::DBItem;
+use crate::price_updates::PriceUpdate;
+use dashmap::DashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+/// One page of the items listing as the database returned it.
+#[derive(Clone)]
+pub struct ItemsPage {
+    pub items: Arc<Vec<DBItem>>,
+    /// Items matching the filters across all pages
+    pub total: u64,
+}
+
+struct Entry {
+    page: ItemsPage,
+    stored: Instant,
+}
+
+/// Short-lived cache of `/api/items` pages, keyed on the query that produced
+/// them. Holds at most `capacity` pages; when full, expired pages go first,
+/// then the oldest.
+#[derive(Clone)]
+pub struct ItemsCache {
+    entries: Arc<DashMap<String, Entry>>,
+    ttl: Duration,
+    capacity: usize,
+    hits: Arc<AtomicU64>,
+}
+
+impl ItemsCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            entries: Arc::new(DashMap::new()),
+            ttl,
+            capacity: capacity.max(1),
+            hits: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<ItemsPage> {
+        let page = self
+            .entries
+            .get(key)
+            .filter(|entry| entry.stored.elapsed() < self.ttl)
+            .map(|entry| entry.page.clone())?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(page)
+    }
+
+    pub fn insert(&self, key: String, page: ItemsPage) {
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            self.entries
+                .retain(|_, entry| entry.stored.elapsed() < self.ttl);
+            if self.entries.len() >= self.capacity {
+                let oldest = self
+                    .entries
+                    .iter()
+                    .min_by_key(|entry| entry.stored)
+                    .map(|entry| entry.key().clone());
+                if let Some(oldest) = oldest {
+                    self.entries.remove(&oldest);
+                }
+            }
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                page,
+                stored: Instant::now(),
+            },
+        );
+    }
+
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lookups answered from the cache so far
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Empties the cache on every price update until the sending side closes,
+    /// so listings never lag a finished sync.
+    pub async fn clear_on_updates(self, mut updates: broadcast::Receiver<PriceUpdate>) {
+        // Missed (lagged) updates still mean the prices changed
+        while !matches!(
+            updates.recv().await,
+            Err(broadcast::error::RecvError::Closed)
+        ) {
+            self.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(total: u64) -> ItemsPage {
+        ItemsPage {
+            items: Arc::new(Vec::new()),
+            total,
+        }
+    }
+
+    #[test]
+    fn test_items_cache_expires_and_stays_bounded() {
+        let cache = ItemsCache::new(Duration::from_secs(60), 2);
+        cache.insert("a".to_string(), page(1));
+        cache.insert("b".to_string(), page(2));
+        cache.insert("c".to_string(), page(3));
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.get("c").unwrap().total, 3);
+        assert_eq!(cache.hits(), 1);
+
+        let expired = ItemsCache::new(Duration::ZERO, 2);
+        expired.insert("a".to_string(), page(1));
+        assert!(expired.get("a").is_none());
+        assert_eq!(expired.hits(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_items_cache_clears_on_price_update() {
+        let cache = ItemsCache::new(Duration::from_secs(60), 8);
+        cache.insert("a".to_string(), page(1));
+        let (sender, receiver) = broadcast::channel(4);
+        let clearing = tokio::spawn(cache.clone().clear_on_updates(receiver));
+
+        sender
+            .send(PriceUpdate {
+                finished_at: chrono::Utc::now(),
+                items_updated: 1,
+                changed: vec![1],
+            })
+            .unwrap();
+        drop(sender);
+        clearing.await.unwrap();
+        assert!(cache.is_empty());
+    }
+}
@@ -1,38 +1,449 @@
 use clap::Parser;
+use std::net::SocketAddr;
 use surrealdb::Surreal;
 use surrealdb::engine::any::{Any, connect};
 
+pub mod alert;
+pub mod auth;
+pub mod candles;
+pub mod coins;
+pub mod exchange;
+pub mod gem_sync;
 pub mod gw2_api;
+pub mod history_import;
 pub mod history_pruning;
 pub mod history_record;
+pub mod history_rollup;
+pub mod indicators;
 pub mod item_definition;
 pub mod item_sync;
+pub mod items_cache;
+pub mod listing;
+pub mod metrics;
 pub mod price_sync;
+pub mod price_updates;
+pub mod progress;
+pub mod rate_limit;
+pub mod recipe;
+pub mod recipe_sync;
+pub mod sync_status;
 
-#[derive(serde::Serialize, serde::Deserialize)]
+pub use metrics::timed_query;
+
+/// Share of a sale the trading post keeps (5% listing fee + 10% exchange fee).
+pub const TRADING_POST_FEE: f64 = 0.15;
+
+/// Seconds between price syncs unless `PRICE_SYNC_INTERVAL_SECS` says otherwise.
+pub const DEFAULT_PRICE_SYNC_INTERVAL_SECS: u64 = 900;
+
+/// Price syncs a price may miss before it counts as stale.
+pub const STALE_AFTER_SYNCS: u32 = 4;
+
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub struct PriceDetail {
     pub quantity: u32,
     pub unit_price: u32,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub struct DBItem {
+    #[schema(value_type = Object)]
     pub id: surrealdb::sql::Thing,
     pub gw2_id: u32,
     pub name: String,
     pub icon: Option<String>,
-    pub rarity: String,
+    pub rarity: item_definition::Rarity,
     pub buys: Option<PriceDetail>,
     pub sells: Option<PriceDetail>,
     pub profit: Option<f64>,
     pub roi: Option<f32>,
+    pub spread: Option<i64>,
+    /// Prices haven't been refreshed for over an hour (or ever)
+    #[serde(default)]
+    pub is_stale: bool,
+    /// When item sync first stored the item
+    #[serde(default)]
+    pub first_seen: Option<chrono::DateTime<chrono::Utc>>,
+    /// What an NPC vendor pays for the item, in copper
+    #[serde(default)]
+    pub vendor_value: Option<i64>,
+    /// Cheapest ingredient cost of crafting one unit, for craftable items
+    #[serde(default)]
+    pub craft_cost: Option<i64>,
+    /// Buy price minus `craft_cost`; positive when crafting is cheaper
+    #[serde(default)]
+    pub craft_savings: Option<i64>,
+    /// `liquidity_score` of the listed quantities
+    #[serde(default)]
+    pub liquidity: Option<f64>,
+    /// The latest price is far off its trailing mean, likely one outsized order
+    #[serde(default)]
+    pub price_anomaly: bool,
+    /// Standard deviations the latest price lies from its trailing mean; absent
+    /// while the item has too little history to judge
+    #[serde(default)]
+    pub price_anomaly_magnitude: Option<f64>,
+}
+
+impl DBItem {
+    /// Fields a client may request through `ItemParams::fields`
+    pub const FIELDS: &'static [&'static str] = &[
+        "id",
+        "gw2_id",
+        "name",
+        "icon",
+        "rarity",
+        "buys",
+        "sells",
+        "profit",
+        "roi",
+        "spread",
+        "is_stale",
+        "first_seen",
+        "vendor_value",
+        "craft_cost",
+        "craft_savings",
+        "liquidity",
+        "price_anomaly",
+        "price_anomaly_magnitude",
+    ];
+
+    /// Serializes the item keeping only `fields`.
+    pub fn project(&self, fields: &[&str]) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            object.retain(|key, _| fields.contains(&key.as_str()));
+        }
+        value
+    }
+}
+
+/// Parses a comma-separated field list against `DBItem::FIELDS`.
+/// `id` and `gw2_id` are always included so results stay identifiable.
+pub fn parse_item_fields(fields: &str) -> Result<Vec<&'static str>, String> {
+    let mut selected = vec!["id", "gw2_id"];
+    for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let known = DBItem::FIELDS
+            .iter()
+            .find(|f| **f == field)
+            .ok_or_else(|| format!("Unknown field: {}", field))?;
+        if !selected.contains(known) {
+            selected.push(known);
+        }
+    }
+    Ok(selected)
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemSort {
+    #[default]
+    Profit,
+    Roi,
+    Spread,
+    /// Most recently added items first
+    FirstSeen,
+    Liquidity,
+}
+
+impl ItemSort {
+    /// Column name in the items projection
+    pub fn column(self) -> &'static str {
+        match self {
+            ItemSort::Profit => "profit",
+            ItemSort::Roi => "roi",
+            ItemSort::Spread => "spread",
+            ItemSort::FirstSeen => "first_seen",
+            ItemSort::Liquidity => "liquidity",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ItemParams {
+    /// 1-based page number (default 1)
     pub page: Option<u32>,
+    /// Items per page (default 50, max 100)
     pub limit: Option<u32>,
+    /// Case-insensitive substring match on the item name
     pub search: Option<String>,
+    /// Minimum profit after fees, in copper
+    pub min_profit: Option<f64>,
+    /// Maximum profit after fees, in copper
+    pub max_profit: Option<f64>,
+    /// Minimum ROI, in percent
+    pub min_roi: Option<f64>,
+    /// Maximum ROI, in percent
+    pub max_roi: Option<f64>,
+    /// Only return items that can (or cannot) be traded (default true)
+    pub tradeable: Option<bool>,
+    /// Minimum required character level
+    pub min_level: Option<u32>,
+    /// Maximum required character level
+    pub max_level: Option<u32>,
+    /// Minimum current buy price, in copper
+    pub min_buy: Option<u32>,
+    /// Maximum current buy price, in copper
+    pub max_buy: Option<u32>,
+    /// Minimum current sell price, in copper
+    pub min_sell: Option<u32>,
+    /// Maximum current sell price, in copper
+    pub max_sell: Option<u32>,
+    /// Leave out items whose latest price was flagged as a spike (default false)
+    pub exclude_anomalies: Option<bool>,
+    /// Metric to sort by, descending (default profit)
+    pub sort: Option<ItemSort>,
+    /// Comma-separated list of fields to return; `id` and `gw2_id` are always included
+    pub fields: Option<String>,
+}
+
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct ItemBatchRequest {
+    pub ids: Vec<u32>,
+}
+
+/// Body of `POST /api/history/batch`. Unlike the single-item history,
+/// `resolution` is required so a batch never returns raw rows.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
+pub struct HistoryBatchRequest {
+    pub ids: Vec<u32>,
+    /// Only points at or after this time (RFC 3339)
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only points at or before this time (RFC 3339)
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    pub resolution: Resolution,
+}
+
+/// Bucket size for downsampling price history
+#[derive(serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "6h")]
+    SixHours,
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl Resolution {
+    /// SurrealQL duration literal for `time::floor`
+    pub fn duration(self) -> &'static str {
+        match self {
+            Resolution::Hour => "1h",
+            Resolution::SixHours => "6h",
+            Resolution::Day => "1d",
+        }
+    }
+
+    pub fn width(self) -> chrono::Duration {
+        match self {
+            Resolution::Hour => chrono::Duration::hours(1),
+            Resolution::SixHours => chrono::Duration::hours(6),
+            Resolution::Day => chrono::Duration::days(1),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryParams {
+    /// Only points at or after this time (RFC 3339)
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only points at or before this time (RFC 3339)
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    /// Average points into buckets of this size; raw points when omitted
+    pub resolution: Option<Resolution>,
+}
+
+/// Which moving average `/api/items/{id}/moving-average` computes
+#[derive(serde::Deserialize, utoipa::ToSchema, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AverageKind {
+    #[default]
+    Sma,
+    Ema,
+}
+
+/// The window counts samples, not time: raw history rows (written only when a
+/// price moves, so unevenly spaced) or, with `resolution`, buckets. Buckets
+/// without any history are skipped rather than filled.
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MovingAverageParams {
+    /// Samples per window (default 20, at most 500)
+    pub window: Option<usize>,
+    /// Simple or exponential average (default sma)
+    pub kind: Option<AverageKind>,
+    /// Only points at or after this time (RFC 3339); earlier history doesn't
+    /// feed the first windows
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only points at or before this time (RFC 3339)
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    /// Average points into buckets of this size before smoothing
+    pub resolution: Option<Resolution>,
+}
+
+/// Moving averages at one sample; `None` until a full window is available
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema, Debug)]
+pub struct MovingAveragePoint {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub buy_price: Option<f64>,
+    pub sell_price: Option<f64>,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OpportunityParams {
+    /// Minimum profit after the trading post fee, in copper (default 1)
+    pub min_profit: Option<f64>,
+    /// Minimum listed buy plus sell quantity (default 100)
+    pub min_volume: Option<i64>,
+    /// Weight of profit in the score (default 1)
+    pub profit_weight: Option<f64>,
+    /// Weight of ROI in the score (default 1)
+    pub roi_weight: Option<f64>,
+    /// Weight of liquidity in the score (default 1)
+    pub liquidity_weight: Option<f64>,
+    /// Number of results (default 50, at most 100)
+    pub limit: Option<usize>,
+}
+
+/// How much each component counts towards `opportunity_score`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OpportunityWeights {
+    pub profit: f64,
+    pub roi: f64,
+    pub liquidity: f64,
+}
+
+impl Default for OpportunityWeights {
+    fn default() -> Self {
+        Self {
+            profit: 1.0,
+            roi: 1.0,
+            liquidity: 1.0,
+        }
+    }
+}
+
+/// Composite flip score. Profit spans several orders of magnitude, so it counts
+/// logarithmically, like `liquidity` (a `liquidity_score`) already does; ROI
+/// counts as a fraction (100% adds 1).
+pub fn opportunity_score(
+    profit: f64,
+    roi: f64,
+    liquidity: f64,
+    weights: OpportunityWeights,
+) -> f64 {
+    weights.profit * (1.0 + profit.max(0.0)).log10()
+        + weights.roi * roi / 100.0
+        + weights.liquidity * liquidity
+}
+
+/// How readily an item trades both ways: log10 of one plus the thinner side of
+/// the order book. 0 when either side is empty, about 1 at ten units, 2 at a
+/// hundred, so a few huge listings don't drown everything else out.
+pub fn liquidity_score(buy_quantity: i64, sell_quantity: i64) -> f64 {
+    (1.0 + buy_quantity.min(sell_quantity).max(0) as f64).log10()
+}
+
+/// An item worth flipping, with what it scored
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct Opportunity {
+    #[serde(flatten)]
+    pub item: DBItem,
+    /// Listed buy plus sell quantity
+    pub volume: i64,
+    pub score: f64,
+}
+
+/// An item a buy order can be placed on and the goods sold to an NPC vendor
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct VendorFlip {
+    #[serde(flatten)]
+    pub item: DBItem,
+    /// Vendor value minus the current buy price
+    pub vendor_profit: i64,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangesParams {
+    /// Only items whose buy or sell price changed after this time (RFC 3339)
+    pub since: chrono::DateTime<chrono::Utc>,
+    /// Resumes a page: also items changed exactly at `since` with a higher gw2 id.
+    /// The `next` link sets it along with `since`
+    pub after: Option<u32>,
+    /// Number of results (default 500, at most 1000)
+    pub limit: Option<u32>,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WindowParams {
+    /// Trailing window in hours (default 168, at most 720)
+    pub hours: Option<u32>,
+}
+
+/// Volume-weighted average prices over a trailing window, weighting each history
+/// point by the quantity listed on its side
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema, Debug)]
+pub struct Vwap {
+    pub gw2_id: u32,
+    pub hours: u32,
+    /// History points in the window
+    pub samples: usize,
+    /// Null when nothing was listed on that side during the window
+    pub buy_price: Option<f64>,
+    pub sell_price: Option<f64>,
+}
+
+/// How much an item's sell price swung over a trailing window
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema, Debug)]
+pub struct Volatility {
+    pub gw2_id: u32,
+    pub hours: u32,
+    /// History points in the window
+    pub samples: usize,
+    /// Standard deviation of the sell price over its mean; null with too few points
+    pub coefficient_of_variation: Option<f64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema, Debug)]
+pub struct HistoryPoint {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub buy_price: i64,
+    pub sell_price: i64,
+    pub buy_quantity: i64,
+    pub sell_quantity: i64,
+}
+
+/// Headline market numbers for dashboards
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema, Debug)]
+pub struct MarketStats {
+    pub tradeable_items: u64,
+    /// Items with both a current buy and sell listing
+    pub priced_items: u64,
+    pub mean_spread: Option<f64>,
+    pub median_spread: Option<f64>,
+    pub last_price_update: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GemParams {
+    /// How many hours of history to return (default 24, at most 720)
+    pub hours: Option<u32>,
+}
+
+/// Current gem exchange rate plus its recent history
+#[derive(serde::Serialize, serde::Deserialize, utoipa::ToSchema, Debug)]
+pub struct GemOverview {
+    /// Latest recorded rate, if any was recorded yet
+    pub current: Option<exchange::GemRate>,
+    /// Recorded rates in chronological order
+    pub history: Vec<exchange::GemRate>,
 }
 
 #[derive(Parser, Debug)]
@@ -51,6 +462,122 @@ pub struct Args {
 
     #[arg(long, env = "SURREAL_PASS", default_value = "root")]
     pub surreal_pass: String,
+
+    /// File holding the SurrealDB password (e.g. a Docker or Kubernetes secret);
+    /// takes precedence over `--surreal-pass`
+    #[arg(long, env = "SURREAL_PASS_FILE")]
+    pub surreal_pass_file: Option<std::path::PathBuf>,
+
+    /// SurrealDB namespace, e.g. to keep staging apart from production on one server
+    #[arg(long, env = "SURREAL_NS", default_value = "gw2shinies")]
+    pub surreal_ns: String,
+
+    #[arg(long, env = "SURREAL_DB", default_value = "colony_brain")]
+    pub surreal_db: String,
+
+    /// Per-IP request budget for the API (health checks are exempt)
+    #[arg(long, env = "RATE_LIMIT_PER_MINUTE", default_value_t = 120)]
+    pub rate_limit_per_minute: u32,
+
+    /// When set, API requests (except /health) must send `Authorization: Bearer <key>`
+    #[arg(long, env = "API_KEY")]
+    pub api_key: Option<String>,
+
+    /// Enables the admin write endpoints (e.g. history import) for requests sending
+    /// `Authorization: Bearer <key>`; they are disabled when unset
+    #[arg(long, env = "ADMIN_API_KEY")]
+    pub admin_api_key: Option<String>,
+
+    /// Language for synced item names (en, de, fr, es, zh); API default when unset
+    #[arg(long, env = "GW2_LANG", value_parser = ["en", "de", "fr", "es", "zh"])]
+    pub lang: Option<String>,
+
+    /// Address the API server listens on
+    #[arg(long, env = "BIND_ADDR", default_value = "0.0.0.0:3000")]
+    pub bind_addr: SocketAddr,
+
+    /// Seconds between price syncs
+    #[arg(
+        long,
+        env = "PRICE_SYNC_INTERVAL_SECS",
+        default_value_t = DEFAULT_PRICE_SYNC_INTERVAL_SECS,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub price_sync_interval_secs: u64,
+
+    /// Price ids written to the database per batch
+    #[arg(
+        long,
+        env = "PRICE_SYNC_CHUNK_SIZE",
+        default_value_t = 200,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub price_sync_chunk_size: usize,
+
+    /// Price chunks fetched concurrently
+    #[arg(
+        long,
+        env = "PRICE_SYNC_CONCURRENCY",
+        default_value_t = 4,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub price_sync_concurrency: usize,
+
+    /// Run the syncs without writing to the database, logging what they would do
+    #[arg(long, env = "DRY_RUN")]
+    pub dry_run: bool,
+
+    /// Rebuild the history indexes after each pruning pass that removed rows
+    #[arg(long, env = "PRUNE_REBUILD_INDEXES")]
+    pub prune_rebuild_indexes: bool,
+
+    /// When set, the scraper serves its query timings at `/metrics` on this address
+    #[arg(long, env = "METRICS_ADDR")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Retries of the initial database connection, e.g. while the database starts
+    #[arg(long, env = "DB_CONNECT_RETRIES", default_value_t = 5)]
+    pub db_connect_retries: u32,
+
+    /// Delay before the first connection retry in milliseconds, doubled on each one after
+    #[arg(long, env = "DB_CONNECT_DELAY_MS", default_value_t = 1000)]
+    pub db_connect_delay_ms: u64,
+}
+
+impl Args {
+    /// Fails only when `--surreal-pass-file` is set and can't be read.
+    pub fn database_config(&self) -> std::io::Result<DatabaseConfig> {
+        let pass = match &self.surreal_pass_file {
+            Some(path) => std::fs::read_to_string(path)?.trim_end().to_string(),
+            None => self.surreal_pass.clone(),
+        };
+        Ok(DatabaseConfig {
+            uri: self.surreal_uri.clone(),
+            user: self.surreal_user.clone(),
+            pass,
+            namespace: self.surreal_ns.clone(),
+            database: self.surreal_db.clone(),
+            retry: gw2_api::RetryPolicy {
+                max_retries: self.db_connect_retries,
+                base_delay: std::time::Duration::from_millis(self.db_connect_delay_ms),
+            },
+            migrate: !self.dry_run,
+        })
+    }
+}
+
+/// Where `Database::init` connects, and how persistently.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub uri: String,
+    pub user: String,
+    pub pass: String,
+    pub namespace: String,
+    pub database: String,
+    /// Retries of the initial connection
+    pub retry: gw2_api::RetryPolicy,
+    /// Whether `Database::init` applies pending migrations; off for dry runs
+    pub migrate: bool,
 }
 
 // Database connection placeholder
@@ -58,15 +585,486 @@ pub struct Database {
     pub db: Surreal<Any>,
 }
 
+impl DatabaseConfig {
+    /// Whether the engine `uri` selects can run live queries, which the HTTP
+    /// protocol can't; websockets and embedded engines can.
+    pub fn supports_live_queries(&self) -> bool {
+        !matches!(scheme(&self.uri), "http" | "https")
+    }
+}
+
 impl Database {
-    pub async fn init(uri: &str, user: &str, pass: &str) -> surrealdb::Result<Self> {
-        let db = connect(uri).await?;
-        db.signin(surrealdb::opt::auth::Root {
-            username: user,
-            password: pass,
-        })
-        .await?;
-        db.use_ns("gw2shinies").use_db("colony_brain").await?;
+    /// Connects and signs in, retrying with backoff per `config.retry` so a database
+    /// that is still starting up doesn't take the service down, then applies the
+    /// pending migrations when `config.migrate` is set. Returns the last connection
+    /// error once the retries run out.
+    pub async fn init(config: &DatabaseConfig) -> surrealdb::Result<Self> {
+        let retry = &config.retry;
+        let mut attempt = 0;
+        let database = loop {
+            match Self::connect(config).await {
+                Ok(database) => break database,
+                Err(e) if attempt < retry.max_retries => {
+                    let delay = retry.delay(attempt);
+                    attempt += 1;
+                    eprintln!(
+                        "Database connection attempt {}/{} failed: {}. Retrying in {:?}...",
+                        attempt,
+                        retry.max_retries + 1,
+                        e,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        if config.migrate {
+            migrate(&database.db).await?;
+        }
+        Ok(database)
+    }
+
+    async fn connect(config: &DatabaseConfig) -> surrealdb::Result<Self> {
+        let db = connect(&config.uri).await?;
+        if is_remote(&config.uri) {
+            db.signin(surrealdb::opt::auth::Root {
+                username: &config.user,
+                password: &config.pass,
+            })
+            .await?;
+        }
+        Self::select(db, config).await
+    }
+
+    /// Ends the session and drops this handle, so the server isn't left holding a
+    /// dangling websocket session. Clones of `db` share the session and can't be
+    /// used for queries afterwards; the connection closes once the last is dropped.
+    pub async fn close(self) -> surrealdb::Result<()> {
+        self.db.invalidate().await?;
+        println!("Database session closed.");
+        Ok(())
+    }
+
+    async fn select(db: Surreal<Any>, config: &DatabaseConfig) -> surrealdb::Result<Self> {
+        db.use_ns(&config.namespace)
+            .use_db(&config.database)
+            .await?;
         Ok(Self { db })
     }
 }
+
+/// Whether `uri` points at a SurrealDB server, which needs a signin, rather than
+/// an engine embedded in this process (`mem://`, `surrealkv://path`, ...), which
+/// has no users to sign in as.
+fn is_remote(uri: &str) -> bool {
+    matches!(scheme(uri), "ws" | "wss" | "http" | "https")
+}
+
+fn scheme(uri: &str) -> &str {
+    uri.split_once("://").map_or("", |(scheme, _)| scheme)
+}
+
+/// Schema changes in order, as (version, name, statements). Append only: a
+/// version runs once per database. Statements stay idempotent anyway, since both
+/// binaries may migrate the same fresh database at once.
+const MIGRATIONS: &[(u32, &str, &str)] = &[
+    (
+        1,
+        "base schema",
+        // Without the history index, pruning and the history endpoint scan the whole
+        // table. Timestamps are left untyped: the SDK writes them as strings.
+        "DEFINE TABLE IF NOT EXISTS item SCHEMALESS;
+    DEFINE FIELD IF NOT EXISTS gw2_id ON TABLE item TYPE option<int>;
+    DEFINE INDEX IF NOT EXISTS item_gw2_id_idx ON TABLE item COLUMNS gw2_id UNIQUE;
+    DEFINE TABLE IF NOT EXISTS item_history SCHEMALESS;
+    DEFINE FIELD IF NOT EXISTS item ON TABLE item_history TYPE record<item>;
+    DEFINE INDEX IF NOT EXISTS item_history_item_ts_idx ON TABLE item_history COLUMNS item, timestamp;
+    DEFINE TABLE IF NOT EXISTS item_history_agg SCHEMALESS;
+    DEFINE TABLE IF NOT EXISTS item_history_1h SCHEMALESS;
+    DEFINE TABLE IF NOT EXISTS sync_status SCHEMALESS;",
+    ),
+    (
+        2,
+        "price updates",
+        // Defined up front so the API's live query doesn't race the scraper's
+        // first write to create the table
+        "DEFINE TABLE IF NOT EXISTS price_updates SCHEMALESS;",
+    ),
+    (
+        3,
+        "history recorded_at",
+        // `timestamp` holds strings, so a time range over it can't use an index.
+        // `recorded_at` is the same instant as a datetime, kept in step on every
+        // write; the UPDATE backfills rows written before.
+        "DEFINE FIELD IF NOT EXISTS recorded_at ON TABLE item_history TYPE option<datetime> VALUE <datetime>$this.timestamp;
+    DEFINE INDEX IF NOT EXISTS item_history_recorded_at_idx ON TABLE item_history COLUMNS recorded_at;
+    UPDATE item_history WHERE recorded_at = NONE RETURN NONE;",
+    ),
+    (
+        4,
+        "item price_changed_at",
+        // When an item's buy or sell price last moved, for the changes feed. Until
+        // its next move, an item's last price write is the best guess
+        "DEFINE INDEX IF NOT EXISTS item_price_changed_at_idx ON TABLE item COLUMNS price_changed_at;
+    UPDATE item SET price_changed_at = <datetime>last_price_update
+        WHERE price_changed_at = NONE AND last_price_update != NONE RETURN NONE;",
+    ),
+];
+
+/// Applies the migrations this database hasn't seen yet, recording each in the
+/// `migrations` table. Returns the versions applied.
+pub async fn migrate(db: &Surreal<Any>) -> surrealdb::Result<Vec<u32>> {
+    let done: Vec<u32> = db
+        .query("DEFINE TABLE IF NOT EXISTS migrations SCHEMALESS; SELECT VALUE version FROM migrations")
+        .await?
+        .check()?
+        .take(1)?;
+
+    let mut applied = Vec::new();
+    for &(version, name, statements) in MIGRATIONS {
+        if done.contains(&version) {
+            continue;
+        }
+        println!("Applying migration {}: {}", version, name);
+        db.query(statements).await?.check()?;
+        db.query("UPSERT type::thing('migrations', $version) CONTENT { version: $version, name: $name, applied_at: time::now() }")
+            .bind(("version", version))
+            .bind(("name", name))
+            .await?
+            .check()?;
+        applied.push(version);
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_item_fields() {
+        assert_eq!(
+            parse_item_fields("name, profit,name").unwrap(),
+            vec!["id", "gw2_id", "name", "profit"]
+        );
+        assert_eq!(parse_item_fields("").unwrap(), vec!["id", "gw2_id"]);
+        assert_eq!(
+            parse_item_fields("name,secret").unwrap_err(),
+            "Unknown field: secret"
+        );
+    }
+
+    #[test]
+    fn test_item_fields_whitelist_matches_serialization() {
+        let item = DBItem {
+            id: surrealdb::sql::Thing::from(("item", "1")),
+            gw2_id: 1,
+            name: "Item".to_string(),
+            icon: None,
+            rarity: item_definition::Rarity::Fine,
+            buys: None,
+            sells: None,
+            profit: None,
+            roi: None,
+            spread: None,
+            is_stale: false,
+            first_seen: None,
+            vendor_value: None,
+            craft_cost: None,
+            craft_savings: None,
+            liquidity: None,
+            price_anomaly: false,
+            price_anomaly_magnitude: None,
+        };
+        let value = serde_json::to_value(&item).unwrap();
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        let mut fields = DBItem::FIELDS.to_vec();
+        keys.sort();
+        fields.sort();
+        assert_eq!(keys, fields);
+
+        let projected = item.project(&["gw2_id", "name"]);
+        assert_eq!(
+            projected,
+            serde_json::json!({ "gw2_id": 1, "name": "Item" })
+        );
+    }
+
+    #[test]
+    fn test_liquidity_score() {
+        assert_eq!(liquidity_score(0, 5000), 0.0);
+        assert_eq!(liquidity_score(-3, 10), 0.0);
+        assert!((liquidity_score(9, 500) - 1.0).abs() < 1e-9);
+        // The thinner side decides, however deep the other one is
+        assert_eq!(
+            liquidity_score(99, 1_000_000),
+            liquidity_score(1_000_000, 99)
+        );
+        assert!((liquidity_score(99_999, 250_000) - 5.0).abs() < 1e-9);
+        assert!(liquidity_score(1_000, 1_000) > liquidity_score(100, 100));
+    }
+
+    #[test]
+    fn test_opportunity_score() {
+        let weights = OpportunityWeights::default();
+        // log10(1000) + 50% + liquidity
+        let score = opportunity_score(999.0, 50.0, 2.0, weights);
+        assert!((score - 5.5).abs() < 1e-9);
+        // Losses add nothing rather than going negative
+        assert_eq!(opportunity_score(-10.0, 0.0, 0.0, weights), 0.0);
+
+        let liquidity_only = OpportunityWeights {
+            profit: 0.0,
+            roi: 0.0,
+            liquidity: 2.0,
+        };
+        assert!((opportunity_score(999.0, 50.0, 2.0, liquidity_only) - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_args_bind_addr() {
+        let args = Args::try_parse_from(["api", "--bind-addr", "127.0.0.1:8080"]).unwrap();
+        assert_eq!(args.bind_addr, "127.0.0.1:8080".parse().unwrap());
+
+        let err = Args::try_parse_from(["api", "--bind-addr", "localhost"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn test_args_reject_zero_price_sync_settings() {
+        for flag in [
+            "--price-sync-interval-secs",
+            "--price-sync-chunk-size",
+            "--price-sync-concurrency",
+        ] {
+            let err = Args::try_parse_from(["scraper", flag, "0"]).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_is_idempotent() {
+        let db = connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        assert_eq!(migrate(&db).await.unwrap(), vec![1, 2, 3, 4]);
+        assert!(migrate(&db).await.unwrap().is_empty());
+
+        let versions: Vec<u32> = db
+            .query("SELECT VALUE version FROM migrations")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(versions, vec![1, 2, 3, 4]);
+
+        let info: Option<serde_json::Value> = db
+            .query("INFO FOR TABLE item_history")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        let indexes = &info.unwrap()["indexes"];
+        assert!(indexes.get("item_history_item_ts_idx").is_some());
+        assert!(indexes.get("item_history_recorded_at_idx").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_database_init_gives_up_after_retries() {
+        // Nothing listens on port 1
+        let config = DatabaseConfig {
+            uri: "ws://127.0.0.1:1".to_string(),
+            ..test_database_config("test")
+        };
+        assert!(Database::init(&config).await.is_err());
+    }
+
+    fn test_database_config(namespace: &str) -> DatabaseConfig {
+        DatabaseConfig {
+            uri: "mem://".to_string(),
+            user: "root".to_string(),
+            pass: "root".to_string(),
+            namespace: namespace.to_string(),
+            database: "test".to_string(),
+            retry: gw2_api::RetryPolicy {
+                max_retries: 1,
+                base_delay: std::time::Duration::from_millis(10),
+            },
+            migrate: true,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_init_migrates_unless_dry_run() {
+        let database = Database::init(&test_database_config("migrated"))
+            .await
+            .unwrap();
+        assert!(migrate(&database.db).await.unwrap().is_empty());
+
+        let args = Args::parse_from(["scraper", "--dry-run", "--surreal-uri", "mem://"]);
+        let database = Database::init(&args.database_config().unwrap())
+            .await
+            .unwrap();
+        let tables: serde_json::Value = database
+            .db
+            .query("INFO FOR DB")
+            .await
+            .unwrap()
+            .take::<Option<serde_json::Value>>(0)
+            .unwrap()
+            .unwrap()["tables"]
+            .clone();
+        assert!(tables.get("migrations").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_databases_in_other_namespaces_are_isolated() {
+        // One server; handles share their session, so switch between the two in turn
+        let server = connect("mem://").await.unwrap();
+        let count = |db: Surreal<Any>| async move {
+            db.query("SELECT count() FROM item GROUP ALL")
+                .await
+                .unwrap()
+                .take::<Option<serde_json::Value>>(0)
+                .unwrap()
+                .and_then(|v| v.get("count")?.as_u64())
+                .unwrap_or(0)
+        };
+
+        let staging = Database::select(server.clone(), &test_database_config("staging"))
+            .await
+            .unwrap();
+        staging.db.query("CREATE item:1").await.unwrap();
+        assert_eq!(count(staging.db).await, 1);
+
+        let production = Database::select(server.clone(), &test_database_config("production"))
+            .await
+            .unwrap();
+        assert_eq!(count(production.db).await, 0);
+
+        let staging = Database::select(server, &test_database_config("staging"))
+            .await
+            .unwrap();
+        assert_eq!(count(staging.db).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_closed_database_is_unusable() {
+        // Auth only applies to an embedded engine set up with a root user
+        let root = surrealdb::opt::auth::Root {
+            username: "root",
+            password: "root",
+        };
+        let db = connect(("mem://", surrealdb::opt::Config::new().user(root)))
+            .await
+            .unwrap();
+        db.signin(root).await.unwrap();
+        let database = Database::select(db, &test_database_config("test"))
+            .await
+            .unwrap();
+        let handle = database.db.clone();
+        handle
+            .query("CREATE item:1")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        database.close().await.unwrap();
+        let result = handle.query("SELECT * FROM item").await;
+        assert!(result.is_err());
+    }
+
+    /// Signs in over a websocket, which needs a running server, e.g.
+    /// `surreal start --user root --pass root memory`. Run with
+    /// `SURREAL_TEST_URI=ws://127.0.0.1:8000 cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore = "needs a SurrealDB server at SURREAL_TEST_URI"]
+    async fn test_database_init_signs_in_to_server() {
+        let uri =
+            std::env::var("SURREAL_TEST_URI").unwrap_or_else(|_| "ws://127.0.0.1:8000".to_string());
+        let config = DatabaseConfig {
+            uri,
+            ..test_database_config("test")
+        };
+        let database = Database::init(&config).await.unwrap();
+        let mut response = database.db.query("RETURN 1").await.unwrap();
+        assert_eq!(response.take::<Option<i64>>(0).unwrap(), Some(1));
+        database.close().await.unwrap();
+
+        let config = DatabaseConfig {
+            pass: "wrong".to_string(),
+            ..config
+        };
+        assert!(Database::init(&config).await.is_err());
+    }
+
+    #[test]
+    fn test_only_servers_need_signin() {
+        assert!(is_remote("ws://127.0.0.1:8000"));
+        assert!(is_remote("https://db.example.com"));
+        assert!(!is_remote("mem://"));
+        assert!(!is_remote("surrealkv://data"));
+        assert!(!is_remote("rocksdb://data"));
+
+        let config = |uri: &str| DatabaseConfig {
+            uri: uri.to_string(),
+            ..test_database_config("test")
+        };
+        assert!(config("wss://db.example.com").supports_live_queries());
+        assert!(config("mem://").supports_live_queries());
+        assert!(!config("https://db.example.com").supports_live_queries());
+    }
+
+    #[tokio::test]
+    async fn test_database_init_embedded_without_auth() {
+        let database = Database::init(&test_database_config("test")).await.unwrap();
+        database
+            .db
+            .query("CREATE item:1")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("gw2shinies-test-{}", std::process::id()));
+        let config = DatabaseConfig {
+            uri: format!("surrealkv://{}", dir.display()),
+            ..test_database_config("test")
+        };
+        let database = Database::init(&config).await.unwrap();
+        database
+            .db
+            .query("CREATE item:1")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        drop(database);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_password_file_takes_precedence() {
+        let path = std::env::temp_dir().join(format!("gw2shinies-pass-{}", std::process::id()));
+        std::fs::write(&path, "s3cret\n").unwrap();
+        let args = Args::parse_from([
+            "scraper",
+            "--surreal-pass",
+            "root",
+            "--surreal-pass-file",
+            path.to_str().unwrap(),
+        ]);
+        let config = args.database_config();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.unwrap().pass, "s3cret");
+
+        let args = Args::parse_from(["scraper", "--surreal-pass-file", "/nonexistent/pass"]);
+        assert!(args.database_config().is_err());
+    }
+}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// One price level of an order book side.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListingTier {
+    pub unit_price: i64,
+    /// Number of separate orders at this price
+    pub listings: i64,
+    /// Total units across those orders
+    pub quantity: i64,
+}
+
+/// Full order book for one item, as returned by `/v2/commerce/listings`.
+/// Buy tiers are sorted highest price first, sell tiers lowest price first.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawListing {
+    pub id: u32,
+    pub buys: Vec<ListingTier>,
+    pub sells: Vec<ListingTier>,
+}
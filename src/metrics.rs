@@ -0,0 +1,98 @@
+use dashmap::DashMap;
+use std::fmt::Write;
+use std::future::IntoFuture;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+/// Durations of one named query, accumulated since the process started.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueryTiming {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+// Process-wide, so every worker and handler reports into the one `/metrics` view
+static QUERY_TIMINGS: LazyLock<DashMap<&'static str, QueryTiming>> = LazyLock::new(DashMap::new);
+
+/// Awaits `query` (a SurrealDB query, or anything else worth timing) and records
+/// how long it took under `name`, whether it succeeded or not.
+pub async fn timed_query<F: IntoFuture>(name: &'static str, query: F) -> F::Output {
+    let started = Instant::now();
+    let output = query.await;
+    record_query(name, started.elapsed());
+    output
+}
+
+pub fn record_query(name: &'static str, elapsed: Duration) {
+    let mut timing = QUERY_TIMINGS.entry(name).or_default();
+    timing.count += 1;
+    timing.total += elapsed;
+    timing.max = timing.max.max(elapsed);
+}
+
+pub fn query_timing(name: &str) -> Option<QueryTiming> {
+    QUERY_TIMINGS.get(name).map(|t| *t)
+}
+
+/// Every timing in the Prometheus text format, as a summary per query name plus
+/// the slowest run seen.
+pub fn render() -> String {
+    let mut timings: Vec<(&'static str, QueryTiming)> = QUERY_TIMINGS
+        .iter()
+        .map(|t| (*t.key(), *t.value()))
+        .collect();
+    timings.sort_by_key(|(name, _)| *name);
+
+    let mut out = String::new();
+    out.push_str("# HELP gw2shinies_query_duration_seconds Time spent in database queries.\n");
+    out.push_str("# TYPE gw2shinies_query_duration_seconds summary\n");
+    for (name, timing) in &timings {
+        let _ = writeln!(
+            out,
+            "gw2shinies_query_duration_seconds_sum{{query=\"{}\"}} {}",
+            name,
+            timing.total.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "gw2shinies_query_duration_seconds_count{{query=\"{}\"}} {}",
+            name, timing.count
+        );
+    }
+    out.push_str("# HELP gw2shinies_query_duration_seconds_max Slowest run of each query.\n");
+    out.push_str("# TYPE gw2shinies_query_duration_seconds_max gauge\n");
+    for (name, timing) in &timings {
+        let _ = writeln!(
+            out,
+            "gw2shinies_query_duration_seconds_max{{query=\"{}\"}} {}",
+            name,
+            timing.max.as_secs_f64()
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_timed_query_records_duration() {
+        let output = timed_query("test_timed_query", async {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            42
+        })
+        .await;
+        assert_eq!(output, 42);
+
+        let timing = query_timing("test_timed_query").unwrap();
+        assert_eq!(timing.count, 1);
+        assert!(timing.total >= Duration::from_millis(5));
+        assert_eq!(timing.max, timing.total);
+        assert!(
+            render()
+                .contains("gw2shinies_query_duration_seconds_count{query=\"test_timed_query\"} 1")
+        );
+    }
+}
@@ -1,4 +1,11 @@
-use crate::gw2_api::Gw2Client;
+use crate::gw2_api::{CHUNK_CONCURRENCY, Gw2Client, Gw2Error, MAX_IDS_PER_REQUEST, chunk_ids};
+use crate::history_record::HistoryRecord;
+use crate::indicators;
+use crate::price_updates::{self, PriceUpdate};
+use crate::progress::{ProgressSender, SyncPhase, SyncProgress};
+use crate::sync_status;
+use crate::timed_query;
+use futures::StreamExt;
 use surrealdb::Surreal;
 use surrealdb::engine::any::Any;
 
@@ -6,10 +13,109 @@ use std::time::Duration;
 use tokio::time::interval;
 use tokio_util::sync::CancellationToken;
 
+// History rows are unique per item and timestamp. A live trading post price is
+// exact, so it also replaces recovered gw2bltc rows (flagged `recovered`) in its
+// minute; a recovered row is an aggregate and only fills minutes with no row yet.
+const LIVE_HISTORY_INSERT: &str = "FOR $r IN $records {
+    LET $minute = time::floor(<datetime>$r.timestamp, 1m);
+    DELETE item_history WHERE item = $r.item AND (
+        <datetime>timestamp = <datetime>$r.timestamp
+        OR (recovered = true AND <datetime>timestamp >= $minute AND <datetime>timestamp < $minute + 1m)
+    );
+    CREATE item_history CONTENT $r;
+}";
+pub(crate) const RECOVERED_HISTORY_INSERT: &str = "FOR $r IN $records {
+    LET $minute = time::floor(<datetime>$r.timestamp, 1m);
+    IF count(SELECT id FROM item_history WHERE item = $r.item
+        AND <datetime>timestamp >= $minute AND <datetime>timestamp < $minute + 1m LIMIT 1) = 0 {
+        CREATE item_history SET item = $r.item, timestamp = $r.timestamp,
+            buy_price = $r.buy_price, sell_price = $r.sell_price,
+            buy_quantity = $r.buy_quantity, sell_quantity = $r.sell_quantity,
+            recovered = true;
+    };
+}";
+
+/// A price as written onto its item record, with what's derived from it in Rust.
+#[derive(serde::Serialize)]
+struct PriceWrite {
+    #[serde(flatten)]
+    record: HistoryRecord,
+    liquidity: f64,
+    /// Whether the buy or sell price moved, which stamps `price_changed_at`
+    changed: bool,
+}
+
+/// History a new price is compared against when looking for spikes.
+const ANOMALY_WINDOW: chrono::Duration = chrono::Duration::days(7);
+
+/// Pause applied to history recovery when gw2bltc rate limits without a `Retry-After`.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// What a `PriceSync::run_sync` pass did.
+#[derive(Debug, Clone, Default)]
+pub struct PriceSyncReport {
+    /// Existing item records whose prices were written
+    pub items_updated: usize,
+    pub history_inserted: usize,
+    pub chunks: usize,
+    pub elapsed: Duration,
+    /// Prices that couldn't be fetched or parsed, even after a retry, or whose
+    /// history row couldn't be written
+    pub errors: usize,
+    /// Price alerts that fired on the new prices
+    pub alerts_triggered: usize,
+    /// Gw2 ids of the items whose buy or sell price moved
+    pub changed: Vec<u32>,
+    /// Moved items whose new price was flagged as a spike
+    pub anomalies: usize,
+}
+
+/// Tuning knobs for `PriceSync`; the defaults suit the public API.
+#[derive(Debug, Clone, Copy)]
+pub struct PriceSyncConfig {
+    /// Ids fetched and written per batch (requests are still capped at 200 ids);
+    /// must be at least 1
+    pub chunk_size: usize,
+    /// Chunks fetched concurrently; must be at least 1
+    pub concurrency: usize,
+    /// Log progress every this many chunks
+    pub log_every: usize,
+    /// Trading post cut used for the stored profit and ROI
+    pub fee: f64,
+    /// Items whose gw2bltc history is fetched concurrently during recovery
+    pub recovery_concurrency: usize,
+    /// Items bltc answered for this recently are skipped by recovery, since it
+    /// often just has no data for them. Failed fetches don't count
+    pub recovery_cooldown: Duration,
+    /// Fetch and diff as usual but write nothing, not even the sync status; the
+    /// report lists what would have been written
+    pub dry_run: bool,
+    /// Standard deviations from the trailing mean at which a new price counts
+    /// as a spike
+    pub anomaly_std_devs: f64,
+}
+
+impl Default for PriceSyncConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: MAX_IDS_PER_REQUEST,
+            concurrency: CHUNK_CONCURRENCY,
+            log_every: 10,
+            fee: crate::TRADING_POST_FEE,
+            recovery_concurrency: 4,
+            recovery_cooldown: Duration::from_secs(7 * 86400),
+            dry_run: false,
+            anomaly_std_devs: 4.0,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PriceSync {
     db: Surreal<Any>,
     gw2: Gw2Client,
+    config: PriceSyncConfig,
+    progress: ProgressSender,
 }
 
 impl PriceSync {
@@ -17,54 +123,393 @@ impl PriceSync {
         Self {
             db,
             gw2: Gw2Client::new(),
+            config: PriceSyncConfig::default(),
+            progress: ProgressSender::default(),
+        }
+    }
+
+    /// Emits `SyncProgress` events per price chunk and per recovered item.
+    pub fn with_progress(mut self, sender: tokio::sync::mpsc::Sender<SyncProgress>) -> Self {
+        self.progress = ProgressSender::new(sender);
+        self
+    }
+
+    pub fn with_config(mut self, config: PriceSyncConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Uses `gw2` instead of a default client, e.g. to share its rate limit.
+    pub fn with_client(mut self, gw2: Gw2Client) -> Self {
+        self.gw2 = gw2;
+        self
+    }
+
+    pub async fn run_sync(&self) -> Result<PriceSyncReport, Box<dyn std::error::Error>> {
+        let result = self.sync_prices().await;
+        if self.config.dry_run {
+            return result;
         }
+        sync_status::record_outcome(&self.db, sync_status::PRICE_SYNC, result).await
     }
 
-    pub async fn run_sync(&self) -> Result<(), Box<dyn std::error::Error>> {
+    async fn sync_prices(&self) -> Result<PriceSyncReport, Box<dyn std::error::Error>> {
         println!("Starting Price Sync...");
+        let started = std::time::Instant::now();
+        let mut report = PriceSyncReport::default();
         let all_ids = self.gw2.fetch_all_price_ids().await?;
         println!("Found {} prices to sync.", all_ids.len());
 
-        let chunks = all_ids.chunks(200);
-        for (i, chunk) in chunks.enumerate() {
-            if i % 10 == 0 {
+        let chunks = chunk_ids(&all_ids, self.config.chunk_size);
+        let total = chunks.len();
+        let mut done = 0;
+        let mut fetches = futures::stream::iter(chunks.into_iter().enumerate())
+            .map(|(i, ids)| {
+                let gw2 = self.gw2.clone();
+                async move { (i, gw2.fetch_prices_chunk(&ids).await) }
+            })
+            .buffer_unordered(self.config.concurrency);
+        let mut failed = Vec::new();
+        while let Some((i, chunk)) = fetches.next().await {
+            if i % self.config.log_every.max(1) == 0 {
                 println!("Syncing price chunk {}...", i + 1);
             }
-            let prices = self.gw2.fetch_prices_chunk(chunk).await?;
-
-            for history in &prices {
-                let item_id = history.item.clone();
-
-                // 1. Update the item record with current price information for quick lookup
-                let _: Option<serde::de::IgnoredAny> = self
-                    .db
-                    .update(&item_id)
-                    .merge(serde_json::json!({
-                        "buys": {
-                            "quantity": history.buy_quantity,
-                            "unit_price": history.buy_price,
-                        },
-                        "sells": {
-                            "quantity": history.sell_quantity,
-                            "unit_price": history.sell_price,
-                        },
-                        "last_price_update": history.timestamp,
-                    }))
-                    .await?;
+            done += 1;
+            self.progress.report(SyncPhase::PriceSync, done, total);
+            // One bad chunk shouldn't stall the rest of the run; what it fetched is
+            // kept and the rest of its ids are retried below
+            let chunk = match chunk {
+                Ok(chunk) => {
+                    report.chunks += 1;
+                    chunk
+                }
+                Err(e) => {
+                    eprintln!("Price chunk {} failed: {}", i + 1, e);
+                    e.partial
+                }
+            };
+            if !chunk.missing.is_empty() {
+                println!(
+                    "No trading-post listing for {} items in chunk {}.",
+                    chunk.missing.len(),
+                    i + 1
+                );
+            }
+            failed.extend(chunk.failed);
+            self.store_prices(chunk.records, &mut report).await?;
+        }
+
+        // Give failed chunks and unparseable entries one more chance; they're often
+        // transient upstream glitches
+        if !failed.is_empty() {
+            println!("Retrying {} failed prices...", failed.len());
+            match self.gw2.fetch_prices_chunk(&failed).await {
+                Ok(retry) => {
+                    if !retry.failed.is_empty() {
+                        eprintln!(
+                            "{} prices still failed and were left stale.",
+                            retry.failed.len()
+                        );
+                    }
+                    report.errors += retry.failed.len();
+                    self.store_prices(retry.records, &mut report).await?;
+                }
+                Err(e) => {
+                    eprintln!("Retrying {} failed prices failed: {}", failed.len(), e);
+                    report.errors += e.partial.failed.len();
+                    self.store_prices(e.partial.records, &mut report).await?;
+                }
+            }
+        }
+
+        // Alerts fire on the prices just written; a failure here shouldn't fail the sync
+        if !self.config.dry_run {
+            match crate::alert::check(&self.db).await {
+                Ok(triggered) => report.alerts_triggered = triggered,
+                Err(e) => eprintln!("Failed to check price alerts: {}", e),
             }
+        }
 
-            // 2. Insert historical records for tracking trends (Batch)
-            let _: Result<Vec<serde::de::IgnoredAny>, _> =
-                self.db.insert("item_history").content(prices).await;
+        // Craft costs follow the ingredient prices just written; also best effort
+        if !self.config.dry_run
+            && !report.changed.is_empty()
+            && let Err(e) = crate::recipe_sync::update_craft_costs(&self.db).await
+        {
+            eprintln!("Failed to update craft costs: {}", e);
+        }
+
+        // Only a moved price can start or end a spike; also best effort
+        if !self.config.dry_run {
+            match self.flag_anomalies(&report.changed).await {
+                Ok(anomalies) => report.anomalies = anomalies,
+                Err(e) => eprintln!("Failed to check price anomalies: {}", e),
+            }
         }
 
-        println!("Price sync complete.");
+        // Tell API clients about the new prices; like alerts, best effort
+        if !self.config.dry_run {
+            let update = PriceUpdate {
+                finished_at: chrono::Utc::now(),
+                items_updated: report.items_updated,
+                changed: report.changed.clone(),
+            };
+            if let Err(e) = price_updates::publish(&self.db, &update).await {
+                eprintln!("Failed to publish price update: {}", e);
+            }
+        }
+
+        report.elapsed = started.elapsed();
+        if self.config.dry_run {
+            println!("Dry run: nothing below was written.");
+        }
+        println!(
+            "Price sync complete: {} items updated, {} history rows, {} chunks, {} errors, {} alerts, {} anomalies in {:?}.",
+            report.items_updated,
+            report.history_inserted,
+            report.chunks,
+            report.errors,
+            report.alerts_triggered,
+            report.anomalies,
+            report.elapsed
+        );
+        Ok(report)
+    }
+
+    /// Writes current prices onto the item records, and a history row for each
+    /// item whose buy or sell price moved since the last sync.
+    async fn store_prices(
+        &self,
+        mut prices: Vec<HistoryRecord>,
+        report: &mut PriceSyncReport,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if prices.is_empty() {
+            return Ok(());
+        }
+
+        // 0. Read the prices we stored last time, before overwriting them
+        #[derive(serde::Deserialize)]
+        struct StoredPrice {
+            id: surrealdb::RecordId,
+            buy: Option<i64>,
+            sell: Option<i64>,
+        }
+        let items: Vec<surrealdb::RecordId> = prices.iter().map(|p| p.item.clone()).collect();
+        let stored: Vec<StoredPrice> = timed_query(
+            "price_sync_read_prices",
+            self.db
+                .query("SELECT id, buys.unit_price AS buy, sells.unit_price AS sell FROM $items WHERE is_tradeable = true")
+                .bind(("items", items)),
+        )
+        .await?
+        .take(0)?;
+        let stored: std::collections::HashMap<String, (Option<i64>, Option<i64>)> = stored
+            .into_iter()
+            .map(|s| (s.id.to_string(), (s.buy, s.sell)))
+            .collect();
+        // Prices for items we don't know or that can't be traded are only write noise
+        prices.retain(|p| stored.contains_key(&p.item.to_string()));
+        if prices.is_empty() {
+            return Ok(());
+        }
+        report.items_updated += prices.len();
+        let moved = |p: &HistoryRecord| {
+            stored.get(&p.item.to_string()) != Some(&(Some(p.buy_price), Some(p.sell_price)))
+        };
+        if self.config.dry_run {
+            report.history_inserted += prices.iter().filter(|p| moved(p)).count();
+            return Ok(());
+        }
+
+        // 1. Insert historical records for tracking trends (Batch), skipping
+        //    unchanged prices so the table only grows when the market moves. This
+        //    goes first: if it fails, those items keep their stored prices, so the
+        //    next sync still sees the move and writes it then
+        let (changed, mut prices): (Vec<HistoryRecord>, Vec<HistoryRecord>) =
+            prices.into_iter().partition(|p| moved(p));
+        if !changed.is_empty() {
+            let count = changed.len();
+            match self
+                .insert_history(changed.clone(), LIVE_HISTORY_INSERT)
+                .await
+            {
+                Ok(()) => {
+                    report.history_inserted += count;
+                    report.changed.extend(changed.iter().filter_map(|p| {
+                        String::try_from(p.item.key().clone())
+                            .ok()?
+                            .parse::<u32>()
+                            .ok()
+                    }));
+                    prices.extend(changed);
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Failed to write {} history rows, leaving their prices for the next sync: {}",
+                        count, e
+                    );
+                    report.errors += count;
+                }
+            }
+        }
+        if prices.is_empty() {
+            return Ok(());
+        }
+
+        // 2. Update the item records with current price information for quick lookup,
+        //    in one round-trip for the whole chunk
+        let update = self
+            .db
+            .query(
+                "FOR $p IN $prices {
+                    LET $profit = math::round($p.sell_price * (1 - $fee)) - $p.buy_price;
+                    UPDATE $p.item MERGE {
+                        buys: { quantity: $p.buy_quantity, unit_price: $p.buy_price },
+                        sells: { quantity: $p.sell_quantity, unit_price: $p.sell_price },
+                        last_price_update: $p.timestamp,
+                        profit: $profit,
+                        liquidity: $p.liquidity,
+                        roi: IF $p.buy_price > 0 THEN $profit / $p.buy_price * 100 ELSE 0 END,
+                    };
+                    IF $p.changed {
+                        UPDATE $p.item SET price_changed_at = <datetime>$p.timestamp RETURN NONE;
+                    };
+                }",
+            )
+            .bind((
+                "prices",
+                prices
+                    .iter()
+                    .map(|record| PriceWrite {
+                        liquidity: crate::liquidity_score(
+                            record.buy_quantity,
+                            record.sell_quantity,
+                        ),
+                        changed: moved(record),
+                        record: record.clone(),
+                    })
+                    .collect::<Vec<_>>(),
+            ))
+            .bind(("fee", self.config.fee));
+        timed_query("price_sync_update_items", update)
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Compares the latest buy and sell price of each of `gw2_ids` with its
+    /// trailing history and stores `price_anomaly` plus the larger z-score as
+    /// `price_anomaly_magnitude`. Items with too little history are never
+    /// flagged and get no magnitude. Returns how many were flagged.
+    async fn flag_anomalies(&self, gw2_ids: &[u32]) -> surrealdb::Result<usize> {
+        #[derive(serde::Deserialize)]
+        struct Point {
+            item: surrealdb::RecordId,
+            buy_price: i64,
+            sell_price: i64,
+        }
+        #[derive(serde::Serialize)]
+        struct Anomaly {
+            gw2_id: u32,
+            flagged: bool,
+            magnitude: Option<f64>,
+        }
+
+        let mut flagged = 0;
+        for ids in gw2_ids.chunks(self.config.chunk_size) {
+            let items: Vec<surrealdb::RecordId> = ids
+                .iter()
+                .map(|id| surrealdb::RecordId::from(("item", id.to_string())))
+                .collect();
+            let points: Vec<Point> = timed_query(
+                "price_sync_anomaly_history",
+                self.db
+                    .query(
+                        // recorded_at is indexed, unlike the string timestamp
+                        "SELECT item, buy_price, sell_price, recorded_at FROM item_history
+                            WHERE item IN $items AND recorded_at >= $since ORDER BY recorded_at",
+                    )
+                    .bind(("items", items))
+                    .bind((
+                        "since",
+                        surrealdb::Datetime::from(chrono::Utc::now() - ANOMALY_WINDOW),
+                    )),
+            )
+            .await?
+            .take(0)?;
+            let mut series: std::collections::HashMap<String, Vec<(f64, f64)>> =
+                std::collections::HashMap::new();
+            for p in points {
+                series
+                    .entry(p.item.to_string())
+                    .or_default()
+                    .push((p.buy_price as f64, p.sell_price as f64));
+            }
+
+            let anomalies: Vec<Anomaly> = ids
+                .iter()
+                .map(|&gw2_id| {
+                    let key = surrealdb::RecordId::from(("item", gw2_id.to_string())).to_string();
+                    let magnitude = series.get(&key).and_then(|points| {
+                        let (latest, trailing) = points.split_last()?;
+                        let buys: Vec<f64> = trailing.iter().map(|p| p.0).collect();
+                        let sells: Vec<f64> = trailing.iter().map(|p| p.1).collect();
+                        let buy = indicators::z_score(&buys, latest.0)?;
+                        let sell = indicators::z_score(&sells, latest.1)?;
+                        Some(if buy.abs() > sell.abs() { buy } else { sell })
+                    });
+                    Anomaly {
+                        gw2_id,
+                        flagged: magnitude.is_some_and(|m| m.abs() > self.config.anomaly_std_devs),
+                        magnitude,
+                    }
+                })
+                .collect();
+            flagged += anomalies.iter().filter(|a| a.flagged).count();
+            self.db
+                .query(
+                    "FOR $a IN $anomalies {
+                        UPDATE type::thing('item', <string>$a.gw2_id)
+                            SET price_anomaly = $a.flagged, price_anomaly_magnitude = $a.magnitude
+                            RETURN NONE;
+                    }",
+                )
+                .bind(("anomalies", anomalies))
+                .await?
+                .check()?;
+        }
+        Ok(flagged)
+    }
+
+    /// Inserts history rows with `query`, one of the `*_HISTORY_INSERT` statements,
+    /// so an item never ends up with two rows in the same minute.
+    async fn insert_history(
+        &self,
+        records: Vec<HistoryRecord>,
+        query: &'static str,
+    ) -> surrealdb::Result<()> {
+        timed_query(
+            "price_sync_insert_history",
+            self.db.query(query).bind(("records", records)),
+        )
+        .await?
+        .check()?;
         Ok(())
     }
 
     pub async fn recover_history(
         &self,
         token: CancellationToken,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self.recover_missing_history(token).await;
+        if self.config.dry_run {
+            return result;
+        }
+        sync_status::record_outcome(&self.db, sync_status::HISTORY_RECOVERY, result).await
+    }
+
+    async fn recover_missing_history(
+        &self,
+        token: CancellationToken,
     ) -> Result<(), Box<dyn std::error::Error>> {
         println!("Starting historical data recovery check...");
 
@@ -76,7 +521,15 @@ impl PriceSync {
         }
         let items: Vec<ItemId> = self
             .db
-            .query("SELECT id, gw2_id FROM item WHERE gw2_id != NONE AND is_tradeable = true")
+            .query(
+                "SELECT id, gw2_id FROM item WHERE gw2_id != NONE AND is_tradeable = true
+                    AND (last_recovery_attempt = NONE
+                        OR <datetime>last_recovery_attempt < time::now() - type::duration($cooldown))",
+            )
+            .bind((
+                "cooldown",
+                format!("{}s", self.config.recovery_cooldown.as_secs()),
+            ))
             .await?
             .take(0)?;
         println!("Checked {} items for history recovery.", items.len());
@@ -113,31 +566,81 @@ impl PriceSync {
             items_to_recover.len()
         );
 
-        // 3. Recover history for these items
-        for (i, item) in items_to_recover.iter().enumerate() {
+        // 3. Recover history for these items, a few at a time. The client's throttle
+        //    paces the actual requests to gw2bltc.
+        let total = items_to_recover.len();
+        let mut done = 0;
+        let gw2_ids: Vec<u32> = items_to_recover.iter().map(|item| item.gw2_id).collect();
+        let mut fetches = futures::stream::iter(gw2_ids.into_iter().enumerate())
+            .map(|(i, gw2_id)| {
+                let gw2 = self.gw2.clone();
+                async move { (i, gw2_id, gw2.fetch_item_history(gw2_id).await) }
+            })
+            .buffer_unordered(self.config.recovery_concurrency.max(1));
+
+        loop {
+            let (i, gw2_id, result) = tokio::select! {
+                next = fetches.next() => match next {
+                    Some(next) => next,
+                    None => break,
+                },
+                _ = token.cancelled() => {
+                    println!("Historical data recovery shutting down...");
+                    return Ok(());
+                }
+            };
             if i % 50 == 0 {
-                println!("Recovering history: {}/{}", i + 1, items_to_recover.len());
+                println!("Recovering history: {}/{}", i + 1, total);
+            }
+            done += 1;
+            self.progress
+                .report(SyncPhase::HistoryRecovery, done, total);
+
+            if self.config.dry_run {
+                if let Ok(history) = &result {
+                    println!(
+                        "Dry run: would store {} history rows for item {}.",
+                        history.len(),
+                        gw2_id
+                    );
+                }
+            } else if result.is_ok() {
+                // Only an answer, even an empty one, starts the cooldown; failed
+                // fetches stay eligible so an outage doesn't park every item
+                self.db
+                    .query("UPDATE type::thing('item', <string>$gw2_id) SET last_recovery_attempt = time::now()")
+                    .bind(("gw2_id", gw2_id))
+                    .await?
+                    .check()?;
             }
 
-            match self.gw2.fetch_item_history(item.gw2_id).await {
+            match result {
                 Ok(history) => {
-                    if !history.is_empty() {
-                        // Batch insert history records for efficiency
-                        let _: Result<Vec<serde::de::IgnoredAny>, _> =
-                            self.db.insert("item_history").content(history).await;
+                    if !self.config.dry_run
+                        && !history.is_empty()
+                        && let Err(e) = self.insert_history(history, RECOVERED_HISTORY_INSERT).await
+                    {
+                        eprintln!("Failed to store history for item {}: {}", gw2_id, e);
                     }
                 }
-                Err(e) => {
-                    eprintln!("Failed to fetch history for item {}: {}", item.gw2_id, e);
+                Err(Gw2Error::RateLimited { retry_after }) => {
+                    // bltc is still throttling us after a retry; not polling the stream
+                    // pauses every in-flight fetch too
+                    let backoff = retry_after.unwrap_or(RATE_LIMIT_BACKOFF);
+                    eprintln!(
+                        "gw2bltc rate limit on item {}, pausing recovery for {:?}",
+                        gw2_id, backoff
+                    );
+                    tokio::select! {
+                        _ = tokio::time::sleep(backoff) => {}
+                        _ = token.cancelled() => {
+                            println!("Historical data recovery shutting down...");
+                            return Ok(());
+                        }
+                    }
                 }
-            }
-
-            // Rate limiting for gw2bltc
-            tokio::select! {
-                _ = tokio::time::sleep(Duration::from_millis(100)) => {}
-                _ = token.cancelled() => {
-                    println!("Historical data recovery shutting down...");
-                    return Ok(());
+                Err(e) => {
+                    eprintln!("Failed to fetch history for item {}: {}", gw2_id, e);
                 }
             }
         }
@@ -174,6 +677,7 @@ mod tests {
     async fn setup_db() -> Surreal<Any> {
         let db = connect("mem://").await.unwrap();
         db.use_ns("test").use_db("test").await.unwrap();
+        crate::migrate(&db).await.unwrap();
         db
     }
 
@@ -182,38 +686,46 @@ mod tests {
         let db = setup_db().await;
         let server = MockServer::start().await;
 
-        // Create an item in DB so update() works
-        db.query("CREATE item:⟨1⟩ SET name = 'Test Item'")
-            .await
-            .unwrap();
+        // Create the items in DB so update() works; 2 can't be traded and 3 is unknown
+        db.query(
+            "CREATE item:⟨1⟩ SET name = 'Test Item', is_tradeable = true;
+             CREATE item:⟨2⟩ SET name = 'Bound Item', is_tradeable = false",
+        )
+        .await
+        .unwrap();
 
-        let mock_prices = vec![serde_json::json!({
-            "id": 1,
-            "buys": { "quantity": 100, "unit_price": 50 },
-            "sells": { "quantity": 200, "unit_price": 60 }
-        })];
+        let mock_prices: Vec<serde_json::Value> = (1..=3)
+            .map(|id| {
+                serde_json::json!({
+                    "id": id,
+                    "buys": { "quantity": 100, "unit_price": 50 },
+                    "sells": { "quantity": 200, "unit_price": 60 }
+                })
+            })
+            .collect();
 
         Mock::given(method("GET"))
             .and(path("/v2/commerce/prices"))
             .and(wiremock::matchers::query_param_is_missing("ids"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1]))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2, 3]))
             .mount(&server)
             .await;
 
         Mock::given(method("GET"))
             .and(path("/v2/commerce/prices"))
-            .and(wiremock::matchers::query_param("ids", "1"))
+            .and(wiremock::matchers::query_param("ids", "1,2,3"))
             .respond_with(ResponseTemplate::new(200).set_body_json(mock_prices))
             .mount(&server)
             .await;
 
         let gw2 = Gw2Client::with_urls(server.uri(), "".to_string());
-        let sync = PriceSync {
-            db: db.clone(),
-            gw2,
-        };
+        let sync = PriceSync::new(db.clone()).with_client(gw2);
 
-        sync.run_sync().await.unwrap();
+        let report = sync.run_sync().await.unwrap();
+        assert_eq!(report.items_updated, 1);
+        assert_eq!(report.history_inserted, 1);
+        assert_eq!(report.chunks, 1);
+        assert_eq!(report.errors, 0);
 
         // Verify item update
         #[derive(serde::Deserialize)]
@@ -228,7 +740,23 @@ mod tests {
         let item: PriceCheck = res.take::<Option<PriceCheck>>(0).unwrap().unwrap();
         assert_eq!(item.buys.unit_price, 50);
 
-        // Verify history insertion
+        // Profit, ROI and liquidity are stored alongside the prices
+        let stored: Option<serde_json::Value> = db
+            .query("SELECT profit, roi, liquidity FROM ONLY item:⟨1⟩")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        let stored = stored.unwrap();
+        let profit = (60.0 * (1.0 - crate::TRADING_POST_FEE)).round() - 50.0;
+        assert_eq!(stored["profit"].as_f64(), Some(profit));
+        assert_eq!(stored["roi"].as_f64(), Some(profit / 50.0 * 100.0));
+        assert_eq!(
+            stored["liquidity"].as_f64(),
+            Some(crate::liquidity_score(100, 200))
+        );
+
+        // Verify history insertion, for the tradeable item only
         let count: usize = db
             .query("SELECT count() FROM item_history GROUP ALL")
             .await
@@ -238,6 +766,118 @@ mod tests {
             .and_then(|v| v.get("count")?.as_u64())
             .unwrap_or(0) as usize;
         assert_eq!(count, 1);
+
+        // The run is recorded as the latest price sync success
+        let statuses = crate::sync_status::fetch_all(&db).await.unwrap();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].job, crate::sync_status::PRICE_SYNC);
+        assert!(statuses[0].last_success.is_some());
+        assert_eq!(statuses[0].last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_price_sync_refreshes_craft_costs() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        // Item 100 is crafted from two of item 10, last priced at 30 each
+        db.query(
+            "CREATE item:⟨10⟩ SET gw2_id = 10, is_tradeable = true, buys = { quantity: 1, unit_price: 30 };
+             CREATE item:⟨100⟩ SET gw2_id = 100, is_tradeable = true, craft_cost = 60;
+             CREATE recipe:⟨1⟩ CONTENT {
+                gw2_id: 1, output_item_id: 100, output_item_count: 1, min_rating: 0,
+                time_to_craft_ms: 0, disciplines: [], ingredients: [{ item_id: 10, count: 2 }]
+             };",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![10, 100]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param("ids", "10,100"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "id": 10, "buys": { "quantity": 1, "unit_price": 40 }, "sells": { "quantity": 1, "unit_price": 50 } },
+                { "id": 100, "buys": { "quantity": 1, "unit_price": 100 }, "sells": { "quantity": 1, "unit_price": 120 } }
+            ])))
+            .mount(&server)
+            .await;
+
+        let gw2 = Gw2Client::with_urls(server.uri(), "".to_string());
+        PriceSync::new(db.clone())
+            .with_client(gw2)
+            .run_sync()
+            .await
+            .unwrap();
+
+        let item: Option<serde_json::Value> = db
+            .query("SELECT craft_cost, craft_savings FROM ONLY item:⟨100⟩")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        let item = item.unwrap();
+        assert_eq!(item["craft_cost"], 80);
+        assert_eq!(item["craft_savings"], 20);
+    }
+
+    #[tokio::test]
+    async fn test_price_sync_dry_run_writes_nothing() {
+        // Unmigrated, so any table the run writes to shows up below
+        let db = connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        let server = MockServer::start().await;
+
+        db.query("CREATE item:⟨1⟩ SET name = 'Test Item', is_tradeable = true")
+            .await
+            .unwrap();
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param("ids", "1"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(vec![serde_json::json!({
+                    "id": 1,
+                    "buys": { "quantity": 100, "unit_price": 50 },
+                    "sells": { "quantity": 200, "unit_price": 60 }
+                })]),
+            )
+            .mount(&server)
+            .await;
+
+        let sync = PriceSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()))
+            .with_config(PriceSyncConfig {
+                dry_run: true,
+                ..PriceSyncConfig::default()
+            });
+        let report = sync.run_sync().await.unwrap();
+        assert_eq!(report.items_updated, 1);
+        assert_eq!(report.history_inserted, 1);
+
+        let item: Option<serde_json::Value> = db
+            .query("SELECT buys FROM ONLY item:⟨1⟩")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert!(item.unwrap()["buys"].is_null());
+        let tables: Option<serde_json::Value> =
+            db.query("INFO FOR DB").await.unwrap().take(0).unwrap();
+        let tables = tables.unwrap()["tables"].clone();
+        assert_eq!(tables.as_object().unwrap().len(), 1, "{tables}");
     }
 
     #[tokio::test]
@@ -259,10 +899,7 @@ mod tests {
             .await;
 
         let gw2 = Gw2Client::with_urls("".to_string(), server.uri());
-        let sync = PriceSync {
-            db: db.clone(),
-            gw2,
-        };
+        let sync = PriceSync::new(db.clone()).with_client(gw2);
         let token = CancellationToken::new();
 
         sync.recover_history(token).await.unwrap();
@@ -278,4 +915,468 @@ mod tests {
             .unwrap() as usize;
         assert_eq!(count, 1);
     }
+
+    #[tokio::test]
+    async fn test_price_sync_skips_unchanged_history() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        db.query("CREATE item:⟨1⟩ SET name = 'Test Item', is_tradeable = true; CREATE item:⟨2⟩ SET name = 'Other', is_tradeable = true")
+            .await
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2]))
+            .mount(&server)
+            .await;
+        // Item 1 keeps its price between runs, item 2's sell price moves
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param("ids", "1,2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![
+                serde_json::json!({ "id": 1, "buys": { "quantity": 1, "unit_price": 50 }, "sells": { "quantity": 1, "unit_price": 60 } }),
+                serde_json::json!({ "id": 2, "buys": { "quantity": 1, "unit_price": 10 }, "sells": { "quantity": 1, "unit_price": 20 } }),
+            ]))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param("ids", "1,2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![
+                serde_json::json!({ "id": 1, "buys": { "quantity": 5, "unit_price": 50 }, "sells": { "quantity": 5, "unit_price": 60 } }),
+                serde_json::json!({ "id": 2, "buys": { "quantity": 1, "unit_price": 10 }, "sells": { "quantity": 1, "unit_price": 25 } }),
+            ]))
+            .mount(&server)
+            .await;
+
+        let sync = PriceSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+        let mut first = sync.run_sync().await.unwrap().changed;
+        first.sort();
+        assert_eq!(first, vec![1, 2]);
+        assert_eq!(sync.run_sync().await.unwrap().changed, vec![2]);
+
+        // Each run is published for the API's live stream
+        let published: Vec<Vec<u32>> = db
+            .query("SELECT VALUE changed FROM price_updates")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(published.len(), 2);
+
+        let mut counts = Vec::new();
+        for id in ["1", "2"] {
+            let count = db
+                .query("SELECT count() FROM item_history WHERE item = type::thing('item', $id) GROUP ALL")
+                .bind(("id", id))
+                .await
+                .unwrap()
+                .take::<Option<serde_json::Value>>(0)
+                .unwrap()
+                .and_then(|v| v.get("count")?.as_u64())
+                .unwrap_or(0);
+            counts.push(count);
+        }
+        assert_eq!(counts, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_history_write_retried_next_sync() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        // A field type the price rows can't satisfy makes the history write fail
+        db.query(
+            "CREATE item:⟨1⟩ SET name = 'Test Item', is_tradeable = true;
+             DEFINE FIELD buy_price ON item_history TYPE string;",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param("ids", "1"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(vec![serde_json::json!({
+                    "id": 1,
+                    "buys": { "quantity": 100, "unit_price": 50 },
+                    "sells": { "quantity": 200, "unit_price": 60 }
+                })]),
+            )
+            .mount(&server)
+            .await;
+
+        let sync = PriceSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+        let report = sync.run_sync().await.unwrap();
+        assert_eq!(report.errors, 1);
+        assert_eq!(report.history_inserted, 0);
+        assert!(report.changed.is_empty());
+
+        // The stored price didn't advance, so the move isn't mistaken for unchanged
+        let buys: Option<serde_json::Value> = db
+            .query("SELECT VALUE buys FROM ONLY item:⟨1⟩")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(buys, None);
+
+        db.query("REMOVE FIELD buy_price ON item_history")
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+        let report = sync.run_sync().await.unwrap();
+        assert_eq!(report.errors, 0);
+        assert_eq!(report.history_inserted, 1);
+        assert_eq!(report.changed, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_price_sync_continues_past_failed_chunk() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        db.query("CREATE item:⟨1⟩ SET is_tradeable = true; CREATE item:⟨2⟩ SET is_tradeable = true; CREATE item:⟨3⟩ SET is_tradeable = true")
+            .await
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2, 3]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param("ids", "2"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+        for id in [1, 3] {
+            Mock::given(method("GET"))
+                .and(path("/v2/commerce/prices"))
+                .and(wiremock::matchers::query_param("ids", id.to_string()))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(vec![serde_json::json!({
+                        "id": id,
+                        "buys": { "quantity": 1, "unit_price": 10 },
+                        "sells": { "quantity": 1, "unit_price": 20 }
+                    })]),
+                )
+                .mount(&server)
+                .await;
+        }
+
+        let gw2 = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .retry_policy(crate::gw2_api::RetryPolicy {
+                max_retries: 0,
+                base_delay: Duration::from_millis(1),
+            })
+            .build();
+        let sync = PriceSync::new(db.clone())
+            .with_client(gw2)
+            .with_config(PriceSyncConfig {
+                chunk_size: 1,
+                ..PriceSyncConfig::default()
+            });
+
+        let report = sync.run_sync().await.unwrap();
+        assert_eq!(report.chunks, 2);
+        assert_eq!(report.items_updated, 2);
+        assert_eq!(report.errors, 1);
+
+        let mut priced: Vec<String> = db
+            .query("SELECT VALUE <string>record::id(id) FROM item WHERE buys != NONE")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        priced.sort();
+        assert_eq!(priced, vec!["1", "3"]);
+    }
+
+    #[tokio::test]
+    async fn test_recover_history_skips_recent_attempts() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        let recent = chrono::Utc::now().to_rfc3339();
+        db.query(
+            "CREATE item:⟨1⟩ SET gw2_id = 1, is_tradeable = true, last_recovery_attempt = $recent;
+             CREATE item:⟨2⟩ SET gw2_id = 2, is_tradeable = true;",
+        )
+        .bind(("recent", recent))
+        .await
+        .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/api/tp/chart/2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Vec::<Vec<i64>>::new()))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let sync = PriceSync::new(db.clone())
+            .with_client(Gw2Client::with_urls("".to_string(), server.uri()));
+        sync.recover_history(CancellationToken::new())
+            .await
+            .unwrap();
+
+        // Item 2 is now marked too, so a second pass fetches nothing
+        let attempted: Option<serde_json::Value> = db
+            .query("SELECT VALUE last_recovery_attempt != NONE FROM ONLY item:⟨2⟩")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(attempted, Some(serde_json::json!(true)));
+        sync.recover_history(CancellationToken::new())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recover_history_retries_failed_fetches() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        db.query("CREATE item:⟨1⟩ SET gw2_id = 1, is_tradeable = true")
+            .await
+            .unwrap();
+        Mock::given(method("GET"))
+            .and(path("/api/tp/chart/1"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let sync = PriceSync::new(db.clone())
+            .with_client(Gw2Client::with_urls("".to_string(), server.uri()));
+        sync.recover_history(CancellationToken::new())
+            .await
+            .unwrap();
+
+        // The outage didn't start a cooldown, so the next pass asks again
+        let attempted: Option<serde_json::Value> = db
+            .query("SELECT VALUE last_recovery_attempt != NONE FROM ONLY item:⟨1⟩")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(attempted, Some(serde_json::json!(false)));
+        sync.recover_history(CancellationToken::new())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_history_rows_unique_per_minute() {
+        let db = setup_db().await;
+        let sync = PriceSync::new(db.clone());
+        let row = |buy_price, seconds: i64| HistoryRecord {
+            item: surrealdb::RecordId::from(("item", "1")),
+            timestamp: chrono::DateTime::from_timestamp(1_700_000_040 + seconds, 0).unwrap(),
+            buy_price,
+            sell_price: 200,
+            buy_quantity: 10,
+            sell_quantity: 10,
+        };
+        let prices = || async {
+            let mut prices: Vec<i64> = db
+                .query("SELECT VALUE buy_price FROM item_history")
+                .await
+                .unwrap()
+                .take(0)
+                .unwrap();
+            prices.sort();
+            prices
+        };
+
+        // The same row twice only survives once
+        sync.insert_history(vec![row(100, 0), row(100, 0)], LIVE_HISTORY_INSERT)
+            .await
+            .unwrap();
+        assert_eq!(prices().await, vec![100]);
+
+        // A recovered row never displaces a live one in the same minute...
+        sync.insert_history(vec![row(90, 5)], RECOVERED_HISTORY_INSERT)
+            .await
+            .unwrap();
+        assert_eq!(prices().await, vec![100]);
+
+        // ...but fills minutes without one, and a later live row replaces it
+        sync.insert_history(vec![row(80, 120)], RECOVERED_HISTORY_INSERT)
+            .await
+            .unwrap();
+        assert_eq!(prices().await, vec![80, 100]);
+        sync.insert_history(vec![row(110, 125)], LIVE_HISTORY_INSERT)
+            .await
+            .unwrap();
+        assert_eq!(prices().await, vec![100, 110]);
+    }
+
+    #[tokio::test]
+    async fn test_price_alert_triggers_once() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        db.query("CREATE item:⟨1⟩ SET name = 'Test Item', is_tradeable = true")
+            .await
+            .unwrap();
+        let alert = crate::alert::create(
+            &db,
+            crate::alert::NewAlert {
+                gw2_id: 1,
+                field: crate::alert::AlertField::SellPrice,
+                comparator: crate::alert::Comparator::Below,
+                threshold: 100,
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(alert.active);
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1]))
+            .mount(&server)
+            .await;
+        let price_mock = |sell: i64| {
+            Mock::given(method("GET"))
+                .and(path("/v2/commerce/prices"))
+                .and(wiremock::matchers::query_param("ids", "1"))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(vec![serde_json::json!({
+                        "id": 1,
+                        "buys": { "quantity": 100, "unit_price": 50 },
+                        "sells": { "quantity": 200, "unit_price": sell }
+                    })]),
+                )
+        };
+        let sync = PriceSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+
+        // Above the threshold, nothing fires yet
+        let guard = price_mock(120).mount_as_scoped(&server).await;
+        assert_eq!(sync.run_sync().await.unwrap().alerts_triggered, 0);
+        drop(guard);
+
+        // Crossing below fires, and staying below doesn't fire again
+        price_mock(90).mount(&server).await;
+        assert_eq!(sync.run_sync().await.unwrap().alerts_triggered, 1);
+        assert_eq!(sync.run_sync().await.unwrap().alerts_triggered, 0);
+
+        let triggered: Vec<serde_json::Value> = db
+            .query("SELECT gw2_id, price, threshold FROM triggered_alerts")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(triggered.len(), 1);
+        assert_eq!(triggered[0]["price"], 90);
+        assert_eq!(triggered[0]["threshold"], 100);
+        let active: Option<bool> = db
+            .query("SELECT VALUE active FROM ONLY $alert")
+            .bind(("alert", alert.id))
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(active, Some(false));
+    }
+
+    #[tokio::test]
+    async fn test_price_spike_flagged() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        db.query(
+            "CREATE item:⟨1⟩ SET is_tradeable = true; CREATE item:⟨2⟩ SET is_tradeable = true",
+        )
+        .await
+        .unwrap();
+        // Item 1 has a day of steady prices around 100, item 2 only a few points
+        let sync = PriceSync::new(db.clone())
+            .with_client(Gw2Client::with_urls(server.uri(), "".to_string()));
+        let now = chrono::Utc::now();
+        let row = |id: &str, hours: i64, sell_price: i64| HistoryRecord {
+            item: surrealdb::RecordId::from(("item", id)),
+            timestamp: now - chrono::Duration::hours(hours),
+            buy_price: 80,
+            sell_price,
+            buy_quantity: 10,
+            sell_quantity: 10,
+        };
+        let mut history: Vec<HistoryRecord> = (1..=24)
+            .map(|h| row("1", h, if h % 2 == 0 { 95 } else { 105 }))
+            .collect();
+        history.extend((1..=3).map(|h| row("2", h, 100)));
+        sync.insert_history(history, LIVE_HISTORY_INSERT)
+            .await
+            .unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/v2/commerce/prices"))
+            .and(wiremock::matchers::query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2]))
+            .mount(&server)
+            .await;
+        let price_mock = |sell: i64| {
+            let price = |id| {
+                serde_json::json!({
+                    "id": id,
+                    "buys": { "quantity": 10, "unit_price": 80 },
+                    "sells": { "quantity": 10, "unit_price": sell }
+                })
+            };
+            Mock::given(method("GET"))
+                .and(path("/v2/commerce/prices"))
+                .and(wiremock::matchers::query_param("ids", "1,2"))
+                .respond_with(ResponseTemplate::new(200).set_body_json(vec![price(1), price(2)]))
+        };
+        let flags = || async {
+            let flags: Vec<serde_json::Value> = db
+                .query("SELECT record::id(id) AS key, price_anomaly, price_anomaly_magnitude FROM item ORDER BY key")
+                .await
+                .unwrap()
+                .take(0)
+                .unwrap();
+            flags
+        };
+
+        // One outsized listing: 1000 is far beyond a series swinging by 5
+        let guard = price_mock(1000).mount_as_scoped(&server).await;
+        assert_eq!(sync.run_sync().await.unwrap().anomalies, 1);
+        drop(guard);
+        let items = flags().await;
+        assert_eq!(items[0]["price_anomaly"], true);
+        assert!(items[0]["price_anomaly_magnitude"].as_f64().unwrap() > 100.0);
+        // Too little history to judge, so no flag however far it moved
+        assert_eq!(items[1]["price_anomaly"], false);
+        assert!(items[1]["price_anomaly_magnitude"].is_null());
+
+        // Back to normal clears the flag
+        price_mock(100).mount(&server).await;
+        assert_eq!(sync.run_sync().await.unwrap().anomalies, 0);
+        let items = flags().await;
+        assert_eq!(items[0]["price_anomaly"], false);
+        assert!(items[0]["price_anomaly_magnitude"].as_f64().unwrap().abs() < 4.0);
+    }
 }
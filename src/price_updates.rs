@@ -0,0 +1,109 @@
+// Price sync notifications. The scraper records each finished price sync in
+// `price_updates`; the API follows that table with a live query and fans the
+// rows out to its `/api/stream` clients.
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use surrealdb::Surreal;
+use surrealdb::engine::any::Any;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+const TABLE: &str = "price_updates";
+
+/// Pause before subscribing again after the live query failed or ended.
+const RESUBSCRIBE_DELAY: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// One completed price sync.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct PriceUpdate {
+    pub finished_at: DateTime<Utc>,
+    /// Item records whose prices were written
+    pub items_updated: usize,
+    /// Gw2 ids of the items whose buy or sell price moved
+    pub changed: Vec<u32>,
+}
+
+/// Records `update` for the API to pick up. Rows older than a day are dropped on
+/// the way; they only exist to be seen live.
+pub async fn publish(db: &Surreal<Any>, update: &PriceUpdate) -> surrealdb::Result<()> {
+    db.query(
+        "CREATE price_updates CONTENT $update RETURN NONE;
+        DELETE price_updates WHERE <datetime>finished_at < time::now() - 1d;",
+    )
+    .bind(("update", update.clone()))
+    .await?
+    .check()?;
+    Ok(())
+}
+
+/// Sends every newly published `PriceUpdate` to `updates` until `token` is
+/// cancelled, resubscribing when the live query drops.
+pub async fn forward(
+    db: Surreal<Any>,
+    updates: broadcast::Sender<PriceUpdate>,
+    token: CancellationToken,
+) {
+    loop {
+        match db.select::<Vec<PriceUpdate>>(TABLE).live().await {
+            Ok(mut stream) => loop {
+                tokio::select! {
+                    notification = stream.next() => match notification {
+                        Some(Ok(n)) if n.action == surrealdb::Action::Create => {
+                            // No subscribers is fine, there's just nobody to tell
+                            let _ = updates.send(n.data);
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => eprintln!("Price update notification error: {}", e),
+                        None => break,
+                    },
+                    _ = token.cancelled() => return,
+                }
+            },
+            Err(e) => eprintln!("Failed to follow price updates: {}", e),
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(RESUBSCRIBE_DELAY) => {}
+            _ = token.cancelled() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use surrealdb::engine::any::connect;
+
+    #[tokio::test]
+    async fn test_forward_published_updates() {
+        let db = connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        crate::migrate(&db).await.unwrap();
+        let (sender, mut receiver) = broadcast::channel(8);
+        let token = CancellationToken::new();
+        let forwarder = tokio::spawn(forward(db.clone(), sender, token.clone()));
+
+        let update = PriceUpdate {
+            finished_at: Utc::now(),
+            items_updated: 3,
+            changed: vec![19684, 19721],
+        };
+        // The live query subscribes asynchronously; publish until it's listening
+        let received = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                publish(&db, &update).await.unwrap();
+                if let Ok(Ok(received)) =
+                    tokio::time::timeout(std::time::Duration::from_millis(100), receiver.recv())
+                        .await
+                {
+                    return received;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received, update);
+
+        token.cancel();
+        forwarder.await.unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+use tokio::sync::mpsc;
+
+/// Which long-running job a progress event belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPhase {
+    ItemSync,
+    PriceSync,
+    HistoryRecovery,
+}
+
+/// `current` of `total` units (chunks or items) of `phase` are done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct SyncProgress {
+    pub phase: SyncPhase,
+    pub current: usize,
+    pub total: usize,
+}
+
+/// Optional progress sink shared by the sync workers. Events are dropped rather
+/// than slowing a sync down when the receiver falls behind.
+#[derive(Clone, Default)]
+pub struct ProgressSender(Option<mpsc::Sender<SyncProgress>>);
+
+impl ProgressSender {
+    pub fn new(sender: mpsc::Sender<SyncProgress>) -> Self {
+        Self(Some(sender))
+    }
+
+    pub fn report(&self, phase: SyncPhase, current: usize, total: usize) {
+        if let Some(sender) = &self.0 {
+            let _ = sender.try_send(SyncProgress {
+                phase,
+                current,
+                total,
+            });
+        }
+    }
+}
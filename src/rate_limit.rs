@@ -0,0 +1,136 @@
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use dashmap::DashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Token-bucket rate limiter keyed on client IP.
+///
+/// Each IP may burst up to `requests_per_minute` requests, refilled continuously
+/// at the same rate.
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<DashMap<IpAddr, Bucket>>,
+    capacity: f64,
+    refill_per_sec: f64,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32) -> Self {
+        let capacity = requests_per_minute.max(1) as f64;
+        Self {
+            buckets: Arc::new(DashMap::new()),
+            capacity,
+            refill_per_sec: capacity / 60.0,
+        }
+    }
+
+    /// Takes a token for `ip`, or returns how long until one is available.
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut bucket = self.buckets.entry(ip).or_insert(Bucket {
+            tokens: self.capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(Duration::from_secs_f64(missing / self.refill_per_sec))
+        }
+    }
+
+    /// Drops buckets that have fully refilled, so idle clients don't pin memory.
+    pub fn prune_idle(&self) {
+        let now = Instant::now();
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens + elapsed * self.refill_per_sec < self.capacity
+        });
+    }
+}
+
+pub async fn rate_limit_middleware(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Requests without connection info (e.g. in-process tests) share one bucket
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+
+    match limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_bucket_exhausts_per_ip() {
+        let limiter = RateLimiter::new(2);
+        let a = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        assert!(limiter.check(a).is_ok());
+        assert!(limiter.check(a).is_ok());
+        let retry_after = limiter.check(a).unwrap_err();
+        assert!(retry_after <= Duration::from_secs(30));
+
+        // Other clients are unaffected
+        assert!(limiter.check(b).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_middleware_returns_429_with_retry_after() {
+        let limiter = RateLimiter::new(1);
+        let app = Router::new().route("/", get(|| async { "ok" })).layer(
+            axum::middleware::from_fn_with_state(limiter, rate_limit_middleware),
+        );
+
+        let first = app
+            .clone()
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(first.status(), StatusCode::OK);
+
+        let second = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(second.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(second.headers()[header::RETRY_AFTER], "60");
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// One entry of a recipe's `ingredients`, as returned by `/v2/recipes`.
+#[derive(Debug, Deserialize)]
+pub struct RawIngredient {
+    /// Older payloads call it `item_id`
+    #[serde(alias = "item_id")]
+    pub id: u32,
+    pub count: u32,
+    /// "Item", "Currency" or "GuildUpgrade"; missing on older payloads, which only had items
+    pub r#type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawRecipe {
+    pub id: u32,
+    pub output_item_id: u32,
+    pub output_item_count: u32,
+    #[serde(default)]
+    pub min_rating: u32,
+    #[serde(default)]
+    pub time_to_craft_ms: u32,
+    #[serde(default)]
+    pub disciplines: Vec<String>,
+    pub ingredients: Vec<RawIngredient>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ingredient {
+    pub item_id: u32,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    pub gw2_id: u32,
+    pub output_item_id: u32,
+    pub output_item_count: u32,
+    pub min_rating: u32,
+    pub time_to_craft_ms: u32,
+    pub disciplines: Vec<String>,
+    pub ingredients: Vec<Ingredient>,
+}
+
+impl Recipe {
+    /// Recipes consuming currencies or guild upgrades can't be priced off the
+    /// trading post, so they're dropped.
+    pub fn from_raw(raw: RawRecipe) -> Option<Self> {
+        let ingredients = raw
+            .ingredients
+            .into_iter()
+            .map(|i| match i.r#type.as_deref() {
+                None | Some("Item") => Some(Ingredient {
+                    item_id: i.id,
+                    count: i.count,
+                }),
+                Some(_) => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            gw2_id: raw.id,
+            output_item_id: raw.output_item_id,
+            output_item_count: raw.output_item_count,
+            min_rating: raw.min_rating,
+            time_to_craft_ms: raw.time_to_craft_ms,
+            disciplines: raw.disciplines,
+            ingredients,
+        })
+    }
+
+    /// Cost in copper of crafting one output unit from ingredients bought at
+    /// `prices` (gw2 id to unit price), rounded up. `None` when an ingredient
+    /// has no price.
+    pub fn craft_cost(&self, prices: &HashMap<u32, i64>) -> Option<i64> {
+        let total = self.ingredients.iter().try_fold(0i64, |total, i| {
+            Some(total + prices.get(&i.item_id)? * i.count as i64)
+        })?;
+        let count = self.output_item_count.max(1) as i64;
+        Some((total + count - 1) / count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recipe_parsing() {
+        let raw: RawRecipe = serde_json::from_value(serde_json::json!({
+            "id": 7319,
+            "type": "Refinement",
+            "output_item_id": 19713,
+            "output_item_count": 1,
+            "min_rating": 0,
+            "time_to_craft_ms": 1000,
+            "disciplines": ["Artificer", "Huntsman"],
+            "flags": ["AutoLearned"],
+            "ingredients": [
+                { "type": "Item", "id": 19723, "count": 2 },
+                { "item_id": 19704, "count": 1 }
+            ],
+            "chat_link": "[&CZccAAA=]"
+        }))
+        .unwrap();
+
+        let recipe = Recipe::from_raw(raw).unwrap();
+        assert_eq!(recipe.gw2_id, 7319);
+        assert_eq!(recipe.output_item_id, 19713);
+        assert_eq!(recipe.disciplines, vec!["Artificer", "Huntsman"]);
+        assert_eq!(
+            recipe.ingredients,
+            vec![
+                Ingredient {
+                    item_id: 19723,
+                    count: 2
+                },
+                Ingredient {
+                    item_id: 19704,
+                    count: 1
+                },
+            ]
+        );
+
+        let raw: RawRecipe = serde_json::from_value(serde_json::json!({
+            "id": 12000,
+            "output_item_id": 1,
+            "output_item_count": 1,
+            "ingredients": [{ "type": "Currency", "id": 2, "count": 10 }]
+        }))
+        .unwrap();
+        assert!(Recipe::from_raw(raw).is_none());
+    }
+
+    #[test]
+    fn test_craft_cost_two_ingredients() {
+        let recipe = Recipe {
+            gw2_id: 1,
+            output_item_id: 100,
+            output_item_count: 2,
+            min_rating: 0,
+            time_to_craft_ms: 0,
+            disciplines: vec![],
+            ingredients: vec![
+                Ingredient {
+                    item_id: 10,
+                    count: 3,
+                },
+                Ingredient {
+                    item_id: 11,
+                    count: 1,
+                },
+            ],
+        };
+        let mut prices = HashMap::from([(10, 25), (11, 40)]);
+
+        // (3 * 25 + 40) / 2 = 57.5, rounded up
+        assert_eq!(recipe.craft_cost(&prices), Some(58));
+        prices.remove(&11);
+        assert_eq!(recipe.craft_cost(&prices), None);
+    }
+}
@@ -0,0 +1,303 @@
+use crate::gw2_api::{CHUNK_CONCURRENCY, Gw2Client, chunk_ids};
+use crate::recipe::Recipe;
+use crate::sync_status;
+use futures::StreamExt;
+use std::collections::HashMap;
+use surrealdb::Surreal;
+use surrealdb::engine::any::Any;
+use tokio_util::sync::CancellationToken;
+
+/// Cheapest way found to craft one unit of an item.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct CraftCost {
+    pub gw2_id: u32,
+    /// Copper spent on ingredients at their current buy prices
+    pub cost: i64,
+}
+
+/// Stores every recipe from `/v2/recipes` as `recipe:<id>`, then prices each
+/// craftable item: `craft_cost` is the cheapest recipe's ingredient cost per
+/// unit, and `craft_savings` the item's own buy price minus that, positive when
+/// crafting beats buying. The price sync refreshes both as prices move, see
+/// `update_craft_costs`.
+#[derive(Clone)]
+pub struct RecipeSync {
+    db: Surreal<Any>,
+    gw2: Gw2Client,
+}
+
+impl RecipeSync {
+    pub fn new(db: Surreal<Any>) -> Self {
+        Self {
+            db,
+            gw2: Gw2Client::new(),
+        }
+    }
+
+    /// Uses `gw2` instead of a default client, e.g. to share its rate limit.
+    pub fn with_client(mut self, gw2: Gw2Client) -> Self {
+        self.gw2 = gw2;
+        self
+    }
+
+    /// Syncs recipes and refreshes craft costs. Returns how many recipes were stored.
+    pub async fn run_sync(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let result = self.sync().await;
+        sync_status::record_outcome(&self.db, sync_status::RECIPE_SYNC, result).await
+    }
+
+    async fn sync(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let stored = self.sync_recipes().await?;
+        update_craft_costs(&self.db).await?;
+        Ok(stored)
+    }
+
+    async fn sync_recipes(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        println!("Starting Recipe Sync...");
+        let ids = self.gw2.fetch_all_recipe_ids().await?;
+        println!("Found {} recipes.", ids.len());
+
+        let chunks = chunk_ids(&ids, self.gw2.chunk_size());
+        let mut fetches = futures::stream::iter(chunks)
+            .map(|chunk| {
+                let gw2 = self.gw2.clone();
+                async move { (chunk.len(), gw2.fetch_recipes_chunk(&chunk).await) }
+            })
+            .buffer_unordered(CHUNK_CONCURRENCY);
+        let mut stored = 0;
+        let mut skipped = 0;
+        while let Some((len, chunk)) = fetches.next().await {
+            // One bad chunk shouldn't sink the rest; its recipes are fetched next run
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    eprintln!("Recipe chunk failed: {}", e);
+                    skipped += len;
+                    continue;
+                }
+            };
+            skipped += chunk.failed.len();
+            stored += chunk.recipes.len();
+            if !chunk.recipes.is_empty() {
+                self.db
+                    .query(
+                        "FOR $recipe IN $recipes {
+                            UPSERT type::thing('recipe', <string>$recipe.gw2_id) CONTENT $recipe;
+                        }",
+                    )
+                    .bind(("recipes", chunk.recipes))
+                    .await?
+                    .check()?;
+            }
+        }
+
+        println!(
+            "Recipe sync complete: {} stored, {} skipped.",
+            stored, skipped
+        );
+        Ok(stored)
+    }
+
+    pub async fn spawn(self, interval_duration: std::time::Duration, token: CancellationToken) {
+        let mut interval = tokio::time::interval(interval_duration);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(e) = self.run_sync().await {
+                        eprintln!("Recipe sync error: {}", e);
+                    }
+                }
+                _ = token.cancelled() => {
+                    println!("Recipe sync worker shutting down...");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Prices every stored recipe with current ingredient buy prices and writes
+/// the cheapest per output item. Items no recipe can be priced for lose their
+/// craft cost. Returns the costs written.
+pub async fn update_craft_costs(
+    db: &Surreal<Any>,
+) -> Result<Vec<CraftCost>, Box<dyn std::error::Error>> {
+    #[derive(serde::Deserialize)]
+    struct BuyPrice {
+        gw2_id: u32,
+        price: i64,
+    }
+    let mut response = db
+        .query("SELECT * OMIT id FROM recipe")
+        .query("SELECT gw2_id, buys.unit_price AS price FROM item WHERE buys.unit_price > 0")
+        .await?
+        .check()?;
+    let recipes: Vec<Recipe> = response.take(0)?;
+    let prices: HashMap<u32, i64> = response
+        .take::<Vec<BuyPrice>>(1)?
+        .into_iter()
+        .map(|p| (p.gw2_id, p.price))
+        .collect();
+
+    let costs = cheapest_craft_costs(&recipes, &prices);
+    let ids: Vec<u32> = costs.iter().map(|c| c.gw2_id).collect();
+    db.query(
+        "FOR $c IN $costs {
+            UPDATE type::thing('item', <string>$c.gw2_id)
+                SET craft_cost = $c.cost, craft_savings = buys.unit_price - $c.cost
+                RETURN NONE;
+        };
+        UPDATE item SET craft_cost = NONE, craft_savings = NONE
+            WHERE craft_cost != NONE AND gw2_id NOTINSIDE $ids RETURN NONE;",
+    )
+    .bind(("costs", costs.clone()))
+    .bind(("ids", ids))
+    .await?
+    .check()?;
+    println!("Updated craft costs of {} items.", costs.len());
+    Ok(costs)
+}
+
+/// The cheapest priced recipe per output item, sorted by gw2 id.
+fn cheapest_craft_costs(recipes: &[Recipe], prices: &HashMap<u32, i64>) -> Vec<CraftCost> {
+    let mut cheapest: HashMap<u32, i64> = HashMap::new();
+    for recipe in recipes {
+        if let Some(cost) = recipe.craft_cost(prices) {
+            let entry = cheapest.entry(recipe.output_item_id).or_insert(cost);
+            *entry = (*entry).min(cost);
+        }
+    }
+    let mut costs: Vec<CraftCost> = cheapest
+        .into_iter()
+        .map(|(gw2_id, cost)| CraftCost { gw2_id, cost })
+        .collect();
+    costs.sort_by_key(|c| c.gw2_id);
+    costs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use surrealdb::engine::any::connect;
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn setup_db() -> Surreal<Any> {
+        let db = connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+        crate::migrate(&db).await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_recipe_sync_prices_craftable_items() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/recipes"))
+            .and(query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2]))
+            .mount(&server)
+            .await;
+        // Two ways to make item 100; the second is cheaper
+        Mock::given(method("GET"))
+            .and(path("/v2/recipes"))
+            .and(query_param("ids", "1,2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {
+                    "id": 1,
+                    "output_item_id": 100,
+                    "output_item_count": 1,
+                    "ingredients": [
+                        { "type": "Item", "id": 10, "count": 2 },
+                        { "type": "Item", "id": 11, "count": 1 }
+                    ]
+                },
+                {
+                    "id": 2,
+                    "output_item_id": 100,
+                    "output_item_count": 1,
+                    "ingredients": [{ "type": "Item", "id": 11, "count": 3 }]
+                }
+            ])))
+            .mount(&server)
+            .await;
+
+        for (id, buy) in [(10, 30), (11, 15), (100, 80)] {
+            db.query("CREATE type::thing('item', <string>$id) SET gw2_id = $id, buys = { quantity: 1, unit_price: $buy }")
+                .bind(("id", id))
+                .bind(("buy", buy))
+                .await
+                .unwrap()
+                .check()
+                .unwrap();
+        }
+        // A stale cost left from an earlier run
+        db.query("UPDATE item:⟨10⟩ SET craft_cost = 1")
+            .await
+            .unwrap();
+
+        let gw2 = Gw2Client::with_urls(server.uri(), "".to_string());
+        let stored = RecipeSync::new(db.clone())
+            .with_client(gw2)
+            .run_sync()
+            .await
+            .unwrap();
+        assert_eq!(stored, 2);
+
+        let items: Vec<serde_json::Value> = db
+            .query("SELECT gw2_id, craft_cost, craft_savings FROM item WHERE craft_cost != NONE")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["gw2_id"], 100);
+        assert_eq!(items[0]["craft_cost"], 45);
+        assert_eq!(items[0]["craft_savings"], 35);
+    }
+
+    #[tokio::test]
+    async fn test_recipe_sync_continues_past_failed_chunk() {
+        let db = setup_db().await;
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/v2/recipes"))
+            .and(query_param_is_missing("ids"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(vec![1, 2]))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/recipes"))
+            .and(query_param("ids", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("not json"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v2/recipes"))
+            .and(query_param("ids", "2"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                    "id": 2,
+                    "output_item_id": 100,
+                    "output_item_count": 1,
+                    "ingredients": [{ "type": "Item", "id": 11, "count": 3 }]
+                }])),
+            )
+            .mount(&server)
+            .await;
+
+        let gw2 = Gw2Client::builder()
+            .base_urls(server.uri(), "".to_string())
+            .chunk_size(1)
+            .build();
+        let stored = RecipeSync::new(db.clone())
+            .with_client(gw2)
+            .run_sync()
+            .await
+            .unwrap();
+        assert_eq!(stored, 1);
+    }
+}
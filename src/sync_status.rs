@@ -0,0 +1,190 @@
+use chrono::{DateTime, Utc};
+use surrealdb::Surreal;
+use surrealdb::engine::any::Any;
+
+pub const ITEM_SYNC: &str = "item_sync";
+pub const PRICE_SYNC: &str = "price_sync";
+pub const RECIPE_SYNC: &str = "recipe_sync";
+pub const HISTORY_RECOVERY: &str = "history_recovery";
+pub const HISTORY_PRUNING: &str = "history_pruning";
+pub const HISTORY_ROLLUP: &str = "history_rollup";
+
+/// The scraper's jobs, with how long after its last success each counts as
+/// stale: a few missed runs, not one slow one. Price sync runs every
+/// `price_sync_interval`, so it's stale after `STALE_AFTER_SYNCS` of them.
+/// History recovery only runs at startup, so it's only stale until it first
+/// succeeds.
+pub fn jobs(
+    price_sync_interval: std::time::Duration,
+) -> [(&'static str, Option<chrono::TimeDelta>); 6] {
+    let price_sync_stale_after =
+        chrono::TimeDelta::from_std(price_sync_interval * crate::STALE_AFTER_SYNCS)
+            .unwrap_or(chrono::TimeDelta::MAX);
+    [
+        (ITEM_SYNC, Some(chrono::TimeDelta::days(2))),
+        (PRICE_SYNC, Some(price_sync_stale_after)),
+        (HISTORY_RECOVERY, None),
+        (HISTORY_PRUNING, Some(chrono::TimeDelta::days(2))),
+        (HISTORY_ROLLUP, Some(chrono::TimeDelta::hours(3))),
+        (RECIPE_SYNC, Some(chrono::TimeDelta::days(2))),
+    ]
+}
+
+/// Outcome of the latest run of a sync job, one `sync_status` row per job.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct SyncStatus {
+    pub job: String,
+    /// When the job last finished without error; survives later failures
+    pub last_success: Option<DateTime<Utc>>,
+    /// Error of the latest run, cleared again by the next success
+    pub last_error: Option<String>,
+    /// Checksum of the id list the job last fully synced, if it tracks one
+    #[serde(default)]
+    pub id_checksum: Option<String>,
+}
+
+/// A job's latest outcome with how long ago it last succeeded.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct JobStatus {
+    pub job: String,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    /// Seconds since `last_success`; absent while the job never succeeded
+    pub seconds_since_success: Option<i64>,
+    /// The job never succeeded, or not within its expected interval
+    pub stale: bool,
+}
+
+impl JobStatus {
+    pub fn new(
+        job: &str,
+        status: Option<SyncStatus>,
+        stale_after: Option<chrono::TimeDelta>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let (last_success, last_error) =
+            status.map_or((None, None), |s| (s.last_success, s.last_error));
+        let since = last_success.map(|t| now - t);
+        Self {
+            job: job.to_string(),
+            last_success,
+            last_error,
+            seconds_since_success: since.map(|d| d.num_seconds()),
+            stale: match (since, stale_after) {
+                (_, None) => last_success.is_none(),
+                (None, Some(_)) => true,
+                (Some(since), Some(limit)) => since > limit,
+            },
+        }
+    }
+}
+
+/// Every job in `jobs` order, including those that never recorded a run.
+pub async fn job_statuses(
+    db: &Surreal<Any>,
+    price_sync_interval: std::time::Duration,
+) -> surrealdb::Result<Vec<JobStatus>> {
+    let mut statuses = fetch_all(db).await?;
+    let now = Utc::now();
+    Ok(jobs(price_sync_interval)
+        .into_iter()
+        .map(|(job, stale_after)| {
+            let status = statuses
+                .iter()
+                .position(|s| s.job == job)
+                .map(|i| statuses.swap_remove(i));
+            JobStatus::new(job, status, stale_after, now)
+        })
+        .collect())
+}
+
+/// Records how a run of `job` ended. `error` is `None` for a successful run.
+pub async fn record(db: &Surreal<Any>, job: &str, error: Option<String>) -> surrealdb::Result<()> {
+    let query = if error.is_none() {
+        "UPSERT type::thing('sync_status', $job) MERGE { job: $job, last_success: $now, last_error: NONE }"
+    } else {
+        "UPSERT type::thing('sync_status', $job) MERGE { job: $job, last_error: $error }"
+    };
+    db.query(query)
+        .bind(("job", job.to_string()))
+        .bind(("now", Utc::now()))
+        .bind(("error", error))
+        .await?
+        .check()?;
+    Ok(())
+}
+
+/// Records how a run of `job` ended and hands `result` back, logging rather
+/// than failing when the status can't be written. The boxed error isn't `Send`,
+/// so only its message is kept across the write; the returned future is `Send`
+/// whenever `T` is.
+pub fn record_outcome<'a, T>(
+    db: &'a Surreal<Any>,
+    job: &'static str,
+    result: Result<T, Box<dyn std::error::Error>>,
+) -> impl Future<Output = Result<T, Box<dyn std::error::Error>>> + 'a
+where
+    T: 'a,
+{
+    let result = result.map_err(|e| e.to_string());
+    async move {
+        if let Err(e) = record(db, job, result.as_ref().err().cloned()).await {
+            eprintln!("Failed to record {} status: {}", job, e);
+        }
+        result.map_err(Into::into)
+    }
+}
+
+/// Stores the checksum of the id list `job` just synced completely.
+pub async fn record_id_checksum(
+    db: &Surreal<Any>,
+    job: &str,
+    checksum: String,
+) -> surrealdb::Result<()> {
+    db.query("UPSERT type::thing('sync_status', $job) MERGE { job: $job, id_checksum: $checksum }")
+        .bind(("job", job.to_string()))
+        .bind(("checksum", checksum))
+        .await?
+        .check()?;
+    Ok(())
+}
+
+pub async fn fetch(db: &Surreal<Any>, job: &str) -> surrealdb::Result<Option<SyncStatus>> {
+    db.query("SELECT job, last_success, last_error, id_checksum FROM ONLY type::thing('sync_status', $job)")
+        .bind(("job", job.to_string()))
+        .await?
+        .take(0)
+}
+
+pub async fn fetch_all(db: &Surreal<Any>) -> surrealdb::Result<Vec<SyncStatus>> {
+    db.query("SELECT job, last_success, last_error, id_checksum FROM sync_status ORDER BY job")
+        .await?
+        .take(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use surrealdb::engine::any::connect;
+
+    #[tokio::test]
+    async fn test_record_keeps_last_success_across_failures() {
+        let db = connect("mem://").await.unwrap();
+        db.use_ns("test").use_db("test").await.unwrap();
+
+        record(&db, PRICE_SYNC, None).await.unwrap();
+        record(&db, PRICE_SYNC, Some("upstream down".to_string()))
+            .await
+            .unwrap();
+
+        let statuses = fetch_all(&db).await.unwrap();
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].job, PRICE_SYNC);
+        assert!(statuses[0].last_success.is_some());
+        assert_eq!(statuses[0].last_error.as_deref(), Some("upstream down"));
+
+        record(&db, PRICE_SYNC, None).await.unwrap();
+        let statuses = fetch_all(&db).await.unwrap();
+        assert_eq!(statuses[0].last_error, None);
+    }
+}